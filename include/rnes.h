@@ -0,0 +1,47 @@
+/* rnes C interface, written by hand to match src/ffi.rs.
+   Link against the rnes cdylib from cargo build.
+   Handles come from rnes_new and go back through rnes_free, paths are NUL
+   terminated UTF-8. Functions returning int give 0 on success and -1 on a
+   null or bad argument, a failed load or an internal panic, the reason is
+   printed on stdout. */
+#ifndef RNES_H
+#define RNES_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* controller buttons for rnes_set_input */
+#define RNES_BUTTON_A      0x01
+#define RNES_BUTTON_B      0x02
+#define RNES_BUTTON_SELECT 0x04
+#define RNES_BUTTON_START  0x08
+#define RNES_BUTTON_UP     0x10
+#define RNES_BUTTON_DOWN   0x20
+#define RNES_BUTTON_LEFT   0x40
+#define RNES_BUTTON_RIGHT  0x80
+
+typedef struct Rnes Rnes;
+
+/* NULL if the machine could not be built */
+Rnes *rnes_new(void);
+/* NULL is ignored */
+void rnes_free(Rnes *rnes);
+
+/* insert the cartridge and power on */
+int rnes_load_rom(Rnes *rnes, const char *rom_path);
+/* 1 when a frame ran, 0 when the machine is paused or halted */
+int rnes_run_frame(Rnes *rnes);
+/* buttons held on port 0 or 1 from the next frame */
+int rnes_set_input(Rnes *rnes, unsigned int port, uint8_t buttons);
+
+int rnes_save_state(const Rnes *rnes, const char *state_path);
+int rnes_load_state(Rnes *rnes, const char *state_path);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
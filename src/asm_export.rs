@@ -0,0 +1,266 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::path::Path;
+use crate::cdl::CDL_CODE;
+use crate::disasm::{decode, jump_target, operand_length, operand_text};
+
+const BANK_SIZE: usize = 16384;
+
+// Where each 16KB PRG bank sits in CPU space. Without mapper knowledge the
+// guess is: one bank mirrors at $C000, two banks fill $8000-$FFFF, and
+// bigger boards switch banks at $8000 with the last one fixed at $C000.
+fn bank_origin(bank: usize, banks: usize) -> u16 {
+    match banks {
+        1 => 0xC000,
+        2 => 0x8000 + (bank * BANK_SIZE) as u16,
+        _ if bank == banks - 1 => 0xC000,
+        _ => 0x8000,
+    }
+}
+
+enum Line {
+    Instruction([u8; 3]),
+    Data(Vec<u8>),
+    Vectors([u16; 3]),
+}
+
+struct Bank<'a> {
+    origin: u16,
+    bytes: &'a [u8],
+    // CDL flags for these bytes when a log was given
+    cdl: Option<&'a [u8]>,
+    has_vectors: bool,
+}
+
+impl Bank<'_> {
+    fn address(&self, offset: usize) -> u16 {
+        self.origin.wrapping_add(offset as u16)
+    }
+
+    fn contains(&self, address: u16) -> bool {
+        address >= self.origin && ((address - self.origin) as usize) < self.bytes.len()
+    }
+
+    fn vector_start(&self) -> usize {
+        if self.has_vectors { self.bytes.len() - 6 } else { self.bytes.len() }
+    }
+
+    // With a code/data log only bytes seen executing are code, otherwise try everything.
+    fn is_code(&self, offset: usize) -> bool {
+        match self.cdl {
+            Some(cdl) => cdl[offset] & CDL_CODE != 0,
+            None => true,
+        }
+    }
+
+    fn instruction_at(&self, offset: usize) -> Option<([u8; 3], usize)> {
+        let (_, mode) = decode(self.bytes[offset])?;
+        let length = 1 + operand_length(&mode) as usize;
+        if offset + length > self.vector_start() || !(offset..offset + length).all(|i| self.is_code(i)) {
+            return None;
+        }
+        let mut bytes = [0; 3];
+        bytes[..length].copy_from_slice(&self.bytes[offset..offset + length]);
+        Some((bytes, length))
+    }
+
+    fn lines(&self) -> Vec<(usize, Line)> {
+        let mut lines = Vec::new();
+        let mut offset = 0;
+        let end = self.vector_start();
+        while offset < end {
+            match self.instruction_at(offset) {
+                Some((bytes, length)) => {
+                    lines.push((offset, Line::Instruction(bytes)));
+                    offset += length;
+                }
+                None => {
+                    match lines.last_mut() {
+                        Some((_, Line::Data(data))) if data.len() < 8 => data.push(self.bytes[offset]),
+                        _ => lines.push((offset, Line::Data(vec![self.bytes[offset]]))),
+                    }
+                    offset += 1;
+                }
+            }
+        }
+        if self.has_vectors {
+            let word = |i: usize| u16::from_le_bytes([self.bytes[end + i], self.bytes[end + i + 1]]);
+            lines.push((end, Line::Vectors([word(0), word(2), word(4)])));
+        }
+        lines
+    }
+}
+
+fn label_name(address: u16, vectors: &BTreeMap<u16, &str>) -> String {
+    match vectors.get(&address) {
+        Some(name) => name.to_string(),
+        None => format!("L_{:04X}", address),
+    }
+}
+
+fn render(bank: &Bank, index: usize, total: usize, file_offset: usize) -> String {
+    let lines = bank.lines();
+
+    // labels: jump targets inside this bank plus the vector entry points
+    let mut vectors = BTreeMap::new();
+    for (_, line) in &lines {
+        if let Line::Vectors(words) = line {
+            for (word, name) in words.iter().zip(["nmi", "reset", "irq"]) {
+                if bank.contains(*word) {
+                    vectors.entry(*word).or_insert(name);
+                }
+            }
+        }
+    }
+    let mut targets: BTreeSet<u16> = vectors.keys().copied().collect();
+    for (offset, line) in &lines {
+        if let Line::Instruction(bytes) = line {
+            if let Some(target) = jump_target(*bytes, bank.address(*offset)) {
+                if bank.contains(target) {
+                    targets.insert(target);
+                }
+            }
+        }
+    }
+    let starts: BTreeSet<u16> = lines.iter().map(|(offset, _)| bank.address(*offset)).collect();
+    let name = |address: u16| if targets.contains(&address) { Some(label_name(address, &vectors)) } else { None };
+
+    let mut out = String::new();
+    out.push_str(&format!("; bank {} of {}, file offset ${:05X}, mapped at ${:04X}\n", index, total, file_offset, bank.origin));
+    out.push_str(if bank.cdl.is_some() { "; code/data split from a code/data log\n" } else { "; no code/data log, everything decodable is shown as code\n" });
+    // targets in the middle of an instruction or data run cannot be line labels
+    for target in targets.iter().filter(|t| !starts.contains(t)) {
+        out.push_str(&format!("{} = ${:04X}\n", label_name(*target, &vectors), target));
+    }
+    out.push_str(&format!("\n.org ${:04X}\n", bank.origin));
+    for (offset, line) in &lines {
+        let address = bank.address(*offset);
+        if targets.contains(&address) {
+            out.push_str(&format!("\n{}:\n", label_name(address, &vectors)));
+        }
+        match line {
+            Line::Instruction(bytes) => {
+                let (mnemonic, mode) = decode(bytes[0]).unwrap();
+                out.push_str(&format!("    {}{}\n", mnemonic, operand_text(&mode, *bytes, address, &name)));
+            }
+            Line::Data(data) => {
+                let values: Vec<String> = data.iter().map(|b| format!("${:02X}", b)).collect();
+                out.push_str(&format!("    .byte {}\n", values.join(",")));
+            }
+            Line::Vectors(words) => {
+                out.push_str("\n; vectors\n");
+                for (word, name) in words.iter().zip(["nmi", "reset", "irq"]) {
+                    let target = if targets.contains(word) { label_name(*word, &vectors) } else { format!("${:04X}", word) };
+                    out.push_str(&format!("    .word {} ; {}\n", target, name));
+                }
+            }
+        }
+    }
+    out
+}
+
+// Write one .asm file per 16KB PRG bank into out_dir, returns the files written.
+pub fn export(rom_path: &str, out_dir: &str, cdl_path: Option<&str>) -> io::Result<Vec<String>> {
+    let rom = fs::read(rom_path)?;
+    if rom.len() < 16 || &rom[0..4] != b"NES\x1A" {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not an iNES rom"));
+    }
+    let trainer = if rom[6] & 0x04 != 0 { 512 } else { 0 };
+    let prg_start = 16 + trainer;
+    let banks = rom[4] as usize;
+    let prg_end = prg_start + banks * BANK_SIZE;
+    if prg_end > rom.len() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "rom is shorter than its header says"));
+    }
+    let prg = &rom[prg_start..prg_end];
+    let cdl = match cdl_path {
+        Some(path) => {
+            let cdl = fs::read(path)?;
+            if cdl.len() < prg.len() {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "code/data log is smaller than PRG-ROM"));
+            }
+            Some(cdl)
+        }
+        None => None,
+    };
+
+    fs::create_dir_all(out_dir)?;
+    let mut written = Vec::new();
+    for index in 0..banks {
+        let range = index * BANK_SIZE..(index + 1) * BANK_SIZE;
+        let bank = Bank {
+            origin: bank_origin(index, banks),
+            bytes: &prg[range.clone()],
+            cdl: cdl.as_ref().map(|c| &c[range]),
+            has_vectors: bank_origin(index, banks) == 0xC000 && (index == banks - 1),
+        };
+        let path = Path::new(out_dir).join(format!("bank_{:02}.asm", index));
+        fs::write(&path, render(&bank, index, banks, prg_start + index * BANK_SIZE))?;
+        written.push(path.display().to_string());
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // one 16KB bank at $C000: LDX #$05, loop: DEX, BNE loop, JMP $C000, then data and vectors
+    fn rom() -> Vec<u8> {
+        let mut rom = b"NES\x1A\x01\x00".to_vec();
+        rom.resize(16, 0);
+        let mut prg = vec![0xEA; BANK_SIZE];
+        prg[..8].copy_from_slice(&[0xA2, 0x05, 0xCA, 0xD0, 0xFD, 0x4C, 0x00, 0xC0]);
+        prg[8..12].copy_from_slice(&[0x01, 0x02, 0x03, 0x04]);
+        // nmi $C002, reset $C000, irq outside the bank
+        prg[BANK_SIZE - 6..].copy_from_slice(&[0x02, 0xC0, 0x00, 0xC0, 0x00, 0x80]);
+        rom.extend(prg);
+        rom
+    }
+
+    fn exported(name: &str, cdl: Option<&[u8]>) -> String {
+        let dir = std::env::temp_dir().join(format!("rnes-asm-{}-{}", name, std::process::id()));
+        let rom_path = dir.with_extension("nes");
+        let cdl_path = dir.with_extension("cdl");
+        fs::write(&rom_path, rom()).unwrap();
+        if let Some(cdl) = cdl {
+            fs::write(&cdl_path, cdl).unwrap();
+        }
+        let cdl_arg = cdl.map(|_| cdl_path.to_str().unwrap());
+        let written = export(rom_path.to_str().unwrap(), dir.to_str().unwrap(), cdl_arg).unwrap();
+        assert_eq!(written.len(), 1);
+        let text = fs::read_to_string(&written[0]).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        fs::remove_file(&rom_path).unwrap();
+        let _ = fs::remove_file(&cdl_path);
+        text
+    }
+
+    #[test]
+    fn a_code_data_log_splits_code_from_byte_runs() {
+        let mut cdl = vec![0; BANK_SIZE];
+        cdl[..8].fill(CDL_CODE);
+        let text = exported("cdl", Some(&cdl));
+        assert!(text.starts_with("; bank 0 of 1, file offset $00010, mapped at $C000\n; code/data split from a code/data log\n"));
+        assert!(text.contains("\n.org $C000\n\nreset:\n    LDX #$05\n\nnmi:\n    DEX\n    BNE nmi\n    JMP reset\n"));
+        // data goes out eight bytes to a line
+        assert!(text.contains("    JMP reset\n    .byte $01,$02,$03,$04,$EA,$EA,$EA,$EA\n"));
+        assert!(text.ends_with("; vectors\n    .word nmi ; nmi\n    .word reset ; reset\n    .word $8000 ; irq\n"));
+    }
+
+    #[test]
+    fn without_a_log_everything_decodable_is_code() {
+        let text = exported("plain", None);
+        assert!(text.contains("; no code/data log, everything decodable is shown as code\n"));
+        assert!(!text.contains("    .byte $01,$02,$03,$04,$EA"));
+        assert!(text.contains("    NOP\n"));
+    }
+
+    #[test]
+    fn banks_map_by_count() {
+        assert_eq!(bank_origin(0, 1), 0xC000);
+        assert_eq!((bank_origin(0, 2), bank_origin(1, 2)), (0x8000, 0xC000));
+        assert_eq!((bank_origin(2, 8), bank_origin(7, 8)), (0x8000, 0xC000));
+    }
+}
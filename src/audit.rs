@@ -0,0 +1,175 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use crate::batch::panic_message;
+use crate::savestate::{self, tag_name};
+use crate::Emulator;
+
+// Where a difference between the two instances showed up first.
+pub struct Divergence {
+    pub frame: u64,
+    pub component: &'static str,
+    pub detail: String,
+}
+
+pub enum Outcome {
+    // both ran every frame with identical state throughout
+    Matched(u64),
+    // both stopped on the same frame with identical state, paused or halted
+    Stopped(u64),
+    Diverged(Divergence),
+    Failed(String),
+}
+
+// What an instance sends after each frame: whether it is still running, its
+// cycle count and save state encoding.
+type FrameState = Result<(bool, u64, Vec<u8>), String>;
+
+// Which part of the machine a byte of a save state section belongs to.
+fn component(tag: &[u8; 4], offset: usize) -> &'static str {
+    match tag {
+        b"CPU\0" => "CPU",
+        b"PPU\0" | b"VRAM" => "PPU",
+        b"RAM\0" => match offset {
+            0x0000..=0x1FFF => "CPU RAM",
+            0x6000..=0x7FFF => "cartridge WRAM",
+            0x8000..=0xFFFF => "cartridge PRG",
+            _ => "IO registers",
+        },
+        _ => "unknown",
+    }
+}
+
+fn compare(frame: u64, a: &(bool, u64, Vec<u8>), b: &(bool, u64, Vec<u8>)) -> Option<Divergence> {
+    if a.1 != b.1 {
+        return Some(Divergence {
+            frame,
+            component: "CPU",
+            detail: format!("cycle count {} vs {}", a.1, b.1),
+        });
+    }
+    // both come straight from encode, the layout is the same
+    let left_sections = savestate::sections(&a.2[savestate::HEADER_LEN..]).unwrap_or_default();
+    let right_sections = savestate::sections(&b.2[savestate::HEADER_LEN..]).unwrap_or_default();
+    for ((tag, left), (_, right)) in left_sections.into_iter().zip(right_sections) {
+        if let Some(offset) = left.iter().zip(right).position(|(x, y)| x != y) {
+            return Some(Divergence {
+                frame,
+                component: component(&tag, offset),
+                detail: format!("{} section byte ${:04X}: {:02X} vs {:02X}", tag_name(&tag), offset, left[offset], right[offset]),
+            });
+        }
+    }
+    if a.0 != b.0 {
+        return Some(Divergence {
+            frame,
+            component: "run state",
+            detail: "one instance stopped, the other kept running".to_string(),
+        });
+    }
+    None
+}
+
+fn spawn<'scope>(scope: &'scope thread::Scope<'scope, '_>, setup: &'scope (dyn Fn() -> Result<Emulator, String> + Sync), frames: u64) -> Receiver<FrameState> {
+    // one frame of slack keeps the two instances close to lockstep
+    let (sender, receiver) = mpsc::sync_channel(1);
+    scope.spawn(move || {
+        let mut emulator = match setup() {
+            Ok(emulator) => emulator,
+            Err(e) => {
+                let _ = sender.send(Err(e));
+                return;
+            }
+        };
+        emulator.verbose = false;
+        for frame in 1..=frames {
+            let running = match panic::catch_unwind(AssertUnwindSafe(|| emulator.run_frame())) {
+                Ok(running) => running,
+                Err(payload) => {
+                    let _ = sender.send(Err(format!("frame {}: panicked: {}", frame, panic_message(payload.as_ref()))));
+                    return;
+                }
+            };
+            // the audit stops listening at the first divergence
+            if sender.send(Ok((running, emulator.total_cycles, savestate::encode(&emulator)))).is_err() || !running {
+                return;
+            }
+        }
+    });
+    receiver
+}
+
+// Build two machines with setup, run them side by side on their own threads
+// and compare their whole state at the end of every frame.
+pub fn run(setup: &(dyn Fn() -> Result<Emulator, String> + Sync), frames: u64) -> Outcome {
+    thread::scope(|scope| {
+        let left = spawn(scope, setup, frames);
+        let right = spawn(scope, setup, frames);
+        for frame in 1..=frames {
+            let (a, b) = match (left.recv(), right.recv()) {
+                (Ok(Ok(a)), Ok(Ok(b))) => (a, b),
+                (Ok(Err(e)), _) | (_, Ok(Err(e))) => return Outcome::Failed(e),
+                _ => return Outcome::Failed(format!("an instance panicked during frame {}", frame)),
+            };
+            if let Some(divergence) = compare(frame, &a, &b) {
+                return Outcome::Diverged(divergence);
+            }
+            if !a.0 {
+                return Outcome::Stopped(frame);
+            }
+        }
+        Outcome::Matched(frames)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame_state(emulator: &Emulator) -> (bool, u64, Vec<u8>) {
+        (true, emulator.total_cycles, savestate::encode(emulator))
+    }
+
+    #[test]
+    fn points_at_the_first_byte_that_differs() {
+        let left = Emulator::new();
+        let mut right = Emulator::new();
+        assert!(compare(1, &frame_state(&left), &frame_state(&right)).is_none());
+
+        right.memory[0x0123] = 0x42;
+        right.memory[0x6000] = 0x01;
+        let divergence = compare(4, &frame_state(&left), &frame_state(&right)).unwrap();
+        assert_eq!(divergence.frame, 4);
+        assert_eq!(divergence.component, "CPU RAM");
+        assert_eq!(divergence.detail, "RAM section byte $0123: 00 vs 42");
+
+        right.memory[0x0123] = 0;
+        let divergence = compare(4, &frame_state(&left), &frame_state(&right)).unwrap();
+        assert_eq!(divergence.component, "cartridge WRAM");
+        assert_eq!(divergence.detail, "RAM section byte $6000: 00 vs 01");
+    }
+
+    #[test]
+    fn cycle_counts_and_run_state_count_as_divergences() {
+        let left = Emulator::new();
+        let mut right = Emulator::new();
+        right.total_cycles = 7;
+        let divergence = compare(2, &frame_state(&left), &frame_state(&right)).unwrap();
+        assert_eq!((divergence.component, divergence.detail.as_str()), ("CPU", "cycle count 0 vs 7"));
+
+        let stopped = (false, 0, savestate::encode(&left));
+        let divergence = compare(2, &frame_state(&left), &stopped).unwrap();
+        assert_eq!(divergence.component, "run state");
+    }
+
+    #[test]
+    fn matching_instances_run_to_the_end() {
+        let setup = || {
+            let mut emulator = Emulator::new();
+            emulator.memory[0x8010..0x8014].copy_from_slice(&[0xA2, 0x01, 0xD0, 0xFE]);
+            emulator.registers.program_counter = 0x8010;
+            Ok(emulator)
+        };
+        assert!(matches!(run(&setup, 3), Outcome::Matched(3)));
+    }
+}
@@ -0,0 +1,164 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use crate::input::parse_buttons;
+use crate::session::state_hash;
+use crate::Emulator;
+
+/* Automation Protocol, one line per frame in each direction
+    out: frame N lag L hash H ram HEX
+                                written at the end of every frame, L is 1 for a lag frame and
+                                HEX is the 2KB of internal RAM
+    in:  P1 [P2]                read after each frame, buttons held for the frame after it,
+                                either letters as the debugger shows them (UDLRsSBA, '.' is
+                                ignored) or a number like 9 or $09, a blank line holds nothing
+   The first frame runs with nothing held and the run pauses when the input reaches its end.
+*/
+pub struct Automation {
+    input: Option<Box<dyn BufRead>>,
+    output: Option<BufWriter<Box<dyn Write>>>,
+}
+
+// "-" is stdin or stdout, anything else a file or FIFO
+fn open_input(path: &str) -> io::Result<Box<dyn BufRead>> {
+    if path == "-" {
+        return Ok(Box::new(BufReader::new(io::stdin())));
+    }
+    Ok(Box::new(BufReader::new(File::open(path)?)))
+}
+
+fn open_output(path: &str) -> io::Result<BufWriter<Box<dyn Write>>> {
+    let out: Box<dyn Write> = if path == "-" { Box::new(io::stdout()) } else { Box::new(File::create(path)?) };
+    Ok(BufWriter::new(out))
+}
+
+fn parse_line(line: &str) -> Option<[u8; 2]> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    if words.len() > 2 {
+        return None;
+    }
+    let mut buttons = [0; 2];
+    for (held, word) in buttons.iter_mut().zip(words) {
+        *held = parse_buttons(word)?;
+    }
+    Some(buttons)
+}
+
+impl Automation {
+    pub fn open(input: Option<&str>, output: Option<&str>) -> io::Result<Self> {
+        Ok(Automation {
+            input: input.map(open_input).transpose()?,
+            output: output.map(open_output).transpose()?,
+        })
+    }
+}
+
+// Hold the buttons from the next input line. Messages go to stderr since
+// stdout may be carrying frame lines.
+fn next_input(emulator: &mut Emulator) {
+    let Some(input) = emulator.automation.as_mut().and_then(|a| a.input.as_mut()) else {
+        return;
+    };
+    let mut line = String::new();
+    let buttons = match input.read_line(&mut line) {
+        Ok(0) => None,
+        Ok(_) => match parse_line(&line) {
+            Some(buttons) => Some(buttons),
+            None => {
+                eprintln!("automation: cannot parse input {:?}", line.trim_end());
+                None
+            }
+        },
+        Err(e) => {
+            eprintln!("automation: failed to read input: {}", e);
+            None
+        }
+    };
+    match buttons {
+        Some(buttons) => {
+            for (controller, buttons) in emulator.controllers.iter_mut().zip(buttons) {
+                controller.release(0xFF);
+                controller.press(buttons);
+            }
+        }
+        None => {
+            if let Some(automation) = emulator.automation.as_mut() {
+                automation.input = None;
+            }
+            emulator.pause();
+        }
+    }
+}
+
+// Called once per frame, reports the frame that just ended and reads the next one's input.
+pub fn end_frame(emulator: &mut Emulator) {
+    if emulator.automation.as_ref().is_some_and(|a| a.output.is_some()) {
+        let hash = state_hash(emulator);
+        let ram: String = emulator.memory[..0x800].iter().map(|b| format!("{:02x}", b)).collect();
+        let line = format!("frame {} lag {} hash {:016x} ram {}", emulator.ppu.frame, emulator.lag.last_frame_lagged as u8, hash, ram);
+        let written = emulator.automation.as_mut().and_then(|a| a.output.as_mut()).map(|out| {
+            writeln!(out, "{}", line)?;
+            // the script on the other end is waiting on this line before it sends input
+            out.flush()
+        });
+        if let Some(Err(e)) = written {
+            eprintln!("automation: failed to write frame: {}", e);
+            if let Some(automation) = emulator.automation.as_mut() {
+                automation.output = None;
+            }
+        }
+    }
+    next_input(emulator);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::{BUTTON_A, BUTTON_START, BUTTON_UP};
+    use crate::RunState;
+    use std::fs;
+
+    #[test]
+    fn parses_letters_numbers_and_blank_lines() {
+        assert_eq!(parse_line("UA\n"), Some([BUTTON_UP | BUTTON_A, 0]));
+        assert_eq!(parse_line("..S. $09"), Some([BUTTON_START, 0x09]));
+        assert_eq!(parse_line("9 1"), Some([0x09, 0x01]));
+        assert_eq!(parse_line(""), Some([0, 0]));
+        assert_eq!(parse_line("A B A"), None);
+        assert_eq!(parse_line("X"), None);
+        assert_eq!(parse_line("$1FF"), None);
+    }
+
+    #[test]
+    fn writes_a_frame_line_and_holds_the_next_input() {
+        let dir = std::env::temp_dir();
+        let input = dir.join(format!("rnes_automation_{}.in", std::process::id()));
+        let output = dir.join(format!("rnes_automation_{}.out", std::process::id()));
+        fs::write(&input, "A S\nnonsense\n").unwrap();
+        let mut emulator = Emulator::new();
+        emulator.memory[0] = 0xAB;
+        emulator.memory[0x7FF] = 0xCD;
+        emulator.ppu.frame = 7;
+        emulator.lag.last_frame_lagged = true;
+        emulator.automation = Some(Automation::open(input.to_str(), output.to_str()).unwrap());
+
+        end_frame(&mut emulator);
+        assert_eq!(emulator.controllers[0].buttons(), BUTTON_A);
+        assert_eq!(emulator.controllers[1].buttons(), BUTTON_START);
+        let written = fs::read_to_string(&output).unwrap();
+        let words: Vec<&str> = written.split_whitespace().collect();
+        assert_eq!(words[..4], ["frame", "7", "lag", "1"]);
+        assert_eq!(words[5], format!("{:016x}", state_hash(&emulator)));
+        // the RAM dump is the 2KB of internal RAM as hex, nothing more
+        assert_eq!(words[7].len(), 0x800 * 2);
+        assert!(words[7].starts_with("ab00"));
+        assert!(words[7].ends_with("00cd"));
+
+        // a line that does not parse ends the input and pauses the run
+        end_frame(&mut emulator);
+        assert_eq!(emulator.run_state, RunState::Paused);
+        assert!(emulator.automation.as_ref().unwrap().input.is_none());
+        assert_eq!(fs::read_to_string(&output).unwrap().lines().count(), 2);
+        fs::remove_file(input).unwrap();
+        fs::remove_file(output).unwrap();
+    }
+}
@@ -0,0 +1,207 @@
+use std::fs;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread;
+use crate::info;
+use crate::session::state_hash;
+use crate::snapshot::json_string;
+use crate::{Emulator, HaltReason, RunState};
+
+pub struct RomResult {
+    pub rom: String,
+    // ran every frame without panicking, jamming or failing to load
+    pub booted: bool,
+    pub frames: u64,
+    pub hash: Option<u64>,
+    pub detail: String,
+}
+
+pub fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        return message.to_string();
+    }
+    if let Some(message) = payload.downcast_ref::<String>() {
+        return message.clone();
+    }
+    "panic".to_string()
+}
+
+fn run_rom(path: &Path, frames: u64) -> RomResult {
+    let rom = path.display().to_string();
+    let failed = |detail: String| RomResult {
+        rom: rom.clone(),
+        booted: false,
+        frames: 0,
+        hash: None,
+        detail,
+    };
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) => return failed(format!("load failed: {}", e)),
+    };
+    if info::parse(&bytes).is_none() {
+        return failed("not an iNES rom".to_string());
+    }
+    let mut emulator = Emulator::new();
+    emulator.verbose = false;
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        emulator.load_rom_bytes(&bytes);
+        emulator.run_frames(frames)
+    }));
+    match result {
+        Ok(ran) => RomResult {
+            rom: rom.clone(),
+            booted: ran == frames,
+            frames: ran,
+            hash: Some(state_hash(&emulator)),
+            detail: match emulator.run_state {
+                RunState::Halted(HaltReason::Jam { opcode, address }) => format!("jammed on ${:02X} at ${:04X}", opcode, address),
+                RunState::Halted(HaltReason::UnknownOpcode { opcode, address }) => {
+                    format!("unknown opcode ${:02X} at ${:04X}", opcode, address)
+                }
+                _ => String::new(),
+            },
+        },
+        // the machine is left wherever the panic hit, the last instruction fetched is the useful part
+        Err(payload) => {
+            let address = emulator.history.back().map(|(pc, _)| *pc).unwrap_or(emulator.registers.program_counter);
+            RomResult {
+                frames: emulator.ppu.frame,
+                ..failed(format!("panicked on ${:02X} at ${:04X}: {}", emulator.opcode, address, panic_message(payload.as_ref())))
+            }
+        }
+    }
+}
+
+fn nes_files(dir: &str) -> io::Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|p| p.extension().is_some_and(|e| e.eq_ignore_ascii_case("nes")))
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+// Run every .nes file in dir for the given number of frames, one worker per core.
+pub fn run(dir: &str, frames: u64, threads: usize) -> io::Result<Vec<RomResult>> {
+    let queue = Mutex::new(nes_files(dir)?.into_iter().enumerate().collect::<Vec<_>>());
+    let results = Mutex::new(Vec::new());
+    // panics are recorded per ROM, the default hook would print each one over the report
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    thread::scope(|scope| {
+        for _ in 0..threads.max(1) {
+            scope.spawn(|| loop {
+                let next = queue.lock().unwrap().pop();
+                let Some((index, path)) = next else {
+                    break;
+                };
+                let result = run_rom(&path, frames);
+                results.lock().unwrap().push((index, result));
+            });
+        }
+    });
+    panic::set_hook(hook);
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(index, _)| *index);
+    Ok(results.into_iter().map(|(_, result)| result).collect())
+}
+
+fn csv_field(text: &str) -> String {
+    if text.contains(',') || text.contains('"') || text.contains('\n') {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+pub fn to_csv(results: &[RomResult]) -> String {
+    let mut out = String::from("rom,booted,frames,hash,detail\n");
+    for r in results {
+        out.push_str(&format!(
+            "{},{},{},{},{}\n",
+            csv_field(&r.rom),
+            r.booted,
+            r.frames,
+            r.hash.map(|h| format!("{:016x}", h)).unwrap_or_default(),
+            csv_field(&r.detail)
+        ));
+    }
+    out
+}
+
+pub fn to_json(results: &[RomResult]) -> String {
+    let entries: Vec<String> = results
+        .iter()
+        .map(|r| {
+            format!(
+                "{{\"rom\":{},\"booted\":{},\"frames\":{},\"hash\":{},\"detail\":{}}}",
+                json_string(&r.rom),
+                r.booted,
+                r.frames,
+                r.hash.map(|h| format!("\"{:016x}\"", h)).unwrap_or("null".to_string()),
+                json_string(&r.detail)
+            )
+        })
+        .collect();
+    format!("[{}]", entries.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_fields_quote_commas_quotes_and_newlines() {
+        assert_eq!(csv_field("roms/smb.nes"), "roms/smb.nes");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+        assert_eq!(csv_field(""), "");
+    }
+
+    #[test]
+    fn reports_a_good_and_a_garbage_rom() {
+        let dir = std::env::temp_dir().join(format!("rnes_batch_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        // one 16KB bank spinning on LDX #$01 / BNE
+        let mut good = b"NES\x1A\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+        good.extend([0xA2, 0x01, 0xD0, 0xFE]);
+        good.resize(16 + 0x4000, 0);
+        fs::write(dir.join("good.nes"), good).unwrap();
+        fs::write(dir.join("bad, \"rom\".nes"), b"not a rom").unwrap();
+        fs::write(dir.join("notes.txt"), b"skipped").unwrap();
+
+        let results = run(dir.to_str().unwrap(), 2, 2).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(results.len(), 2);
+        let (bad, good) = (&results[0], &results[1]);
+        assert!(bad.rom.ends_with("bad, \"rom\".nes"));
+        assert!(!bad.booted);
+        assert_eq!((bad.frames, bad.hash, bad.detail.as_str()), (0, None, "not an iNES rom"));
+        assert!(good.booted);
+        assert_eq!(good.frames, 2);
+        let hash = good.hash.unwrap();
+
+        let csv = to_csv(&results);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "rom,booted,frames,hash,detail");
+        assert_eq!(lines[1], format!("\"{}\",false,0,,not an iNES rom", bad.rom.replace('"', "\"\"")));
+        assert_eq!(lines[2], format!("{},true,2,{:016x},", good.rom, hash));
+
+        let json = to_json(&results);
+        assert_eq!(
+            json,
+            format!(
+                "[{{\"rom\":{},\"booted\":false,\"frames\":0,\"hash\":null,\"detail\":\"not an iNES rom\"}},\
+{{\"rom\":{},\"booted\":true,\"frames\":2,\"hash\":\"{:016x}\",\"detail\":\"\"}}]",
+                json_string(&bad.rom),
+                json_string(&good.rom),
+                hash
+            )
+        );
+        assert!(json.contains("bad, \\\"rom\\\".nes"));
+    }
+}
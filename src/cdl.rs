@@ -0,0 +1,139 @@
+use std::fs;
+
+/* FCEUX Code/Data Log
+    one byte per PRG-ROM byte followed by one byte per CHR-ROM byte
+    PRG: bit 0 code, bit 1 data, bits 2-3 which 8KB window of 0x8000 it was seen through
+    CHR: bit 0 rendered, bit 1 read through $2007
+*/
+pub const CDL_CODE: u8 = 0x01;
+pub const CDL_DATA: u8 = 0x02;
+pub const CDL_CHR_READ: u8 = 0x02;
+
+// PRG-ROM starts right after the 16 byte header in memory
+const PRG_START: u16 = 0x8000 + 0x10;
+
+pub struct CodeDataLog {
+    pub prg: Vec<u8>,
+    // the PPU does not fetch patterns to draw yet, so only $2007 reads mark CHR
+    pub chr: Vec<u8>,
+    // bytes of the instruction being executed, operand reads inside it are code not data
+    instruction_start: u16,
+    instruction_length: u16,
+}
+
+impl CodeDataLog {
+    pub fn new(prg_size: usize, chr_size: usize) -> Self {
+        CodeDataLog {
+            prg: vec![0; prg_size],
+            chr: vec![0; chr_size],
+            instruction_start: 0,
+            instruction_length: 0,
+        }
+    }
+
+    // Start from an existing log so several runs accumulate coverage.
+    pub fn load(path: &str, prg_size: usize, chr_size: usize) -> Self {
+        let mut log = CodeDataLog::new(prg_size, chr_size);
+        if let Ok(bytes) = fs::read(path) {
+            if bytes.len() == prg_size + chr_size {
+                log.prg.copy_from_slice(&bytes[..prg_size]);
+                log.chr.copy_from_slice(&bytes[prg_size..]);
+            } else {
+                println!("Ignoring {}: size does not match this ROM", path);
+            }
+        }
+        log
+    }
+
+    fn mark(&mut self, address: u16, flag: u8) {
+        if address < PRG_START {
+            return;
+        }
+        let offset = (address - PRG_START) as usize;
+        if offset < self.prg.len() {
+            let window = (((address - 0x8000) >> 13) & 0x3) as u8;
+            self.prg[offset] |= flag | (window << 2);
+        }
+    }
+
+    // Mark the opcode and operand bytes of the instruction about to execute.
+    pub fn begin_instruction(&mut self, address: u16, length: u16) {
+        self.instruction_start = address;
+        self.instruction_length = length;
+        for i in 0..length {
+            self.mark(address.wrapping_add(i), CDL_CODE);
+        }
+    }
+
+    pub fn mark_data(&mut self, address: u16) {
+        if address.wrapping_sub(self.instruction_start) < self.instruction_length {
+            return;
+        }
+        self.mark(address, CDL_DATA);
+    }
+
+    // A $2007 read from the pattern tables.
+    pub fn mark_chr_read(&mut self, address: u16) {
+        if let Some(byte) = self.chr.get_mut(address as usize) {
+            *byte |= CDL_CHR_READ;
+        }
+    }
+
+    pub fn save(&self, path: &str) -> std::io::Result<()> {
+        let mut out = self.prg.clone();
+        out.extend_from_slice(&self.chr);
+        fs::write(path, out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Emulator;
+
+    #[test]
+    fn code_and_data_are_marked_with_their_window() {
+        let mut log = CodeDataLog::new(0x8000, 0x2000);
+        // LDA $C000,X at $8010: three code bytes, its operands are not data
+        log.begin_instruction(PRG_START, 3);
+        log.mark_data(PRG_START + 1);
+        log.mark_data(0xC000);
+        log.mark_data(0x0200);
+        assert_eq!(log.prg[..4], [CDL_CODE, CDL_CODE, CDL_CODE, 0]);
+        // $C000 is in the third 8KB window
+        assert_eq!(log.prg[(0xC000 - PRG_START) as usize], CDL_DATA | (2 << 2));
+        assert!(log.chr.iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn ppudata_reads_from_the_pattern_tables_mark_chr() {
+        let mut emulator = Emulator::new();
+        emulator.verbose = false;
+        emulator.cdl = Some(CodeDataLog::new(0x4000, 0x2000));
+        emulator.ppu.v = 0x0123;
+        emulator.read_byte(0x2007);
+        // nametable reads are not CHR
+        emulator.ppu.v = 0x2123;
+        emulator.read_byte(0x2007);
+        let log = emulator.cdl.as_ref().unwrap();
+        assert_eq!(log.chr[0x0123], CDL_CHR_READ);
+        assert_eq!(log.chr.iter().filter(|&&b| b != 0).count(), 1);
+    }
+
+    #[test]
+    fn files_are_prg_then_chr_and_accumulate() {
+        let path = std::env::temp_dir().join(format!("rnes-cdl-{}.cdl", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut log = CodeDataLog::new(16, 8);
+        log.prg[2] = CDL_CODE;
+        log.chr[7] = CDL_CHR_READ;
+        log.save(path).unwrap();
+        let bytes = fs::read(path).unwrap();
+        assert_eq!((bytes.len(), bytes[2], bytes[16 + 7]), (24, CDL_CODE, CDL_CHR_READ));
+        let loaded = CodeDataLog::load(path, 16, 8);
+        assert_eq!((loaded.prg, loaded.chr), (log.prg, log.chr));
+        // a log for a different ROM size starts empty
+        assert!(CodeDataLog::load(path, 32, 8).prg.iter().all(|&b| b == 0));
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,581 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, BufRead, Write};
+use crate::guard::Rule;
+use crate::input::{button_text, parse_buttons, Macro, Turbo};
+use crate::netplay;
+use crate::palette;
+use crate::ppu::Ppu;
+use crate::practice::{load_slot, save_slot};
+use crate::state_diff::{self, PendingDiff};
+use crate::watch::Watch;
+use crate::{Emulator, HaltReason};
+
+pub struct Debugger {
+    // stop at the prompt before every instruction
+    pub stepping: bool,
+    // addresses that drop back into the prompt when reached
+    pub breakpoints: HashSet<u16>,
+    // addresses held at a fixed value after every instruction
+    pub frozen: HashMap<u16, u8>,
+    // state captured by diff, printed once its run is over
+    pub diff: Option<PendingDiff>,
+    // full screen terminal view, redrawn while running and at every stop
+    #[cfg(feature = "tui")]
+    pub tui: Option<crate::tui::Live>,
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Debugger {
+            stepping: true,
+            breakpoints: HashSet::new(),
+            frozen: HashMap::new(),
+            diff: None,
+            #[cfg(feature = "tui")]
+            tui: None,
+        }
+    }
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+const HELP: &str = "commands:
+  s                 step one instruction (also empty line)
+  c                 continue running
+  b [addr]          toggle a breakpoint, b alone lists them
+  r                 print registers, controller input and the lag counter
+  diff <n> [f]      run n instructions, or n frames with f, then show what changed
+  m <addr> [len]    hex view of len bytes (default 0x80) and the RAM map names in it
+  k                 hex view of the stack page $0100-$01FF
+  int [frames]      interrupts raised and taken over the last frames (default 1)
+  int json <file>   write the whole interrupt timeline as JSON
+  pal               palette RAM as RGB after PPUMASK grayscale/emphasis
+  oam               the 64 sprites in OAM, * marks those evaluated for the next line
+  w <addr> <byte>.. write bytes starting at addr
+  vm <addr> [len]   hex view of PPU space: pattern tables, nametables, palette
+  vw <addr> <byte>.. write bytes into PPU space starting at addr
+  f <addr> [byte]   freeze addr at byte (default its current value)
+  u <addr>          unfreeze addr
+  watch <name> = <addr>[:u8|s8|u16]
+                    show a named value, watch alone lists them
+  watch <name>      watch a name from the RAM map
+  unwatch <name>    remove a watch
+  map               list the RAM map with current values
+  log               toggle printing watch changes every frame
+  triggers          list triggers and whether they have fired
+  guard [ro|nx <addr>[-<addr>] [from frame|$addr]]
+                    add a write or execute guard, guard alone lists them
+  unguard <n>       remove guard n from the list
+  ss <name>         save the machine into a named practice slot
+  ls [name]         load a practice slot, the last one used by default
+  slots             list practice slots
+  rng <addr> [len]  scramble these RAM bytes on every slot load, rng off clears
+  turbo <buttons> <on> <off>
+                    autofire controller 1 buttons (UDLRsSBA), on and off frames,
+                    turbo <buttons> off stops it
+  rec <slot>        record controller 1 into a macro slot (0-255)
+  stop              stop recording the macro
+  play <slot>       play a macro slot on controller 1
+  macro <slot> <buttons>:<frames>..
+                    write a macro by hand, macro 1 A:2 .:2 A:2 taps A twice
+  mic               toggle the Famicom microphone on controller 2
+  dev [args..]      expansion port device status, or a command for it
+  coin [1|2]        drop a coin into a VS. System slot
+  service           toggle the VS. System service button
+  dip <hex>         set the VS. System DIP switches, switch 1 is bit 0
+  reset             press the reset button
+  power             power cycle, RAM is refilled with the power-on pattern
+  eject             remove the cartridge
+  load <path>       insert another cartridge and power on
+  q                 quit
+addresses in m, w, f and u can also be RAM map names such as player_x or score+2";
+
+// Accepts $1234, 0x1234 or plain hex.
+pub fn parse_hex(text: &str) -> Option<u16> {
+    let digits = text.trim_start_matches('$').trim_start_matches("0x");
+    u16::from_str_radix(digits, 16).ok()
+}
+
+// Hex view with an ascii column, frozen bytes are marked with a *.
+pub fn hex_view(memory: &[u8], frozen: &HashMap<u16, u8>, start: u16, length: u16) -> String {
+    let mut out = String::new();
+    let mut row = start & 0xFFF0;
+    let end = start as u32 + length as u32;
+    while (row as u32) < end {
+        out.push_str(&format!("${:04X}: ", row));
+        let mut ascii = String::new();
+        for i in 0..16 {
+            let address = row.wrapping_add(i);
+            if (address as u32) < start as u32 || address as u32 >= end {
+                out.push_str("   ");
+                ascii.push(' ');
+                continue;
+            }
+            let byte = memory[address as usize];
+            let mark = if frozen.contains_key(&address) { '*' } else { ' ' };
+            out.push_str(&format!("{:02X}{}", byte, mark));
+            ascii.push(if byte.is_ascii_graphic() { byte as char } else { '.' });
+        }
+        out.push_str(&format!(" |{}|\n", ascii));
+        row = match row.checked_add(16) {
+            Some(next) => next,
+            None => break,
+        };
+    }
+    out
+}
+
+// One row per sprite, * for the ones sprite evaluation picked for the next line.
+fn oam_view(ppu: &Ppu) -> String {
+    let picked: Vec<String> = ppu.line_sprites.iter().map(|s| s.to_string()).collect();
+    let mut out = format!("OAMADDR ${:02X}, next line: {}\n", ppu.oam_addr, if picked.is_empty() { "none".to_string() } else { picked.join(" ") });
+    out.push_str(" #   Y  TILE ATTR   X\n");
+    for (sprite, entry) in ppu.oam.chunks(4).enumerate() {
+        let mark = if ppu.line_sprites.contains(&(sprite as u8)) { " *" } else { "" };
+        out.push_str(&format!("{:2}  ${:02X}  ${:02X}  ${:02X}  ${:02X}{}\n", sprite, entry[0], entry[1], entry[2], entry[3], mark));
+    }
+    out
+}
+
+fn run_command(emulator: &mut Emulator, line: &str) -> bool {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let debugger = emulator.debugger.as_mut().unwrap();
+    match words.as_slice() {
+        [] | ["s"] => return true,
+        ["c"] => {
+            debugger.stepping = false;
+            return true;
+        }
+        ["q"] => {
+            emulator.halt(HaltReason::Quit);
+            return true;
+        }
+        ["b"] => {
+            let mut breakpoints: Vec<&u16> = debugger.breakpoints.iter().collect();
+            breakpoints.sort();
+            for address in breakpoints {
+                println!("${:04X}", address);
+            }
+        }
+        ["b", address] => match parse_hex(address) {
+            Some(address) => {
+                if !debugger.breakpoints.remove(&address) {
+                    debugger.breakpoints.insert(address);
+                }
+            }
+            None => println!("bad address {}", address),
+        },
+        ["diff", count, rest @ ..] if rest.is_empty() || rest == ["f"] => match count.parse::<u64>() {
+            Ok(count) if count > 0 => {
+                let baseline = state_diff::capture(emulator);
+                let debugger = emulator.debugger.as_mut().unwrap();
+                debugger.diff = Some(PendingDiff {
+                    baseline,
+                    total: count,
+                    remaining: count,
+                    frames: !rest.is_empty(),
+                });
+                debugger.stepping = false;
+                return true;
+            }
+            _ => println!("usage: diff <n> [f]"),
+        },
+        ["r"] => {
+            emulator.print_state();
+            let microphone = if emulator.controllers[1].microphone() { "  mic" } else { "" };
+            println!("P1 {}  P2 {}{}", button_text(emulator.controllers[0].buttons()), button_text(emulator.controllers[1].buttons()), microphone);
+            let lagged = if emulator.lag.last_frame_lagged { "  (last frame lagged)" } else { "" };
+            println!("lag frames {}{}", emulator.lag.frames, lagged);
+            if let Some(device) = emulator.expansion.as_ref() {
+                println!("expansion: {}", device.status());
+            }
+        }
+        ["turbo", buttons, rest @ ..] => {
+            let turbo = match rest {
+                ["off"] => Some(None),
+                [on, off] => match (on.parse(), off.parse()) {
+                    (Ok(on_frames), Ok(off_frames)) => Some(Some(Turbo { on_frames, off_frames })),
+                    _ => None,
+                },
+                _ => None,
+            };
+            match (parse_buttons(buttons), turbo) {
+                (Some(buttons), Some(turbo)) => {
+                    for bit in (0..8).filter(|bit| buttons & (1 << bit) != 0) {
+                        emulator.controllers[0].set_turbo(1 << bit, turbo);
+                    }
+                }
+                _ => println!("usage: turbo <buttons> <on> <off> | turbo <buttons> off"),
+            }
+        }
+        ["rec", slot] => match slot.parse::<u8>() {
+            Ok(slot) => {
+                emulator.controllers[0].start_recording(slot);
+                println!("recording macro {}", slot);
+            }
+            Err(_) => println!("bad slot {}", slot),
+        },
+        ["stop"] => emulator.controllers[0].stop_recording(),
+        ["play", slot] => match slot.parse::<u8>() {
+            Ok(slot) if emulator.controllers[0].play_macro(slot) => return true,
+            Ok(slot) => println!("no macro in slot {}", slot),
+            Err(_) => println!("bad slot {}", slot),
+        },
+        ["macro", slot, steps @ ..] if !steps.is_empty() => {
+            let steps: Option<Vec<(u8, u16)>> = steps
+                .iter()
+                .map(|step| step.split_once(':').and_then(|(buttons, frames)| Some((parse_buttons(buttons)?, frames.parse().ok()?))))
+                .collect();
+            match (slot.parse::<u8>(), steps) {
+                (Ok(slot), Some(steps)) => emulator.controllers[0].bind_macro(slot, Macro { steps }),
+                _ => println!("usage: macro <slot> <buttons>:<frames>.."),
+            }
+        }
+        ["mic"] => {
+            let on = !emulator.controllers[1].microphone();
+            emulator.controllers[1].set_microphone(on);
+            println!("microphone {}", if on { "on" } else { "off" });
+        }
+        ["dev", rest @ ..] => match emulator.expansion.as_mut() {
+            Some(device) if rest.is_empty() => println!("{}", device.status()),
+            Some(device) => {
+                if let Err(e) = device.command(rest) {
+                    println!("{}", e);
+                }
+            }
+            None => println!("nothing in the expansion port"),
+        },
+        ["reset"] => {
+            emulator.soft_reset();
+            return true;
+        }
+        ["eject"] => match netplay::refuse_jump(emulator, "eject the cartridge") {
+            Ok(()) => {
+                emulator.eject();
+                println!("cartridge ejected");
+            }
+            Err(e) => println!("{}", e),
+        },
+        ["load", path] => match emulator.insert_cartridge(path) {
+            Ok(()) => return true,
+            Err(e) => println!("failed to load {}: {}", path, e),
+        },
+        ["power"] => match netplay::refuse_jump(emulator, "power cycle") {
+            Ok(()) => {
+                emulator.power_cycle();
+                return true;
+            }
+            Err(e) => println!("{}", e),
+        },
+        ["k"] => {
+            println!("SP ${:04X}", 0x0100 + emulator.registers.stack_pointer as u16);
+            print!("{}", hex_view(&emulator.memory, &debugger.frozen, 0x0100, 0x100));
+        }
+        ["pal"] => {
+            println!("PPUMASK {:#010b} {:?}, {}", emulator.ppu.mask, emulator.ppu.region, emulator.ppu.mask_text());
+            for row in 0..4 {
+                let label = if row < 2 { "bg " } else { "spr" };
+                print!("{} {}-{}:", label, (row % 2) * 2, (row % 2) * 2 + 1);
+                for entry in row * 8..row * 8 + 8 {
+                    let index = emulator.ppu.read_vram(0x3F00 + entry as u16);
+                    let (r, g, b) = match emulator.vs.as_ref() {
+                        Some(vs) => palette::lookup(vs.map_color(index), emulator.ppu.mask, emulator.ppu.region),
+                        None => emulator.ppu.color(entry),
+                    };
+                    print!(" {:02X}=#{:02X}{:02X}{:02X}", index, r, g, b);
+                }
+                println!();
+            }
+        }
+        ["oam"] => print!("{}", oam_view(&emulator.ppu)),
+        ["m", address, rest @ ..] => match emulator.ram_map.address(address) {
+            Some(address) => {
+                let length = rest.first().and_then(|l| parse_hex(l)).unwrap_or(0x80);
+                print!("{}", hex_view(&emulator.memory, &debugger.frozen, address, length));
+                // named values in the range, under the bytes
+                let end = address as u32 + length as u32;
+                for label in emulator.ram_map.labels.iter().filter(|l| (l.start as u32) < end && l.end >= address) {
+                    println!("${:04X} {}", label.start, Watch::new(&label.name, label.start, label.kind).text(&emulator.memory));
+                }
+            }
+            None => println!("bad address {}", address),
+        },
+        ["w", address, bytes @ ..] if !bytes.is_empty() => {
+            let address = emulator.ram_map.address(address);
+            let values: Option<Vec<u16>> = bytes.iter().map(|b| parse_hex(b)).collect();
+            match (address, values) {
+                (Some(address), Some(values)) => {
+                    for (i, value) in values.iter().enumerate() {
+                        emulator.memory[address.wrapping_add(i as u16) as usize] = *value as u8;
+                    }
+                }
+                _ => println!("usage: w <addr> <byte>.."),
+            }
+        }
+        ["vm", address, rest @ ..] => match parse_hex(address) {
+            Some(address) => {
+                // PPU space is 14 bits, the view stops at its end
+                let address = address & 0x3FFF;
+                let length = rest.first().and_then(|l| parse_hex(l)).unwrap_or(0x80).min(0x4000 - address);
+                let vram: Vec<u8> = (0..0x4000).map(|a| emulator.ppu.read_vram(a)).collect();
+                print!("{}", hex_view(&vram, &HashMap::new(), address, length));
+            }
+            None => println!("bad address {}", address),
+        },
+        ["vw", address, bytes @ ..] if !bytes.is_empty() => {
+            let values: Option<Vec<u16>> = bytes.iter().map(|b| parse_hex(b)).collect();
+            match (parse_hex(address), values) {
+                (Some(address), Some(values)) => {
+                    for (i, value) in values.iter().enumerate() {
+                        emulator.ppu.write_vram(address.wrapping_add(i as u16), *value as u8);
+                    }
+                }
+                _ => println!("usage: vw <addr> <byte>.."),
+            }
+        }
+        ["f", address, rest @ ..] => match emulator.ram_map.address(address) {
+            Some(address) => {
+                let value = rest.first().and_then(|v| parse_hex(v)).map(|v| v as u8).unwrap_or(emulator.memory[address as usize]);
+                emulator.memory[address as usize] = value;
+                debugger.frozen.insert(address, value);
+            }
+            None => println!("bad address {}", address),
+        },
+        ["u", address] => match emulator.ram_map.address(address) {
+            Some(address) => {
+                debugger.frozen.remove(&address);
+            }
+            None => println!("bad address {}", address),
+        },
+        ["watch"] => {
+            for line in emulator.watches.overlay(&emulator.memory) {
+                println!("{}", line);
+            }
+        }
+        ["watch", name] if emulator.ram_map.find(name).is_some() => {
+            let label = emulator.ram_map.find(name).unwrap();
+            emulator.watches.add(Watch::new(&label.name, label.start, label.kind));
+        }
+        ["watch", ..] => match Watch::parse(line.trim_start_matches("watch")) {
+            Ok(watch) => emulator.watches.add(watch),
+            Err(e) => println!("{}", e),
+        },
+        ["map"] => {
+            for label in emulator.ram_map.labels.iter() {
+                let range = if label.end > label.start { format!("-${:04X}", label.end) } else { String::new() };
+                println!("${:04X}{} {}", label.start, range, Watch::new(&label.name, label.start, label.kind).text(&emulator.memory));
+            }
+        }
+        ["unwatch", name] => {
+            if !emulator.watches.remove(name) {
+                println!("no watch named {}", name);
+            }
+        }
+        ["log"] => {
+            emulator.watches.log_changes = !emulator.watches.log_changes;
+            println!("watch change log {}", if emulator.watches.log_changes { "on" } else { "off" });
+        }
+        ["int", "json", path] => {
+            if let Err(e) = fs::write(path, emulator.interrupts.to_json()) {
+                println!("failed to write {}: {}", path, e);
+            }
+        }
+        ["int", rest @ ..] if rest.len() <= 1 => {
+            let frames = rest.first().and_then(|f| f.parse().ok()).unwrap_or(1);
+            let mut frame = None;
+            for event in emulator.interrupts.recent(emulator.ppu.frame, frames) {
+                if frame != Some(event.asserted.frame) {
+                    frame = Some(event.asserted.frame);
+                    println!("frame {}", event.asserted.frame);
+                }
+                println!("  {}", event.text());
+            }
+        }
+        ["guard"] => {
+            for (i, rule) in emulator.guards.rules.iter().enumerate() {
+                println!("{}: {}", i, rule.text());
+            }
+        }
+        ["guard", ..] => match Rule::parse(line.trim_start_matches("guard")) {
+            Ok(rule) => emulator.guards.rules.push(rule),
+            Err(e) => println!("{}", e),
+        },
+        ["unguard", index] => match index.parse::<usize>() {
+            Ok(index) if index < emulator.guards.rules.len() => {
+                emulator.guards.rules.remove(index);
+            }
+            _ => println!("no guard {}", index),
+        },
+        ["triggers"] => {
+            for trigger in emulator.triggers.list.iter() {
+                println!("{}", trigger.text());
+            }
+        }
+        ["ss", name] => match save_slot(emulator, name) {
+            Ok(()) => println!("saved slot {}", name),
+            Err(e) => println!("failed to save slot {}: {}", name, e),
+        },
+        ["ls", rest @ ..] if rest.len() <= 1 => match load_slot(emulator, rest.first().copied()) {
+            Ok(name) => {
+                println!("loaded slot {}", name);
+                return true;
+            }
+            Err(e) => println!("failed to load slot: {}", e),
+        },
+        ["slots"] => {
+            for slot in emulator.practice.slots() {
+                let mark = if emulator.practice.last.as_deref() == Some(slot.name.as_str()) { '*' } else { ' ' };
+                match slot.metadata {
+                    Some(meta) => {
+                        let other = meta.rom_crc32.is_some() && emulator.rom_crc32.is_some() && meta.rom_crc32 != emulator.rom_crc32;
+                        let note = if other { "  (other ROM)" } else { "" };
+                        println!("{} {:<16} {}  played {}  frame {}{}", mark, slot.name, meta.saved_text(), meta.play_text(), meta.frames, note);
+                    }
+                    None => println!("{} {}", mark, slot.name),
+                }
+            }
+        }
+        ["rng", "off"] => emulator.practice.rng.clear(),
+        ["rng", address, rest @ ..] => match parse_hex(address) {
+            Some(address) => {
+                let length = rest.first().and_then(|l| parse_hex(l)).unwrap_or(1);
+                emulator.practice.rng.extend((0..length).map(|i| address.wrapping_add(i)));
+            }
+            None => println!("bad address {}", address),
+        },
+        ["coin", rest @ ..] if rest.len() <= 1 => match (emulator.vs.as_mut(), rest.first().copied().unwrap_or("1")) {
+            (Some(vs), slot @ ("1" | "2")) => vs.insert_coin(if slot == "1" { 0 } else { 1 }),
+            (Some(_), slot) => println!("no coin slot {}", slot),
+            (None, _) => println!("not a VS. System game"),
+        },
+        ["service"] => match emulator.vs.as_mut() {
+            Some(vs) => vs.service = !vs.service,
+            None => println!("not a VS. System game"),
+        },
+        ["dip", value] => match (emulator.vs.as_mut(), parse_hex(value)) {
+            (Some(vs), Some(value)) => vs.dip = value as u8,
+            (Some(_), None) => println!("bad value {}", value),
+            (None, _) => println!("not a VS. System game"),
+        },
+        _ => println!("{}", HELP),
+    }
+    false
+}
+
+// Read commands from stdin until one of them resumes execution.
+pub fn prompt(emulator: &mut Emulator) {
+    #[cfg(feature = "tui")]
+    if emulator.debugger.as_ref().unwrap().tui.is_some() {
+        crate::tui::draw(emulator);
+    }
+    let pc = emulator.registers.program_counter;
+    let (text, _) = emulator.ram_map.disassemble(&emulator.memory, pc);
+    println!(
+        "${:04X}: {:<24} scanline {:3} dot {:3} cycle {}",
+        pc, text, emulator.ppu.scanline, emulator.ppu.dot, emulator.total_cycles
+    );
+    loop {
+        print!("(rnes) ");
+        // a closed pipe only loses the prompt, the command can still be read
+        let _ = io::stdout().flush();
+        let Some(line) = read_line(emulator) else {
+            // stdin closed, nothing more will be typed so just run
+            emulator.debugger.as_mut().unwrap().stepping = false;
+            return;
+        };
+        if run_command(emulator, line.trim()) {
+            return;
+        }
+    }
+}
+
+// A command line, None once stdin closes. With the TUI on its key thread owns stdin.
+#[cfg_attr(not(feature = "tui"), allow(unused_variables))]
+fn read_line(emulator: &mut Emulator) -> Option<String> {
+    #[cfg(feature = "tui")]
+    if let Some(live) = emulator.debugger.as_mut().and_then(|d| d.tui.as_mut()) {
+        return live.read_line();
+    }
+    let mut line = String::new();
+    match io::stdin().lock().read_line(&mut line) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(line),
+    }
+}
+
+// Stop running and prompt when PC lands on a breakpoint.
+pub fn check_breakpoint(emulator: &mut Emulator) {
+    let pc = emulator.registers.program_counter;
+    if let Some(debugger) = emulator.debugger.as_mut() {
+        if !debugger.stepping && debugger.breakpoints.contains(&pc) {
+            println!("breakpoint at ${:04X}", pc);
+            debugger.stepping = true;
+        }
+    }
+}
+
+// Put frozen bytes back after an instruction may have changed them.
+pub fn apply_freezes(emulator: &mut Emulator) {
+    if let Some(debugger) = emulator.debugger.as_ref() {
+        for (address, value) in debugger.frozen.iter() {
+            emulator.memory[*address as usize] = *value;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn emulator() -> Emulator {
+        let mut emulator = Emulator::new();
+        emulator.verbose = false;
+        emulator.debugger = Some(Debugger::new());
+        emulator
+    }
+
+    #[test]
+    fn vw_writes_ppu_space() {
+        let mut emulator = emulator();
+        run_command(&mut emulator, "vw 2000 12 34");
+        assert_eq!(emulator.ppu.read_vram(0x2000), 0x12);
+        assert_eq!(emulator.ppu.read_vram(0x2001), 0x34);
+        // $3F10 is a mirror of the backdrop color
+        run_command(&mut emulator, "vw $3F10 21");
+        assert_eq!(emulator.ppu.read_vram(0x3F00), 0x21);
+        // the CPU side is left alone
+        assert_eq!(emulator.memory[0x2000], 0);
+    }
+
+    #[test]
+    fn oam_view_lists_every_sprite_and_marks_the_next_line() {
+        let mut emulator = emulator();
+        emulator.ppu.oam[4..8].copy_from_slice(&[0x10, 0x24, 0x43, 0x80]);
+        emulator.ppu.oam_addr = 0x08;
+        emulator.ppu.line_sprites = vec![1, 63];
+        let view = oam_view(&emulator.ppu);
+        let lines: Vec<&str> = view.lines().collect();
+        assert_eq!(lines.len(), 2 + 64);
+        assert_eq!(lines[0], "OAMADDR $08, next line: 1 63");
+        assert_eq!(lines[2], " 0  $00  $00  $00  $00");
+        assert_eq!(lines[3], " 1  $10  $24  $43  $80 *");
+        assert_eq!(lines[65], "63  $00  $00  $00  $00 *");
+        emulator.ppu.line_sprites.clear();
+        assert!(oam_view(&emulator.ppu).starts_with("OAMADDR $08, next line: none\n"));
+    }
+
+    #[test]
+    fn hex_view_marks_frozen_bytes_and_blanks_outside_the_range() {
+        let mut memory = vec![0u8; 0x20];
+        memory[0x11] = b'A';
+        memory[0x12] = 0x07;
+        let frozen = HashMap::from([(0x12, 0x07)]);
+        let view = hex_view(&memory, &frozen, 0x11, 2);
+        assert_eq!(view, format!("$0010:    41 07*{} | A.{}|\n", " ".repeat(3 * 13), " ".repeat(13)));
+    }
+}
@@ -0,0 +1,238 @@
+use crate::Mode;
+use crate::Mode::*;
+
+// Every official 6502 opcode, independent of which ones the CPU core implements
+// so traces and exports can show code the emulator cannot run yet.
+pub fn decode(opcode: u8) -> Option<(&'static str, Mode)> {
+    let decoded = match opcode {
+        0x00 => ("BRK", Implied),
+        0x01 => ("ORA", IndirectX),
+        0x05 => ("ORA", ZeroPage),
+        0x06 => ("ASL", ZeroPage),
+        0x08 => ("PHP", Implied),
+        0x09 => ("ORA", Immediate),
+        0x0A => ("ASL", Accumulator),
+        0x0D => ("ORA", Absolute),
+        0x0E => ("ASL", Absolute),
+        0x10 => ("BPL", Relative),
+        0x11 => ("ORA", IndirectY),
+        0x15 => ("ORA", ZeroPageX),
+        0x16 => ("ASL", ZeroPageX),
+        0x18 => ("CLC", Implied),
+        0x19 => ("ORA", AbsoluteY),
+        0x1D => ("ORA", AbsoluteX),
+        0x1E => ("ASL", AbsoluteX),
+        0x20 => ("JSR", Absolute),
+        0x21 => ("AND", IndirectX),
+        0x24 => ("BIT", ZeroPage),
+        0x25 => ("AND", ZeroPage),
+        0x26 => ("ROL", ZeroPage),
+        0x28 => ("PLP", Implied),
+        0x29 => ("AND", Immediate),
+        0x2A => ("ROL", Accumulator),
+        0x2C => ("BIT", Absolute),
+        0x2D => ("AND", Absolute),
+        0x2E => ("ROL", Absolute),
+        0x30 => ("BMI", Relative),
+        0x31 => ("AND", IndirectY),
+        0x35 => ("AND", ZeroPageX),
+        0x36 => ("ROL", ZeroPageX),
+        0x38 => ("SEC", Implied),
+        0x39 => ("AND", AbsoluteY),
+        0x3D => ("AND", AbsoluteX),
+        0x3E => ("ROL", AbsoluteX),
+        0x40 => ("RTI", Implied),
+        0x41 => ("EOR", IndirectX),
+        0x45 => ("EOR", ZeroPage),
+        0x46 => ("LSR", ZeroPage),
+        0x48 => ("PHA", Implied),
+        0x49 => ("EOR", Immediate),
+        0x4A => ("LSR", Accumulator),
+        0x4C => ("JMP", Absolute),
+        0x4D => ("EOR", Absolute),
+        0x4E => ("LSR", Absolute),
+        0x50 => ("BVC", Relative),
+        0x51 => ("EOR", IndirectY),
+        0x55 => ("EOR", ZeroPageX),
+        0x56 => ("LSR", ZeroPageX),
+        0x58 => ("CLI", Implied),
+        0x59 => ("EOR", AbsoluteY),
+        0x5D => ("EOR", AbsoluteX),
+        0x5E => ("LSR", AbsoluteX),
+        0x60 => ("RTS", Implied),
+        0x61 => ("ADC", IndirectX),
+        0x65 => ("ADC", ZeroPage),
+        0x66 => ("ROR", ZeroPage),
+        0x68 => ("PLA", Implied),
+        0x69 => ("ADC", Immediate),
+        0x6A => ("ROR", Accumulator),
+        0x6C => ("JMP", AbsoluteIndirect),
+        0x6D => ("ADC", Absolute),
+        0x6E => ("ROR", Absolute),
+        0x70 => ("BVS", Relative),
+        0x71 => ("ADC", IndirectY),
+        0x75 => ("ADC", ZeroPageX),
+        0x76 => ("ROR", ZeroPageX),
+        0x78 => ("SEI", Implied),
+        0x79 => ("ADC", AbsoluteY),
+        0x7D => ("ADC", AbsoluteX),
+        0x7E => ("ROR", AbsoluteX),
+        0x81 => ("STA", IndirectX),
+        0x84 => ("STY", ZeroPage),
+        0x85 => ("STA", ZeroPage),
+        0x86 => ("STX", ZeroPage),
+        0x88 => ("DEY", Implied),
+        0x8A => ("TXA", Implied),
+        0x8C => ("STY", Absolute),
+        0x8D => ("STA", Absolute),
+        0x8E => ("STX", Absolute),
+        0x90 => ("BCC", Relative),
+        0x91 => ("STA", IndirectY),
+        0x94 => ("STY", ZeroPageX),
+        0x95 => ("STA", ZeroPageX),
+        0x96 => ("STX", ZeroPageY),
+        0x98 => ("TYA", Implied),
+        0x99 => ("STA", AbsoluteY),
+        0x9A => ("TXS", Implied),
+        0x9D => ("STA", AbsoluteX),
+        0xA0 => ("LDY", Immediate),
+        0xA1 => ("LDA", IndirectX),
+        0xA2 => ("LDX", Immediate),
+        0xA4 => ("LDY", ZeroPage),
+        0xA5 => ("LDA", ZeroPage),
+        0xA6 => ("LDX", ZeroPage),
+        0xA8 => ("TAY", Implied),
+        0xA9 => ("LDA", Immediate),
+        0xAA => ("TAX", Implied),
+        0xAC => ("LDY", Absolute),
+        0xAD => ("LDA", Absolute),
+        0xAE => ("LDX", Absolute),
+        0xB0 => ("BCS", Relative),
+        0xB1 => ("LDA", IndirectY),
+        0xB4 => ("LDY", ZeroPageX),
+        0xB5 => ("LDA", ZeroPageX),
+        0xB6 => ("LDX", ZeroPageY),
+        0xB8 => ("CLV", Implied),
+        0xB9 => ("LDA", AbsoluteY),
+        0xBA => ("TSX", Implied),
+        0xBC => ("LDY", AbsoluteX),
+        0xBD => ("LDA", AbsoluteX),
+        0xBE => ("LDX", AbsoluteY),
+        0xC0 => ("CPY", Immediate),
+        0xC1 => ("CMP", IndirectX),
+        0xC4 => ("CPY", ZeroPage),
+        0xC5 => ("CMP", ZeroPage),
+        0xC6 => ("DEC", ZeroPage),
+        0xC8 => ("INY", Implied),
+        0xC9 => ("CMP", Immediate),
+        0xCA => ("DEX", Implied),
+        0xCC => ("CPY", Absolute),
+        0xCD => ("CMP", Absolute),
+        0xCE => ("DEC", Absolute),
+        0xD0 => ("BNE", Relative),
+        0xD1 => ("CMP", IndirectY),
+        0xD5 => ("CMP", ZeroPageX),
+        0xD6 => ("DEC", ZeroPageX),
+        0xD8 => ("CLD", Implied),
+        0xD9 => ("CMP", AbsoluteY),
+        0xDD => ("CMP", AbsoluteX),
+        0xDE => ("DEC", AbsoluteX),
+        0xE0 => ("CPX", Immediate),
+        0xE1 => ("SBC", IndirectX),
+        0xE4 => ("CPX", ZeroPage),
+        0xE5 => ("SBC", ZeroPage),
+        0xE6 => ("INC", ZeroPage),
+        0xE8 => ("INX", Implied),
+        0xE9 => ("SBC", Immediate),
+        0xEA => ("NOP", Implied),
+        0xEC => ("CPX", Absolute),
+        0xED => ("SBC", Absolute),
+        0xEE => ("INC", Absolute),
+        0xF0 => ("BEQ", Relative),
+        0xF1 => ("SBC", IndirectY),
+        0xF5 => ("SBC", ZeroPageX),
+        0xF6 => ("INC", ZeroPageX),
+        0xF8 => ("SED", Implied),
+        0xF9 => ("SBC", AbsoluteY),
+        0xFD => ("SBC", AbsoluteX),
+        0xFE => ("INC", AbsoluteX),
+        _ => return None,
+    };
+    Some(decoded)
+}
+
+// operand bytes that follow the opcode for each addressing mode
+pub fn operand_length(mode: &Mode) -> u16 {
+    match mode {
+        Null | Implied | Accumulator => 0,
+        Immediate | ZeroPage | ZeroPageX | ZeroPageY | IndirectX | IndirectY | Relative => 1,
+        Absolute | AbsoluteIndirect | AbsoluteX | AbsoluteY => 2,
+    }
+}
+
+// instruction length in bytes, unknown opcodes count as a single byte
+pub fn instruction_length(opcode: u8) -> u16 {
+    match decode(opcode) {
+        Some((_, mode)) => 1 + operand_length(&mode),
+        None => 1,
+    }
+}
+
+// Branch, JSR and JMP absolute destinations, what gets a label in exports.
+pub fn jump_target(bytes: [u8; 3], address: u16) -> Option<u16> {
+    let (mnemonic, mode) = decode(bytes[0])?;
+    match (mnemonic, mode) {
+        (_, Relative) => Some(address.wrapping_add(2).wrapping_add(bytes[1] as i8 as u16)),
+        ("JSR", Absolute) | ("JMP", Absolute) => Some(u16::from_le_bytes([bytes[1], bytes[2]])),
+        _ => None,
+    }
+}
+
+// Operand text, name gives a label for an address when the caller has one.
+pub fn operand_text(mode: &Mode, bytes: [u8; 3], address: u16, name: &dyn Fn(u16) -> Option<String>) -> String {
+    let lo = bytes[1];
+    let word = u16::from_le_bytes([lo, bytes[2]]);
+    let word_text = name(word).unwrap_or(format!("${:04X}", word));
+    let zero_page_text = name(lo as u16).unwrap_or(format!("${:02X}", lo));
+    match mode {
+        Null | Implied => String::new(),
+        Accumulator => " A".to_string(),
+        Immediate => format!(" #${:02X}", lo),
+        ZeroPage => format!(" {}", zero_page_text),
+        ZeroPageX => format!(" {},X", zero_page_text),
+        ZeroPageY => format!(" {},Y", zero_page_text),
+        Absolute => format!(" {}", word_text),
+        AbsoluteIndirect => format!(" ({})", word_text),
+        AbsoluteX => format!(" {},X", word_text),
+        AbsoluteY => format!(" {},Y", word_text),
+        IndirectX => format!(" ({},X)", zero_page_text),
+        IndirectY => format!(" ({}),Y", zero_page_text),
+        Relative => {
+            let target = address.wrapping_add(2).wrapping_add(lo as i8 as u16);
+            format!(" {}", name(target).unwrap_or(format!("${:04X}", target)))
+        }
+    }
+}
+
+// Disassemble the instruction at address, returns the text and its length in bytes.
+// Unknown opcodes come back as a .byte directive of length 1.
+pub fn disassemble(memory: &[u8], address: u16) -> (String, u16) {
+    let read = |offset: u16| memory[address.wrapping_add(offset) as usize];
+    disassemble_bytes([read(0), read(1), read(2)], address)
+}
+
+// Same as disassemble for an instruction already copied out of memory.
+pub fn disassemble_bytes(bytes: [u8; 3], address: u16) -> (String, u16) {
+    disassemble_named(bytes, address, &|_| None)
+}
+
+// disassemble_bytes with operand addresses named, by a RAM map say.
+pub fn disassemble_named(bytes: [u8; 3], address: u16, name: &dyn Fn(u16) -> Option<String>) -> (String, u16) {
+    match decode(bytes[0]) {
+        Some((mnemonic, mode)) => {
+            let text = format!("{}{}", mnemonic, operand_text(&mode, bytes, address, name));
+            (text, 1 + operand_length(&mode))
+        }
+        None => (format!(".byte ${:02X}", bytes[0]), 1),
+    }
+}
@@ -0,0 +1,326 @@
+// Something wired into the joypad lines besides the two standard controllers:
+// a Famicom expansion port device, or an NES port device that has bits of its
+// own. It sees every $4016 write and adds its bits to $4016/$4017 reads.
+//
+// Famicom expansion port devices drive bit 1 of $4016 and bits 1-4 of $4017,
+// NES port devices bits 3 and 4. Bit 0 belongs to the standard controllers
+// and bit 2 of $4016 to the Famicom microphone.
+pub trait ExpansionDevice {
+    fn name(&self) -> &'static str;
+
+    // $4016 write, OUT0-OUT2 are bits 0-2
+    fn write(&mut self, _value: u8) {}
+
+    // A read of $4016 (port 0) or $4017 (port 1), only the device's own bits
+    // may be set. Serial devices shift on the read like a controller does.
+    fn read(&mut self, _port: usize) -> u8 {
+        0
+    }
+
+    // Called once per video frame.
+    fn end_frame(&mut self) {}
+
+    // Debugger command text after "dev", for devices the frontend steers.
+    fn command(&mut self, _words: &[&str]) -> Result<(), String> {
+        Err(format!("{} takes no commands", self.name()))
+    }
+
+    // One line for the debugger.
+    fn status(&self) -> String {
+        self.name().to_string()
+    }
+}
+
+// Which connector a device sits on decides the bits it drives.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Wiring {
+    // controller port 2, bits 3 and 4 of $4017
+    Nes,
+    // expansion port, bit 1 of $4016 and $4017
+    Famicom,
+}
+
+// roughly the span Arkanoid's paddle knob reports from one end to the other
+const VAUS_MIN: u8 = 0x62;
+const VAUS_MAX: u8 = 0xF2;
+
+// Arkanoid's Vaus paddle: a potentiometer read out serially, MSB first and
+// inverted, after a strobe latches it, and one fire button.
+pub struct Vaus {
+    pub wiring: Wiring,
+    pub position: u8,
+    pub fire: bool,
+    strobe: bool,
+    shift: u8,
+}
+
+impl Vaus {
+    pub fn new(wiring: Wiring) -> Self {
+        Vaus {
+            wiring,
+            position: ((VAUS_MIN as u16 + VAUS_MAX as u16) / 2) as u8,
+            fire: false,
+            strobe: false,
+            shift: 0,
+        }
+    }
+
+    // Mouse X across a window width, left edge to the knob's minimum.
+    pub fn mouse_x(&mut self, x: u32, width: u32) {
+        let x = x.min(width.saturating_sub(1)) as u64;
+        let span = (VAUS_MAX - VAUS_MIN) as u64;
+        self.position = VAUS_MIN + (x * span / width.saturating_sub(1).max(1) as u64) as u8;
+    }
+}
+
+impl ExpansionDevice for Vaus {
+    fn name(&self) -> &'static str {
+        match self.wiring {
+            Wiring::Nes => "Vaus paddle (NES)",
+            Wiring::Famicom => "Vaus paddle (Famicom)",
+        }
+    }
+
+    fn write(&mut self, value: u8) {
+        self.strobe = value & 1 != 0;
+        if self.strobe {
+            self.shift = !self.position;
+        }
+    }
+
+    fn read(&mut self, port: usize) -> u8 {
+        let (fire_port, fire_bit, data_port, data_bit) = match self.wiring {
+            Wiring::Nes => (1, 0x08, 1, 0x10),
+            Wiring::Famicom => (0, 0x02, 1, 0x02),
+        };
+        let mut value = 0;
+        if port == fire_port && self.fire {
+            value |= fire_bit;
+        }
+        if port == data_port {
+            if self.shift & 0x80 != 0 {
+                value |= data_bit;
+            }
+            if !self.strobe {
+                self.shift <<= 1;
+            }
+        }
+        value
+    }
+
+    fn command(&mut self, words: &[&str]) -> Result<(), String> {
+        match words {
+            ["fire"] => self.fire = !self.fire,
+            ["pos", value] => self.position = parse_byte(value).ok_or_else(|| format!("bad position {}", value))?,
+            ["mouse", x, width] => match (x.parse(), width.parse()) {
+                (Ok(x), Ok(width)) if width > 0 => self.mouse_x(x, width),
+                _ => return Err("usage: dev mouse <x> <width>".to_string()),
+            },
+            _ => return Err("vaus commands: fire (toggle), pos <byte>, mouse <x> <width>".to_string()),
+        }
+        Ok(())
+    }
+
+    fn status(&self) -> String {
+        format!("{}: position ${:02X}{}", self.name(), self.position, if self.fire { ", fire held" } else { "" })
+    }
+}
+
+// decimal or $hex
+fn parse_byte(text: &str) -> Option<u8> {
+    match text.strip_prefix('$') {
+        Some(hex) => u8::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+// mat buttons in the order each data line shifts them out, the second line has only four
+const POWER_PAD_LINE_3: [u8; 8] = [2, 1, 5, 9, 6, 10, 11, 7];
+const POWER_PAD_LINE_4: [u8; 4] = [4, 3, 12, 8];
+// keys for mat buttons 1-12, one row of the mat per keyboard row
+const POWER_PAD_KEYS: [char; 12] = ['q', 'w', 'e', 'r', 'a', 's', 'd', 'f', 'z', 'x', 'c', 'v'];
+
+// The Power Pad floor mat in controller port 2, twelve buttons read out as two
+// serial streams on bits 3 and 4 of $4017.
+pub struct PowerPad {
+    // bit n set while mat button n + 1 is pressed
+    pub pressed: u16,
+    // keyboard key for each mat button
+    pub keys: [char; 12],
+    strobe: bool,
+    shift_3: u8,
+    shift_4: u8,
+}
+
+impl Default for PowerPad {
+    fn default() -> Self {
+        PowerPad {
+            pressed: 0,
+            keys: POWER_PAD_KEYS,
+            strobe: false,
+            shift_3: 0,
+            shift_4: 0,
+        }
+    }
+}
+
+impl PowerPad {
+    fn latch(&mut self) {
+        let bit = |button: u8| (self.pressed >> (button - 1)) as u8 & 1;
+        self.shift_3 = POWER_PAD_LINE_3.iter().enumerate().fold(0, |shift, (i, b)| shift | bit(*b) << i);
+        // past the fourth button the line reads 1
+        self.shift_4 = POWER_PAD_LINE_4.iter().enumerate().fold(0xF0, |shift, (i, b)| shift | bit(*b) << i);
+    }
+
+    // A key went down or up, keys not on the mat are ignored.
+    pub fn key(&mut self, key: char, down: bool) {
+        if let Some(index) = self.keys.iter().position(|k| *k == key) {
+            if down {
+                self.pressed |= 1 << index;
+            } else {
+                self.pressed &= !(1 << index);
+            }
+        }
+    }
+}
+
+impl ExpansionDevice for PowerPad {
+    fn name(&self) -> &'static str {
+        "Power Pad"
+    }
+
+    fn write(&mut self, value: u8) {
+        self.strobe = value & 1 != 0;
+        if self.strobe {
+            self.latch();
+        }
+    }
+
+    fn read(&mut self, port: usize) -> u8 {
+        if port != 1 {
+            return 0;
+        }
+        if self.strobe {
+            self.latch();
+        }
+        let value = ((self.shift_3 & 1) << 3) | ((self.shift_4 & 1) << 4);
+        if !self.strobe {
+            self.shift_3 = (self.shift_3 >> 1) | 0x80;
+            self.shift_4 = (self.shift_4 >> 1) | 0x80;
+        }
+        value
+    }
+
+    fn command(&mut self, words: &[&str]) -> Result<(), String> {
+        let buttons = |words: &[&str]| -> Result<u16, String> {
+            words.iter().try_fold(0u16, |mask, word| match word.parse::<u8>() {
+                Ok(button @ 1..=12) => Ok(mask | 1 << (button - 1)),
+                _ => Err(format!("bad mat button {}, expected 1-12", word)),
+            })
+        };
+        match words {
+            ["press", rest @ ..] => self.pressed |= buttons(rest)?,
+            ["release"] => self.pressed = 0,
+            ["release", rest @ ..] => self.pressed &= !buttons(rest)?,
+            ["keys", held] => {
+                self.pressed = 0;
+                for key in held.chars() {
+                    self.key(key, true);
+                }
+            }
+            ["map", keys] if keys.chars().count() == 12 => {
+                for (slot, key) in self.keys.iter_mut().zip(keys.chars()) {
+                    *slot = key;
+                }
+            }
+            _ => return Err("power pad commands: press <1-12>.., release [1-12].., keys <held keys>, map <12 keys for buttons 1-12>".to_string()),
+        }
+        Ok(())
+    }
+
+    fn status(&self) -> String {
+        let held: Vec<String> = (0..12).filter(|i| self.pressed & 1 << i != 0).map(|i| (i + 1).to_string()).collect();
+        let keys: String = self.keys.iter().collect();
+        format!("{}: pressed [{}], keys {}", self.name(), held.join(" "), keys)
+    }
+}
+
+// Device for --expansion, by name.
+pub fn from_name(name: &str) -> Option<Box<dyn ExpansionDevice>> {
+    let device: Box<dyn ExpansionDevice> = match name {
+        "vaus" => Box::new(Vaus::new(Wiring::Nes)),
+        "vaus-famicom" => Box::new(Vaus::new(Wiring::Famicom)),
+        "power-pad" => Box::new(PowerPad::default()),
+        _ => return None,
+    };
+    Some(device)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // strobe then read port eight times
+    fn read_out(device: &mut dyn ExpansionDevice, port: usize) -> Vec<u8> {
+        device.write(1);
+        device.write(0);
+        (0..8).map(|_| device.read(port)).collect()
+    }
+
+    #[test]
+    fn vaus_shifts_the_inverted_position_msb_first() {
+        let mut vaus = Vaus::new(Wiring::Nes);
+        vaus.position = 0x62;
+        vaus.fire = true;
+        // !0x62 is 1001_1101, fire rides along on bit 3
+        let bits: Vec<u8> = read_out(&mut vaus, 1).iter().map(|v| v >> 4 & 1).collect();
+        assert_eq!(bits, [1, 0, 0, 1, 1, 1, 0, 1]);
+        assert_eq!(vaus.read(1) & 0x08, 0x08);
+        assert_eq!(vaus.read(0), 0);
+    }
+
+    #[test]
+    fn famicom_vaus_uses_bit_1_on_both_ports() {
+        let mut vaus = Vaus::new(Wiring::Famicom);
+        vaus.position = 0xFE;
+        vaus.fire = true;
+        let bits: Vec<u8> = read_out(&mut vaus, 1).iter().map(|v| v >> 1 & 1).collect();
+        assert_eq!(bits, [0, 0, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(vaus.read(0), 0x02);
+    }
+
+    #[test]
+    fn vaus_mouse_spans_the_knob_range() {
+        let mut vaus = Vaus::new(Wiring::Nes);
+        vaus.mouse_x(0, 256);
+        assert_eq!(vaus.position, VAUS_MIN);
+        vaus.mouse_x(1000, 256);
+        assert_eq!(vaus.position, VAUS_MAX);
+    }
+
+    #[test]
+    fn power_pad_shifts_two_lines_then_ones() {
+        let mut pad = PowerPad::default();
+        pad.command(&["press", "2", "4", "7"]).unwrap();
+        let reads = read_out(&mut pad, 1);
+        let line_3: Vec<u8> = reads.iter().map(|v| v >> 3 & 1).collect();
+        let line_4: Vec<u8> = reads.iter().map(|v| v >> 4 & 1).collect();
+        // line 3 carries 2 1 5 9 6 10 11 7, line 4 carries 4 3 12 8 then reads 1
+        assert_eq!(line_3, [1, 0, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(line_4, [1, 0, 0, 0, 1, 1, 1, 1]);
+        assert_eq!(pad.read(1), 0x18);
+        assert_eq!(pad.read(0), 0);
+    }
+
+    #[test]
+    fn power_pad_keys_press_mat_buttons() {
+        let mut pad = PowerPad::default();
+        pad.key('q', true);
+        pad.key('v', true);
+        pad.key('p', true);
+        assert_eq!(pad.pressed, 1 | 1 << 11);
+        pad.key('q', false);
+        assert_eq!(pad.pressed, 1 << 11);
+        assert!(pad.command(&["press", "13"]).is_err());
+    }
+}
@@ -0,0 +1,192 @@
+#![allow(clippy::missing_safety_doc)]
+use std::ffi::{c_char, c_int, c_uint, CStr};
+use std::panic::{self, AssertUnwindSafe};
+use crate::batch::panic_message;
+use crate::Emulator;
+
+/* C interface, declared in include/rnes.h
+   Every handle comes from rnes_new and goes back through rnes_free, paths are
+   NUL terminated UTF-8. Functions returning int give 0 on success and -1 on a
+   null or bad argument, a failed load or a panic, the reason is printed.
+   Nothing here unwinds into the caller.
+*/
+
+// Opaque to C, the machine behind a handle.
+pub struct Rnes {
+    emulator: Emulator,
+}
+
+// Run f, turning a panic into fallback so it never crosses into C.
+fn guarded<T>(fallback: T, f: impl FnOnce() -> T) -> T {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(result) => result,
+        Err(payload) => {
+            println!("rnes: panicked: {}", panic_message(payload.as_ref()));
+            fallback
+        }
+    }
+}
+
+unsafe fn path<'a>(path: *const c_char) -> Option<&'a str> {
+    if path.is_null() {
+        return None;
+    }
+    CStr::from_ptr(path).to_str().ok()
+}
+
+#[no_mangle]
+pub extern "C" fn rnes_new() -> *mut Rnes {
+    guarded(std::ptr::null_mut(), || {
+        let mut emulator = Emulator::new();
+        emulator.verbose = false;
+        Box::into_raw(Box::new(Rnes { emulator }))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rnes_free(rnes: *mut Rnes) {
+    if !rnes.is_null() {
+        guarded((), || drop(Box::from_raw(rnes)));
+    }
+}
+
+// Insert the cartridge and power on.
+#[no_mangle]
+pub unsafe extern "C" fn rnes_load_rom(rnes: *mut Rnes, rom_path: *const c_char) -> c_int {
+    let (Some(rnes), Some(rom_path)) = (rnes.as_mut(), path(rom_path)) else {
+        return -1;
+    };
+    guarded(-1, || match rnes.emulator.insert_cartridge(rom_path) {
+        Ok(()) => 0,
+        Err(e) => {
+            println!("Failed to load {}: {}", rom_path, e);
+            -1
+        }
+    })
+}
+
+// 1 when a frame ran, 0 when the machine is paused or halted.
+#[no_mangle]
+pub unsafe extern "C" fn rnes_run_frame(rnes: *mut Rnes) -> c_int {
+    let Some(rnes) = rnes.as_mut() else {
+        return -1;
+    };
+    guarded(-1, || rnes.emulator.run_frame() as c_int)
+}
+
+// Buttons held on controller port 0 or 1 from the next frame, RNES_BUTTON_* bits.
+#[no_mangle]
+pub unsafe extern "C" fn rnes_set_input(rnes: *mut Rnes, port: c_uint, buttons: u8) -> c_int {
+    let Some(rnes) = rnes.as_mut() else {
+        return -1;
+    };
+    let Some(controller) = rnes.emulator.controllers.get_mut(port as usize) else {
+        return -1;
+    };
+    guarded(-1, || {
+        controller.release(0xFF);
+        controller.press(buttons);
+        0
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rnes_save_state(rnes: *const Rnes, state_path: *const c_char) -> c_int {
+    let (Some(rnes), Some(state_path)) = (rnes.as_ref(), path(state_path)) else {
+        return -1;
+    };
+    guarded(-1, || match rnes.emulator.save_state(state_path) {
+        Ok(()) => 0,
+        Err(e) => {
+            println!("Failed to save state {}: {}", state_path, e);
+            -1
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rnes_load_state(rnes: *mut Rnes, state_path: *const c_char) -> c_int {
+    let (Some(rnes), Some(state_path)) = (rnes.as_mut(), path(state_path)) else {
+        return -1;
+    };
+    guarded(-1, || match rnes.emulator.load_state(state_path) {
+        Ok(()) => 0,
+        Err(e) => {
+            println!("Failed to load state {}: {}", state_path, e);
+            -1
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::BUTTON_START;
+    use std::ffi::CString;
+    use std::fs;
+    use std::ptr;
+
+    fn temp_path(name: &str) -> CString {
+        let path = std::env::temp_dir().join(format!("rnes_ffi_{}_{}", std::process::id(), name));
+        CString::new(path.to_str().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn runs_a_rom_and_round_trips_a_state() {
+        let rom = temp_path("spin.nes");
+        let state = temp_path("spin.state");
+        // one 16KB bank spinning on LDX #$01 / BNE
+        let mut bytes = b"NES\x1A\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+        bytes.extend([0xA2, 0x01, 0xD0, 0xFE]);
+        bytes.resize(16 + 0x4000, 0);
+        fs::write(rom.to_str().unwrap(), bytes).unwrap();
+
+        unsafe {
+            let rnes = rnes_new();
+            assert!(!rnes.is_null());
+            assert_eq!(rnes_load_rom(rnes, rom.as_ptr()), 0);
+            assert_eq!(rnes_set_input(rnes, 1, BUTTON_START), 0);
+            assert_eq!((*rnes).emulator.controllers[1].buttons(), BUTTON_START);
+            assert_eq!(rnes_set_input(rnes, 2, BUTTON_START), -1);
+            assert_eq!(rnes_run_frame(rnes), 1);
+            assert_eq!(rnes_save_state(rnes, state.as_ptr()), 0);
+            assert_eq!(rnes_run_frame(rnes), 1);
+            assert_eq!((*rnes).emulator.ppu.frame, 2);
+            assert_eq!(rnes_load_state(rnes, state.as_ptr()), 0);
+            assert_eq!((*rnes).emulator.ppu.frame, 1);
+
+            // a JAM stops the machine, frames report 0 from then on
+            (*rnes).emulator.memory[0x8012] = 0x02;
+            assert_eq!(rnes_run_frame(rnes), 0);
+            assert_eq!(rnes_run_frame(rnes), 0);
+            rnes_free(rnes);
+        }
+        fs::remove_file(rom.to_str().unwrap()).unwrap();
+        fs::remove_file(state.to_str().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn null_and_bad_arguments_fail_without_crashing() {
+        let missing = temp_path("missing.nes");
+        unsafe {
+            assert_eq!(rnes_load_rom(ptr::null_mut(), missing.as_ptr()), -1);
+            assert_eq!(rnes_run_frame(ptr::null_mut()), -1);
+            assert_eq!(rnes_set_input(ptr::null_mut(), 0, 0), -1);
+            assert_eq!(rnes_save_state(ptr::null(), missing.as_ptr()), -1);
+            assert_eq!(rnes_load_state(ptr::null_mut(), missing.as_ptr()), -1);
+            rnes_free(ptr::null_mut());
+
+            let rnes = rnes_new();
+            assert_eq!(rnes_load_rom(rnes, ptr::null()), -1);
+            assert_eq!(rnes_load_rom(rnes, missing.as_ptr()), -1);
+            assert_eq!(rnes_load_state(rnes, missing.as_ptr()), -1);
+            rnes_free(rnes);
+        }
+    }
+
+    #[test]
+    fn panics_become_the_fallback() {
+        assert_eq!(guarded(-1, || panic!("boom")), -1);
+        assert_eq!(guarded(-1, || 0), 0);
+    }
+}
@@ -0,0 +1,185 @@
+use std::fs;
+use std::io;
+use crate::debugger::parse_hex;
+use crate::disasm::disassemble_named;
+use crate::Emulator;
+
+/* Guard Rules, one per line, # starts a comment
+    ro $0300-$03FF              no CPU writes to the range
+    ro $0300-$03FF from 2       ... once frame 2 has started
+    ro $0300-$03FF from $C123   ... once execution reaches $C123, the end of init say
+    nx $0000-$07FF              no instructions fetched from the range
+   A single address works in place of a range.
+*/
+
+// instructions shown before the one that broke a rule
+const REPORT_HISTORY: usize = 4;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Kind {
+    ReadOnly,
+    NoExecute,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Arm {
+    Always,
+    Frame(u64),
+    Address(u16),
+}
+
+pub struct Rule {
+    pub kind: Kind,
+    pub start: u16,
+    pub end: u16,
+    arm: Arm,
+    armed: bool,
+    pub violations: u32,
+}
+
+#[derive(Default)]
+pub struct Guards {
+    pub rules: Vec<Rule>,
+    // the fetch that just broke a no-execute rule, let through once the user continues
+    allowed_fetch: Option<u16>,
+}
+
+impl Rule {
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (kind, range, arm) = match words.as_slice() {
+            [kind, range] => (*kind, *range, None),
+            [kind, range, "from", arm] => (*kind, *range, Some(*arm)),
+            _ => return Err(format!("expected ro|nx <addr>[-<addr>] [from frame|$addr], got {:?}", line)),
+        };
+        let kind = match kind {
+            "ro" => Kind::ReadOnly,
+            "nx" => Kind::NoExecute,
+            _ => return Err(format!("unknown rule {}, expected ro or nx", kind)),
+        };
+        let (start, end) = match range.split_once('-') {
+            Some((start, end)) => (parse_hex(start), parse_hex(end)),
+            None => (parse_hex(range), parse_hex(range)),
+        };
+        let (start, end) = start.zip(end).filter(|(s, e)| s <= e).ok_or_else(|| format!("bad range {}", range))?;
+        let arm = match arm {
+            None => Arm::Always,
+            Some(address) if address.starts_with('$') => Arm::Address(parse_hex(address).ok_or_else(|| format!("bad address {}", address))?),
+            Some(frame) => Arm::Frame(frame.parse().map_err(|_| format!("bad frame {}", frame))?),
+        };
+        Ok(Rule {
+            kind,
+            start,
+            end,
+            arm,
+            armed: arm == Arm::Always,
+            violations: 0,
+        })
+    }
+
+    fn covers(&self, address: u16) -> bool {
+        (self.start..=self.end).contains(&address)
+    }
+
+    pub fn text(&self) -> String {
+        let kind = match self.kind {
+            Kind::ReadOnly => "ro",
+            Kind::NoExecute => "nx",
+        };
+        let arm = match self.arm {
+            Arm::Always => String::new(),
+            Arm::Frame(frame) => format!(" from {}", frame),
+            Arm::Address(address) => format!(" from ${:04X}", address),
+        };
+        let state = if self.armed { "" } else { " (not armed yet)" };
+        format!("{} ${:04X}-${:04X}{}{}, {} violations", kind, self.start, self.end, arm, state, self.violations)
+    }
+}
+
+impl Guards {
+    pub fn load(path: &str) -> io::Result<Self> {
+        let mut guards = Guards::default();
+        for (number, line) in fs::read_to_string(path)?.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let rule = Rule::parse(line).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", number + 1, e)))?;
+            guards.rules.push(rule);
+        }
+        Ok(guards)
+    }
+}
+
+// Stop in the debugger, or pause without one, and say what happened and what led up to it.
+fn report(emulator: &mut Emulator, what: String) {
+    println!("guard: {} (frame {} scanline {} dot {})", what, emulator.ppu.frame, emulator.ppu.scanline, emulator.ppu.dot);
+    let recent: Vec<&(u16, [u8; 3])> = emulator.history.iter().rev().take(REPORT_HISTORY).collect();
+    for (address, bytes) in recent.into_iter().rev() {
+        println!("  ${:04X}  {}", address, disassemble_named(*bytes, *address, &|a| emulator.ram_map.name(a)).0);
+    }
+    match emulator.debugger.as_mut() {
+        Some(debugger) => debugger.stepping = true,
+        None => emulator.pause(),
+    }
+}
+
+// A CPU write is about to land, it goes through either way.
+pub fn check_write(emulator: &mut Emulator, address: u16, value: u8) {
+    let Some(rule) = emulator.guards.rules.iter_mut().find(|r| r.kind == Kind::ReadOnly && r.armed && r.covers(address)) else {
+        return;
+    };
+    rule.violations += 1;
+    let range = format!("${:04X}-${:04X}", rule.start, rule.end);
+    let pc = emulator.history.back().map(|(pc, _)| *pc).unwrap_or(emulator.registers.program_counter);
+    let target = emulator.ram_map.describe(address);
+    report(emulator, format!("write of ${:02X} to {} in read-only {} by the instruction at ${:04X}", value, target, range, pc));
+}
+
+// Called before each instruction is fetched. False when it should not run yet.
+pub fn check_fetch(emulator: &mut Emulator, pc: u16) -> bool {
+    let frame = emulator.ppu.frame;
+    for rule in emulator.guards.rules.iter_mut() {
+        match rule.arm {
+            Arm::Frame(start) if frame >= start => rule.armed = true,
+            Arm::Address(address) if address == pc => rule.armed = true,
+            _ => {}
+        }
+    }
+    let allowed = emulator.guards.allowed_fetch.take() == Some(pc);
+    let broken = emulator.guards.rules.iter_mut().find(|r| r.kind == Kind::NoExecute && r.armed && r.covers(pc));
+    let stop = match broken {
+        Some(rule) if !allowed => {
+            rule.violations += 1;
+            let range = format!("${:04X}-${:04X}", rule.start, rule.end);
+            emulator.guards.allowed_fetch = Some(pc);
+            report(emulator, format!("executing ${:04X} in no-execute {}", pc, range));
+            true
+        }
+        _ => false,
+    };
+    !stop
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_ranges_single_addresses_and_arming() {
+        let rule = Rule::parse("ro $0300-$03FF").unwrap();
+        assert_eq!((rule.kind, rule.start, rule.end, rule.arm, rule.armed), (Kind::ReadOnly, 0x300, 0x3FF, Arm::Always, true));
+        let rule = Rule::parse("nx $0000 from 2").unwrap();
+        assert_eq!((rule.kind, rule.start, rule.end, rule.arm, rule.armed), (Kind::NoExecute, 0, 0, Arm::Frame(2), false));
+        let rule = Rule::parse("ro $0300-$03FF from $C123").unwrap();
+        assert_eq!(rule.arm, Arm::Address(0xC123));
+        assert_eq!(rule.text(), "ro $0300-$03FF from $C123 (not armed yet), 0 violations");
+    }
+
+    #[test]
+    fn rejects_bad_rules() {
+        for line in ["rw $0300", "ro", "ro $0400-$0300", "ro $zz", "ro $0300 from", "ro $0300 from soon", "ro $0300 after 2"] {
+            assert!(Rule::parse(line).is_err(), "{:?} accepted", line);
+        }
+    }
+}
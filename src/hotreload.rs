@@ -0,0 +1,153 @@
+use std::fs;
+use std::time::SystemTime;
+use crate::Emulator;
+
+// What survives when the watched ROM is rebuilt
+pub enum ReloadMode {
+    // start over from a clean machine
+    Fresh,
+    // keep internal RAM so the game picks up where it was
+    KeepRam,
+    // load this save state on top of the new ROM
+    State(String),
+}
+
+// Polls the ROM file's modification time, no file system events needed.
+pub struct RomWatch {
+    pub path: String,
+    pub mode: ReloadMode,
+    modified: Option<SystemTime>,
+}
+
+fn modified_time(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+impl RomWatch {
+    pub fn new(path: &str, mode: ReloadMode) -> Self {
+        RomWatch {
+            path: path.to_string(),
+            mode,
+            modified: modified_time(path),
+        }
+    }
+
+    // True once per change. Assemblers write in several steps, so a file
+    // that is missing or empty right now is left alone until the next poll.
+    fn changed(&mut self) -> bool {
+        let modified = modified_time(&self.path);
+        if modified.is_none() || modified == self.modified {
+            return false;
+        }
+        if fs::metadata(&self.path).map(|m| m.len() == 0).unwrap_or(true) {
+            return false;
+        }
+        self.modified = modified;
+        true
+    }
+}
+
+// Reload the ROM if it changed on disk since the last check.
+pub fn poll(emulator: &mut Emulator) {
+    let watch = match emulator.rom_watch.as_mut() {
+        Some(watch) => watch,
+        None => return,
+    };
+    if !watch.changed() {
+        return;
+    }
+    let path = watch.path.clone();
+    println!("{} changed, reloading", path);
+    let ram: Vec<u8> = emulator.memory[0x0000..0x0800].to_vec();
+    let state = match &watch.mode {
+        ReloadMode::State(state) => Some(state.clone()),
+        _ => None,
+    };
+    let keep_ram = matches!(watch.mode, ReloadMode::KeepRam);
+
+    // swapping the cartridge drops the old PRG so a smaller build leaves nothing stale,
+    // the watch and code/data log carry over since it is the same project
+    let rom_watch = emulator.rom_watch.take();
+    let cdl = emulator.cdl.take();
+    let result = emulator.insert_cartridge(&path);
+    emulator.rom_watch = rom_watch;
+    emulator.cdl = cdl;
+    if let Err(e) = result {
+        println!("Failed to reload {}: {}", path, e);
+        return;
+    }
+    if keep_ram {
+        emulator.memory[0x0000..0x0800].copy_from_slice(&ram);
+    }
+    if let Some(state) = state {
+        if let Err(e) = emulator.load_state(&state) {
+            println!("Failed to reapply state {}: {}", state, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::time::Duration;
+
+    fn touch(path: &std::path::Path, seconds: u64) {
+        let file = File::options().write(true).open(path).unwrap();
+        file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds)).unwrap();
+    }
+
+    fn rom(spin_x: u8) -> Vec<u8> {
+        let mut rom = b"NES\x1A\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+        rom.extend([0xA2, spin_x, 0xD0, 0xFE]);
+        rom.resize(16 + 0x4000, 0);
+        rom
+    }
+
+    #[test]
+    fn reports_each_change_once_and_skips_empty_files() {
+        let path = std::env::temp_dir().join(format!("rnes_hotreload_{}.nes", std::process::id()));
+        fs::write(&path, b"first").unwrap();
+        touch(&path, 1_000_000);
+        let mut watch = RomWatch::new(path.to_str().unwrap(), ReloadMode::Fresh);
+        assert!(!watch.changed());
+
+        touch(&path, 1_000_010);
+        assert!(watch.changed());
+        assert!(!watch.changed());
+
+        // the assembler truncated the file and has not written it yet
+        fs::write(&path, b"").unwrap();
+        touch(&path, 1_000_020);
+        assert!(!watch.changed());
+        fs::write(&path, b"second").unwrap();
+        touch(&path, 1_000_020);
+        assert!(watch.changed());
+
+        fs::remove_file(&path).unwrap();
+        assert!(!watch.changed());
+    }
+
+    #[test]
+    fn keep_ram_reloads_the_rom_under_the_running_game() {
+        let path = std::env::temp_dir().join(format!("rnes_hotreload_keep_{}.nes", std::process::id()));
+        fs::write(&path, rom(0x01)).unwrap();
+        touch(&path, 1_000_000);
+        let mut emulator = Emulator::new();
+        emulator.verbose = false;
+        emulator.insert_cartridge(path.to_str().unwrap()).unwrap();
+        emulator.rom_watch = Some(RomWatch::new(path.to_str().unwrap(), ReloadMode::KeepRam));
+        emulator.memory[0x0042] = 0x99;
+
+        poll(&mut emulator);
+        assert_eq!(emulator.memory[0x8011], 0x01);
+
+        fs::write(&path, rom(0x02)).unwrap();
+        touch(&path, 1_000_010);
+        poll(&mut emulator);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(emulator.memory[0x8011], 0x02);
+        assert_eq!(emulator.memory[0x0042], 0x99);
+        assert!(emulator.rom_watch.is_some());
+    }
+}
@@ -0,0 +1,251 @@
+use crate::snapshot::json_string;
+
+// Everything the header and a hash can tell about a ROM file.
+pub struct RomInfo {
+    pub nes2: bool,
+    pub prg_rom: usize,
+    pub chr_rom: usize,
+    pub mapper: u16,
+    pub submapper: Option<u8>,
+    pub mirroring: &'static str,
+    pub battery: bool,
+    pub trainer: bool,
+    pub console: &'static str,
+    pub region: &'static str,
+    // of everything after the header, which is how ROM databases key dumps
+    pub crc32: u32,
+    pub sha1: [u8; 20],
+}
+
+pub fn mapper_name(mapper: u16) -> Option<&'static str> {
+    let name = match mapper {
+        0 => "NROM",
+        1 => "MMC1",
+        2 => "UxROM",
+        3 => "CNROM",
+        4 => "MMC3",
+        5 => "MMC5",
+        7 => "AxROM",
+        9 => "MMC2",
+        10 => "MMC4",
+        11 => "Color Dreams",
+        19 => "Namco 163",
+        21 | 23 | 25 => "VRC2/VRC4",
+        22 => "VRC2",
+        24 | 26 => "VRC6",
+        34 => "BNROM/NINA-001",
+        66 => "GxROM",
+        69 => "Sunsoft FME-7",
+        71 => "Camerica",
+        85 => "VRC7",
+        99 => "VS. System",
+        206 => "Namco 108",
+        _ => return None,
+    };
+    Some(name)
+}
+
+// NES 2.0 sizes are either a plain count with a high nibble from byte 9, or
+// when that nibble is all ones an exponent and multiplier packed into the low byte.
+fn nes2_size(low: u8, high_nibble: u8, unit: usize) -> usize {
+    if high_nibble == 0x0F {
+        let exponent = (low >> 2) as u32;
+        let multiplier = (low & 0x03) as usize * 2 + 1;
+        return 1usize.checked_shl(exponent).unwrap_or(0) * multiplier;
+    }
+    ((high_nibble as usize) << 8 | low as usize) * unit
+}
+
+pub fn parse(rom: &[u8]) -> Option<RomInfo> {
+    if rom.len() < 16 || &rom[0..4] != b"NES\x1A" {
+        return None;
+    }
+    let nes2 = rom[7] & 0x0C == 0x08;
+    let mut mapper = ((rom[6] >> 4) | (rom[7] & 0xF0)) as u16;
+    let (prg_rom, chr_rom) = if nes2 {
+        mapper |= ((rom[8] & 0x0F) as u16) << 8;
+        (nes2_size(rom[4], rom[9] & 0x0F, 16384), nes2_size(rom[5], rom[9] >> 4, 8192))
+    } else {
+        (rom[4] as usize * 16384, rom[5] as usize * 8192)
+    };
+    let region = if nes2 {
+        match rom[12] & 0x03 {
+            0 => "NTSC",
+            1 => "PAL",
+            2 => "multi-region",
+            _ => "Dendy",
+        }
+    } else if rom[9] & 0x01 != 0 {
+        "PAL"
+    } else {
+        "NTSC"
+    };
+    let body = &rom[16..];
+    Some(RomInfo {
+        nes2,
+        prg_rom,
+        chr_rom,
+        mapper,
+        submapper: if nes2 { Some(rom[8] >> 4) } else { None },
+        mirroring: if rom[6] & 0x08 != 0 {
+            "four-screen"
+        } else if rom[6] & 0x01 != 0 {
+            "vertical"
+        } else {
+            "horizontal"
+        },
+        battery: rom[6] & 0x02 != 0,
+        trainer: rom[6] & 0x04 != 0,
+        console: match rom[7] & 0x03 {
+            1 => "VS. System",
+            2 => "PlayChoice-10",
+            3 if nes2 => "extended",
+            _ => "NES/Famicom",
+        },
+        region,
+        crc32: crc32(body),
+        sha1: sha1(body),
+    })
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB88320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+    for block in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for i in 0..16 {
+            w[i] = u32::from_be_bytes([block[i * 4], block[i * 4 + 1], block[i * 4 + 2], block[i * 4 + 3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (state, value) in h.iter_mut().zip([a, b, c, d, e]) {
+            *state = state.wrapping_add(value);
+        }
+    }
+    let mut digest = [0u8; 20];
+    for (i, word) in h.iter().enumerate() {
+        digest[i * 4..i * 4 + 4].copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+impl RomInfo {
+    fn sha1_hex(&self) -> String {
+        self.sha1.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    pub fn to_text(&self) -> String {
+        let mapper = match (mapper_name(self.mapper), self.submapper) {
+            (Some(name), Some(sub)) => format!("{} ({}), submapper {}", self.mapper, name, sub),
+            (Some(name), None) => format!("{} ({})", self.mapper, name),
+            (None, Some(sub)) => format!("{}, submapper {}", self.mapper, sub),
+            (None, None) => format!("{}", self.mapper),
+        };
+        format!(
+            "format:    {}\nmapper:    {}\nPRG-ROM:   {} KB\nCHR-ROM:   {} KB\nmirroring: {}\nbattery:   {}\ntrainer:   {}\nconsole:   {}\nregion:    {}\ncrc32:     {:08x}\nsha1:      {}\ndatabase:  no match (no ROM database bundled)\n",
+            if self.nes2 { "NES 2.0" } else { "iNES" },
+            mapper,
+            self.prg_rom / 1024,
+            self.chr_rom / 1024,
+            self.mirroring,
+            if self.battery { "yes" } else { "no" },
+            if self.trainer { "yes" } else { "no" },
+            self.console,
+            self.region,
+            self.crc32,
+            self.sha1_hex()
+        )
+    }
+
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{\"format\":{},\"mapper\":{},\"submapper\":{},\"mapper_name\":{},\"prg_rom\":{},\"chr_rom\":{},\
+\"mirroring\":{},\"battery\":{},\"trainer\":{},\"console\":{},\"region\":{},\"crc32\":\"{:08x}\",\"sha1\":\"{}\",\"database\":null}}",
+            json_string(if self.nes2 { "NES 2.0" } else { "iNES" }),
+            self.mapper,
+            self.submapper.map(|s| s.to_string()).unwrap_or("null".to_string()),
+            mapper_name(self.mapper).map(json_string).unwrap_or("null".to_string()),
+            self.prg_rom,
+            self.chr_rom,
+            json_string(self.mirroring),
+            self.battery,
+            self.trainer,
+            json_string(self.console),
+            json_string(self.region),
+            self.crc32,
+            self.sha1_hex()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn crc32_matches_the_check_value() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+    }
+
+    #[test]
+    fn sha1_matches_the_fips_vectors() {
+        assert_eq!(hex(&sha1(b"")), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        assert_eq!(hex(&sha1(b"abc")), "a9993e364706816aba3e25717850c26c9cd0d89d");
+        // 56 bytes, the length no longer fits in the first block
+        assert_eq!(hex(&sha1(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq")), "84983e441c3bd26ebaae4aa1f95129e5e54670f1");
+    }
+
+    #[test]
+    fn parses_ines_and_nes2_headers() {
+        let mut rom = vec![b'N', b'E', b'S', 0x1A, 2, 1, 0x41, 0x00, 0, 0x01, 0, 0, 0, 0, 0, 0];
+        rom.extend(b"123456789");
+        let info = parse(&rom).unwrap();
+        assert_eq!((info.nes2, info.prg_rom, info.chr_rom, info.mapper), (false, 32768, 8192, 4));
+        assert_eq!((info.mirroring, info.region, info.crc32), ("vertical", "PAL", 0xCBF43926));
+        // NES 2.0: mapper high bits and submapper in byte 8, exponent-multiplier PRG size
+        rom[7] = 0x08;
+        rom[8] = 0x31;
+        rom[9] = 0x0F;
+        rom[4] = (10 << 2) | 1;
+        let info = parse(&rom).unwrap();
+        assert_eq!((info.nes2, info.mapper, info.submapper, info.prg_rom), (true, 0x104, Some(3), 3 * 1024));
+        assert!(parse(b"NES\x1A").is_none());
+    }
+}
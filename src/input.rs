@@ -0,0 +1,305 @@
+use std::collections::HashMap;
+
+// Standard controller buttons in the order the shift register reports them
+pub const BUTTON_A: u8 = 0x01;
+pub const BUTTON_B: u8 = 0x02;
+pub const BUTTON_SELECT: u8 = 0x04;
+pub const BUTTON_START: u8 = 0x08;
+pub const BUTTON_UP: u8 = 0x10;
+pub const BUTTON_DOWN: u8 = 0x20;
+pub const BUTTON_LEFT: u8 = 0x40;
+pub const BUTTON_RIGHT: u8 = 0x80;
+
+// Autofire for one button, pressed for on_frames then released for off_frames.
+#[derive(Clone, Copy)]
+pub struct Turbo {
+    pub on_frames: u8,
+    pub off_frames: u8,
+}
+
+// A recorded button sequence, each step holds its buttons for a number of frames.
+#[derive(Clone, Default)]
+pub struct Macro {
+    pub steps: Vec<(u8, u16)>,
+}
+
+impl Macro {
+    // buttons pressed on a given frame of the macro
+    fn buttons_at(&self, frame: u32) -> Option<u8> {
+        let mut start = 0;
+        for (buttons, frames) in &self.steps {
+            if frame < start + *frames as u32 {
+                return Some(*buttons);
+            }
+            start += *frames as u32;
+        }
+        None
+    }
+}
+
+#[derive(Default)]
+pub struct Controller {
+    // buttons the frontend is holding down
+    held: u8,
+    // what the console sees this frame after turbo and macros
+    output: u8,
+    turbo: [Option<Turbo>; 8],
+    macros: HashMap<u8, Macro>,
+    playing: Option<(u8, u32)>,
+    recording: Option<(u8, Macro)>,
+    frame: u32,
+    strobe: bool,
+    shift: u8,
+    // Famicom second controller only, it is not part of the shift register
+    microphone: bool,
+    // buttons netplay agreed on for this frame, seen in place of the frontend's
+    forced: Option<u8>,
+}
+
+impl Controller {
+    pub fn press(&mut self, buttons: u8) {
+        self.held |= buttons;
+        self.update_output();
+    }
+
+    pub fn release(&mut self, buttons: u8) {
+        self.held &= !buttons;
+        self.update_output();
+    }
+
+    pub fn set_turbo(&mut self, button: u8, turbo: Option<Turbo>) {
+        let bit = button.trailing_zeros() as usize;
+        if bit < 8 {
+            self.turbo[bit] = turbo;
+        }
+    }
+
+    pub fn bind_macro(&mut self, slot: u8, recorded: Macro) {
+        self.macros.insert(slot, recorded);
+    }
+
+    pub fn start_recording(&mut self, slot: u8) {
+        self.recording = Some((slot, Macro::default()));
+    }
+
+    // Stop recording and bind what was captured to its slot.
+    pub fn stop_recording(&mut self) {
+        if let Some((slot, recorded)) = self.recording.take() {
+            self.macros.insert(slot, recorded);
+        }
+    }
+
+    // False when nothing is bound to the slot.
+    pub fn play_macro(&mut self, slot: u8) -> bool {
+        if !self.macros.contains_key(&slot) {
+            return false;
+        }
+        self.playing = Some((slot, 0));
+        self.update_output();
+        true
+    }
+
+    fn update_output(&mut self) {
+        let mut buttons = self.held;
+        for bit in 0..8 {
+            if let Some(turbo) = self.turbo[bit] {
+                let period = turbo.on_frames as u32 + turbo.off_frames as u32;
+                if period > 0 && self.frame % period >= turbo.on_frames as u32 {
+                    buttons &= !(1 << bit);
+                }
+            }
+        }
+        if let Some((slot, frame)) = self.playing {
+            match self.macros.get(&slot).and_then(|m| m.buttons_at(frame)) {
+                Some(macro_buttons) => buttons |= macro_buttons,
+                None => self.playing = None,
+            }
+        }
+        self.output = self.forced.unwrap_or(buttons);
+    }
+
+    // Netplay sets what both machines press, None hands the pad back to the frontend.
+    pub fn force(&mut self, buttons: Option<u8>) {
+        self.forced = buttons;
+        self.update_output();
+    }
+
+    // Called once per video frame to advance turbo phases, macro playback and recording.
+    pub fn end_frame(&mut self) {
+        if let Some((_, recorded)) = self.recording.as_mut() {
+            match recorded.steps.last_mut() {
+                Some((buttons, frames)) if *buttons == self.held && *frames < u16::MAX => *frames += 1,
+                _ => recorded.steps.push((self.held, 1)),
+            }
+        }
+        self.frame = self.frame.wrapping_add(1);
+        if let Some((_, frame)) = self.playing.as_mut() {
+            *frame += 1;
+        }
+        self.update_output();
+    }
+
+    // $4016 write, bit 0 high keeps reloading the shift register
+    pub fn write(&mut self, value: u8) {
+        self.strobe = value & 1 != 0;
+        if self.strobe {
+            self.shift = self.output;
+        }
+    }
+
+    // Blowing into or shouting at the microphone, held until turned off.
+    pub fn set_microphone(&mut self, on: bool) {
+        self.microphone = on;
+    }
+
+    pub fn microphone(&self) -> bool {
+        self.microphone
+    }
+
+    // buttons the console sees this frame
+    pub fn buttons(&self) -> u8 {
+        self.output
+    }
+
+    // $4016/$4017 read, one button per read then 1s once all 8 are out
+    pub fn read(&mut self) -> u8 {
+        if self.strobe {
+            return (self.output & 1) | 0x40;
+        }
+        let bit = self.shift & 1;
+        self.shift = (self.shift >> 1) | 0x80;
+        bit | 0x40
+    }
+}
+
+// Input display text, pressed buttons by letter and released ones as dots.
+pub fn button_text(buttons: u8) -> String {
+    let names = [
+        (BUTTON_UP, 'U'), (BUTTON_DOWN, 'D'), (BUTTON_LEFT, 'L'), (BUTTON_RIGHT, 'R'),
+        (BUTTON_SELECT, 's'), (BUTTON_START, 'S'), (BUTTON_B, 'B'), (BUTTON_A, 'A'),
+    ];
+    names.iter().map(|(button, name)| if buttons & button != 0 { *name } else { '.' }).collect()
+}
+
+// Buttons by letter as button_text shows them (UDLRsSBA, '.' is ignored), or a
+// number like 9 or $09.
+pub fn parse_buttons(word: &str) -> Option<u8> {
+    if let Some(hex) = word.strip_prefix('$') {
+        return u8::from_str_radix(hex, 16).ok();
+    }
+    if let Ok(value) = word.parse::<u8>() {
+        return Some(value);
+    }
+    word.chars().try_fold(0u8, |buttons, name| {
+        let button = match name {
+            'U' => BUTTON_UP,
+            'D' => BUTTON_DOWN,
+            'L' => BUTTON_LEFT,
+            'R' => BUTTON_RIGHT,
+            's' => BUTTON_SELECT,
+            'S' => BUTTON_START,
+            'B' => BUTTON_B,
+            'A' => BUTTON_A,
+            '.' => 0,
+            _ => return None,
+        };
+        Some(buttons | button)
+    })
+}
+
+// Counts lag frames, frames where the game never read the controllers.
+// Games that poll input every frame regardless can name an address instead,
+// usually past the end of the NMI handler's game logic, and a frame only
+// counts as not lagged once that address executes.
+#[derive(Default)]
+pub struct LagCounter {
+    pub frames: u64,
+    pub last_frame_lagged: bool,
+    pub end_point: Option<u16>,
+    active: bool,
+}
+
+impl LagCounter {
+    pub fn controller_read(&mut self) {
+        if self.end_point.is_none() {
+            self.active = true;
+        }
+    }
+
+    pub fn executed(&mut self, address: u16) {
+        if self.end_point == Some(address) {
+            self.active = true;
+        }
+    }
+
+    pub fn end_frame(&mut self) {
+        self.last_frame_lagged = !self.active;
+        if self.last_frame_lagged {
+            self.frames += 1;
+        }
+        self.active = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn turbo_follows_its_duty_cycle() {
+        let mut controller = Controller::default();
+        controller.set_turbo(BUTTON_A, Some(Turbo { on_frames: 2, off_frames: 1 }));
+        controller.press(BUTTON_A | BUTTON_B);
+        let mut seen = Vec::new();
+        for _ in 0..6 {
+            seen.push(controller.buttons());
+            controller.end_frame();
+        }
+        let ab = BUTTON_A | BUTTON_B;
+        assert_eq!(seen, vec![ab, ab, BUTTON_B, ab, ab, BUTTON_B]);
+        controller.set_turbo(BUTTON_A, None);
+        controller.end_frame();
+        assert_eq!(controller.buttons(), ab);
+    }
+
+    #[test]
+    fn recorded_macro_plays_back_frame_for_frame() {
+        let mut controller = Controller::default();
+        controller.start_recording(1);
+        controller.press(BUTTON_A);
+        controller.end_frame();
+        controller.end_frame();
+        controller.release(BUTTON_A);
+        controller.press(BUTTON_B);
+        controller.end_frame();
+        controller.stop_recording();
+        controller.release(0xFF);
+
+        assert!(!controller.play_macro(2));
+        assert!(controller.play_macro(1));
+        let mut seen = Vec::new();
+        for _ in 0..4 {
+            seen.push(controller.buttons());
+            controller.end_frame();
+        }
+        assert_eq!(seen, vec![BUTTON_A, BUTTON_A, BUTTON_B, 0]);
+    }
+
+    #[test]
+    fn shift_register_reports_buttons_then_ones() {
+        let mut controller = Controller::default();
+        controller.press(BUTTON_A | BUTTON_START);
+        controller.write(1);
+        controller.write(0);
+        let bits: Vec<u8> = (0..10).map(|_| controller.read() & 1).collect();
+        assert_eq!(bits, vec![1, 0, 0, 1, 0, 0, 0, 0, 1, 1]);
+    }
+
+    #[test]
+    fn parses_button_letters_and_numbers() {
+        assert_eq!(parse_buttons("UA"), Some(BUTTON_UP | BUTTON_A));
+        assert_eq!(parse_buttons("...S"), Some(BUTTON_START));
+        assert_eq!(parse_buttons("$09"), Some(9));
+        assert_eq!(parse_buttons("x"), None);
+    }
+}
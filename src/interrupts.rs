@@ -0,0 +1,231 @@
+use std::collections::VecDeque;
+use crate::snapshot::json_string;
+
+// frames of interrupts kept for the debugger and the JSON dump
+const HISTORY_FRAMES: u64 = 120;
+// a BRK loop would otherwise fill memory
+const MAX_EVENTS: usize = 4096;
+
+// Nothing drives the IRQ line yet, there is no APU frame counter or mapper
+// IRQ, so hardware IRQs get a source once something can raise them.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Source {
+    Nmi,
+    // software interrupt through the IRQ vector
+    Brk,
+}
+
+impl Source {
+    fn name(&self) -> &'static str {
+        match self {
+            Source::Nmi => "NMI",
+            Source::Brk => "BRK",
+        }
+    }
+}
+
+// Where the machine was when something happened.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Position {
+    pub frame: u64,
+    pub scanline: u16,
+    pub dot: u16,
+    pub cycle: u64,
+}
+
+impl Position {
+    fn json(&self) -> String {
+        format!(
+            "{{\"frame\":{},\"scanline\":{},\"dot\":{},\"cycle\":{}}}",
+            self.frame, self.scanline, self.dot, self.cycle
+        )
+    }
+}
+
+pub enum Outcome {
+    // not taken yet, the CPU finishes its current instruction first
+    Pending,
+    // PC at the interrupted instruction and the handler address from the vector
+    Serviced { at: Position, pc: u16, handler: u16 },
+    // the line went back down before the CPU took it, a $2002 read right after vblank does this
+    Dropped { at: Position },
+}
+
+pub struct Event {
+    pub source: Source,
+    pub asserted: Position,
+    pub outcome: Outcome,
+}
+
+impl Event {
+    pub fn text(&self) -> String {
+        let a = &self.asserted;
+        let start = format!("{} asserted scanline {:3} dot {:3}", self.source.name(), a.scanline, a.dot);
+        match self.outcome {
+            Outcome::Pending => format!("{}, pending", start),
+            Outcome::Serviced { at, pc, handler } => format!(
+                "{}, taken scanline {:3} dot {:3} after {} cycles at ${:04X} -> ${:04X}",
+                start,
+                at.scanline,
+                at.dot,
+                at.cycle - a.cycle,
+                pc,
+                handler
+            ),
+            Outcome::Dropped { at } => format!("{}, dropped scanline {:3} dot {:3}", start, at.scanline, at.dot),
+        }
+    }
+
+    fn json(&self) -> String {
+        let outcome = match self.outcome {
+            Outcome::Pending => "\"outcome\":\"pending\"".to_string(),
+            Outcome::Serviced { at, pc, handler } => format!(
+                "\"outcome\":\"serviced\",\"serviced\":{},\"latency_cycles\":{},\"pc\":{},\"handler\":{}",
+                at.json(),
+                at.cycle - self.asserted.cycle,
+                pc,
+                handler
+            ),
+            Outcome::Dropped { at } => format!("\"outcome\":\"dropped\",\"dropped\":{}", at.json()),
+        };
+        format!("{{\"source\":{},\"asserted\":{},{}}}", json_string(self.source.name()), self.asserted.json(), outcome)
+    }
+}
+
+// Interrupts of the last few frames, oldest first.
+#[derive(Default)]
+pub struct Timeline {
+    pub events: VecDeque<Event>,
+    // the NMI line as last seen, so a rise is recorded once
+    nmi_line: bool,
+}
+
+impl Timeline {
+    fn push(&mut self, event: Event) {
+        let oldest = event.asserted.frame.saturating_sub(HISTORY_FRAMES);
+        while self.events.len() >= MAX_EVENTS || self.events.front().is_some_and(|e| e.asserted.frame < oldest) {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+
+    fn pending(&mut self, source: Source) -> Option<&mut Event> {
+        self.events.iter_mut().rev().find(|e| e.source == source && matches!(e.outcome, Outcome::Pending))
+    }
+
+    // Called with the PPU's NMI output whenever it may have changed.
+    pub fn observe_nmi(&mut self, pending: bool, at: Position) {
+        if pending && !self.nmi_line {
+            self.push(Event { source: Source::Nmi, asserted: at, outcome: Outcome::Pending });
+        } else if !pending && self.nmi_line {
+            if let Some(event) = self.pending(Source::Nmi) {
+                event.outcome = Outcome::Dropped { at };
+            }
+        }
+        self.nmi_line = pending;
+    }
+
+    // The CPU pushed PC and jumped through a vector. A BRK is recorded as
+    // asserted and taken at once.
+    pub fn serviced(&mut self, source: Source, at: Position, pc: u16, handler: u16) {
+        if source == Source::Nmi {
+            self.nmi_line = false;
+        }
+        let outcome = Outcome::Serviced { at, pc, handler };
+        let pending = if source == Source::Brk { None } else { self.pending(source) };
+        match pending {
+            Some(event) => event.outcome = outcome,
+            None => self.push(Event { source, asserted: at, outcome }),
+        }
+    }
+
+    // Events asserted in the last frames frames before current.
+    pub fn recent(&self, current: u64, frames: u64) -> impl Iterator<Item = &Event> {
+        let first = current.saturating_sub(frames.saturating_sub(1));
+        self.events.iter().filter(move |e| e.asserted.frame >= first)
+    }
+
+    pub fn to_json(&self) -> String {
+        let events: Vec<String> = self.events.iter().map(|e| e.json()).collect();
+        format!("[{}]", events.join(","))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(frame: u64, scanline: u16, dot: u16, cycle: u64) -> Position {
+        Position { frame, scanline, dot, cycle }
+    }
+
+    #[test]
+    fn an_nmi_goes_from_pending_to_serviced() {
+        let mut timeline = Timeline::default();
+        timeline.observe_nmi(true, at(1, 241, 1, 1000));
+        // still high, not a second rise
+        timeline.observe_nmi(true, at(1, 241, 4, 1001));
+        assert_eq!(timeline.events.len(), 1);
+        assert_eq!(timeline.events[0].text(), "NMI asserted scanline 241 dot   1, pending");
+
+        timeline.serviced(Source::Nmi, at(1, 241, 22, 1007), 0x8123, 0xC000);
+        assert_eq!(timeline.events.len(), 1);
+        assert_eq!(
+            timeline.events[0].text(),
+            "NMI asserted scanline 241 dot   1, taken scanline 241 dot  22 after 7 cycles at $8123 -> $C000"
+        );
+        assert_eq!(
+            timeline.to_json(),
+            "[{\"source\":\"NMI\",\"asserted\":{\"frame\":1,\"scanline\":241,\"dot\":1,\"cycle\":1000},\
+\"outcome\":\"serviced\",\"serviced\":{\"frame\":1,\"scanline\":241,\"dot\":22,\"cycle\":1007},\
+\"latency_cycles\":7,\"pc\":33059,\"handler\":49152}]"
+        );
+    }
+
+    #[test]
+    fn an_nmi_the_line_drops_before_the_cpu_takes_is_dropped() {
+        let mut timeline = Timeline::default();
+        timeline.observe_nmi(true, at(2, 241, 1, 500));
+        timeline.observe_nmi(false, at(2, 241, 3, 501));
+        assert!(matches!(timeline.events[0].outcome, Outcome::Dropped { at } if at.dot == 3));
+        assert_eq!(
+            timeline.to_json(),
+            "[{\"source\":\"NMI\",\"asserted\":{\"frame\":2,\"scanline\":241,\"dot\":1,\"cycle\":500},\
+\"outcome\":\"dropped\",\"dropped\":{\"frame\":2,\"scanline\":241,\"dot\":3,\"cycle\":501}}]"
+        );
+        // the next rise is a new event
+        timeline.observe_nmi(true, at(3, 241, 1, 30000));
+        assert_eq!(timeline.events.len(), 2);
+        assert!(matches!(timeline.events[1].outcome, Outcome::Pending));
+        assert_eq!(timeline.to_json().matches("\"outcome\":\"pending\"").count(), 1);
+    }
+
+    #[test]
+    fn brk_is_asserted_and_taken_at_once() {
+        let mut timeline = Timeline::default();
+        timeline.observe_nmi(true, at(1, 241, 1, 10));
+        timeline.serviced(Source::Brk, at(1, 241, 5, 12), 0x8000, 0x9000);
+        assert_eq!(timeline.events.len(), 2);
+        assert!(matches!(timeline.events[0].outcome, Outcome::Pending));
+        assert_eq!(timeline.events[1].source, Source::Brk);
+        assert_eq!(timeline.events[1].asserted, at(1, 241, 5, 12));
+    }
+
+    #[test]
+    fn old_frames_and_runaway_counts_are_evicted() {
+        let mut timeline = Timeline::default();
+        for frame in 0..HISTORY_FRAMES + 10 {
+            timeline.serviced(Source::Brk, at(frame, 0, 0, frame), 0, 0);
+        }
+        assert_eq!(timeline.events.len() as u64, HISTORY_FRAMES + 1);
+        assert_eq!(timeline.events.front().unwrap().asserted.frame, 9);
+        assert_eq!(timeline.recent(HISTORY_FRAMES + 9, 2).count(), 2);
+
+        let mut timeline = Timeline::default();
+        for cycle in 0..MAX_EVENTS as u64 + 5 {
+            timeline.serviced(Source::Brk, at(0, 0, 0, cycle), 0, 0);
+        }
+        assert_eq!(timeline.events.len(), MAX_EVENTS);
+        assert_eq!(timeline.events.front().unwrap().asserted.cycle, 5);
+    }
+}
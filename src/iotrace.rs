@@ -0,0 +1,168 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use crate::debugger::parse_hex;
+
+const REGISTER_NAMES: [(u16, &str); 8] = [
+    (0x2000, "PPUCTRL"),
+    (0x2001, "PPUMASK"),
+    (0x2002, "PPUSTATUS"),
+    (0x2003, "OAMADDR"),
+    (0x2004, "OAMDATA"),
+    (0x2005, "PPUSCROLL"),
+    (0x2006, "PPUADDR"),
+    (0x2007, "PPUDATA"),
+];
+
+fn apu_name(address: u16) -> Option<&'static str> {
+    let name = match address {
+        0x4000..=0x4003 => "SQ1",
+        0x4004..=0x4007 => "SQ2",
+        0x4008..=0x400B => "TRI",
+        0x400C..=0x400F => "NOISE",
+        0x4010..=0x4013 => "DMC",
+        0x4014 => "OAMDMA",
+        0x4015 => "SND_CHN",
+        0x4016 => "JOY1",
+        0x4017 => "JOY2",
+        _ => return None,
+    };
+    Some(name)
+}
+
+// The PPU registers repeat every 8 bytes up to $3FFF.
+fn register_address(address: u16) -> Option<u16> {
+    match address {
+        0x2000..=0x3FFF => Some(0x2000 + (address & 7)),
+        0x4000..=0x4017 => Some(address),
+        _ => None,
+    }
+}
+
+fn register_name(register: u16) -> &'static str {
+    match REGISTER_NAMES.iter().find(|(address, _)| *address == register) {
+        Some((_, name)) => name,
+        None => apu_name(register).unwrap_or("?"),
+    }
+}
+
+// Accepts a register name (ppustatus) or an address ($2002).
+fn parse_register(text: &str) -> Option<u16> {
+    let upper = text.to_ascii_uppercase();
+    if let Some((address, _)) = REGISTER_NAMES.iter().find(|(_, name)| *name == upper) {
+        return Some(*address);
+    }
+    if let Some(address) = (0x4000..=0x4017).find(|a| apu_name(*a) == Some(upper.as_str())) {
+        return Some(address);
+    }
+    parse_hex(text).and_then(register_address)
+}
+
+pub struct Access {
+    pub cycle: u64,
+    pub scanline: u16,
+    pub dot: u16,
+    pub pc: u16,
+    pub address: u16,
+    pub value: u8,
+    pub write: bool,
+}
+
+// Log of every CPU access to the PPU and APU/IO registers.
+pub struct IoTrace {
+    out: BufWriter<File>,
+    // only these registers are logged when set
+    filter: Option<Vec<u16>>,
+}
+
+impl IoTrace {
+    // filter is a comma separated list of names or addresses, e.g. "ppustatus,$2007,joy1"
+    pub fn create(path: &str, filter: Option<&str>) -> io::Result<Self> {
+        let filter = match filter {
+            Some(list) => {
+                let mut registers = Vec::new();
+                for item in list.split(',').map(|i| i.trim()).filter(|i| !i.is_empty()) {
+                    match parse_register(item) {
+                        Some(register) => registers.push(register),
+                        None => return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("unknown register {}", item))),
+                    }
+                }
+                Some(registers)
+            }
+            None => None,
+        };
+        Ok(IoTrace {
+            out: BufWriter::new(File::create(path)?),
+            filter,
+        })
+    }
+
+    pub fn record(&mut self, access: Access) {
+        let Some(register) = register_address(access.address) else {
+            return;
+        };
+        if self.filter.as_ref().is_some_and(|f| !f.contains(&register)) {
+            return;
+        }
+        // tracing is a debugging aid, a failed write should not stop emulation
+        let _ = writeln!(
+            self.out,
+            "{:>10} {:3}:{:3} ${:04X} {} ${:04X} {:<9} ${:02X}",
+            access.cycle,
+            access.scanline,
+            access.dot,
+            access.pc,
+            if access.write { "W" } else { "R" },
+            access.address,
+            register_name(register),
+            access.value
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn access(address: u16, value: u8, write: bool) -> Access {
+        Access { cycle: 1234, scanline: 241, dot: 5, pc: 0x8010, address, value, write }
+    }
+
+    #[test]
+    fn names_registers_through_their_mirrors() {
+        assert_eq!(register_address(0x3FFA).map(register_name), Some("PPUSTATUS"));
+        assert_eq!(register_address(0x2007).map(register_name), Some("PPUDATA"));
+        assert_eq!(register_address(0x4002).map(register_name), Some("SQ1"));
+        assert_eq!(register_address(0x4014).map(register_name), Some("OAMDMA"));
+        assert_eq!(register_address(0x4017).map(register_name), Some("JOY2"));
+        assert_eq!(register_address(0x4018), None);
+        assert_eq!(register_address(0x1FFF), None);
+    }
+
+    #[test]
+    fn parses_names_and_addresses() {
+        assert_eq!(parse_register("ppustatus"), Some(0x2002));
+        assert_eq!(parse_register("Joy1"), Some(0x4016));
+        // APU names pick the first register of the channel
+        assert_eq!(parse_register("noise"), Some(0x400C));
+        assert_eq!(parse_register("$2007"), Some(0x2007));
+        assert_eq!(parse_register("$200F"), Some(0x2007));
+        assert_eq!(parse_register("$0300"), None);
+        assert_eq!(parse_register("vram"), None);
+    }
+
+    #[test]
+    fn logs_only_the_filtered_registers() {
+        let path = std::env::temp_dir().join(format!("rnes_iotrace_{}.log", std::process::id()));
+        assert!(IoTrace::create(path.to_str().unwrap(), Some("ppustatus,vram")).is_err());
+        let mut trace = IoTrace::create(path.to_str().unwrap(), Some("ppustatus, $4016,")).unwrap();
+        trace.record(access(0x200A, 0x80, false));
+        trace.record(access(0x2007, 0x12, true));
+        trace.record(access(0x4016, 0x01, true));
+        trace.record(access(0x0300, 0xFF, true));
+        drop(trace);
+        let log = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(log, "      1234 241:  5 $8010 R $200A PPUSTATUS $80\n      1234 241:  5 $8010 W $4016 JOY1      $01\n");
+    }
+}
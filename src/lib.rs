@@ -0,0 +1,2540 @@
+
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::num::Wrapping;
+use std::ops::{Add, Shl,Sub};
+use std::string::ToString;
+use crate::Mode::*;
+use crate::Operation::*;
+use crate::cdl::CodeDataLog;
+use crate::debugger::Debugger;
+use crate::expansion::ExpansionDevice;
+use crate::hotreload::{ReloadMode, RomWatch};
+use crate::input::{Controller, LagCounter};
+use crate::iotrace::{Access, IoTrace};
+use crate::netplay::Netplay;
+use crate::pacing::Pacer;
+use crate::palette::Region;
+use crate::ppu::{Mirroring, Ppu};
+use crate::practice::Practice;
+use crate::profiler::Profiler;
+use crate::ram_map::RamMap;
+use crate::savestate::SaveStateError;
+use crate::automation::Automation;
+use crate::session::{Recorder, Session, SessionHeader};
+use crate::snapshot::MachineState;
+use crate::trace::Trace;
+use crate::vs::VsSystem;
+use crate::guard::Guards;
+use crate::interrupts::{Position, Source, Timeline};
+use crate::trigger::Triggers;
+use crate::watch::Watches;
+use lazy_static::lazy_static;
+
+// Per-instruction CPU chatter, headless runs turn it off
+macro_rules! cpu_log {
+    ($emulator:expr, $($arg:tt)*) => {
+        if $emulator.verbose {
+            println!($($arg)*);
+        }
+    };
+}
+
+mod asm_export;
+mod audit;
+mod automation;
+mod batch;
+mod cdl;
+mod debugger;
+mod disasm;
+mod expansion;
+pub mod ffi;
+mod guard;
+mod hotreload;
+mod info;
+mod input;
+mod interrupts;
+mod iotrace;
+mod netplay;
+mod pacing;
+mod palette;
+mod ppu;
+mod practice;
+mod profiler;
+mod ram_map;
+mod savestate;
+mod session;
+#[cfg(feature = "singlestep")]
+mod singlestep;
+mod snapshot;
+mod state_diff;
+mod trace;
+mod trigger;
+#[cfg(feature = "tui")]
+mod tui;
+mod vs;
+mod watch;
+
+/* Memory Layout for NES
+    0x0
+    -- SYSTEM RAM ZERO PAGE
+    0x800
+    --- RAM MIRRORS
+    0x2000
+    -- PPU PORTS
+    0x4000
+    -- APU PORTS IO REGISTERS
+    0x4020
+    -- CARTRIDGE WRAM
+    0x8000
+    -- PRG-ROM
+    0xFFFA
+    --- Vectors
+    0xFFFF
+*/
+
+// LOOK UP TABLE FOR OPCODES
+lazy_static! {static ref INSTRUCTION_TABLE:HashMap<u8,Instruction> = HashMap::from([
+        //////////////////////////////////
+        // FLAG INSTRUCTIONS
+        // RTI
+        (0x40,Instruction{address_mode:Implied,operation:RTI,cycles:6}),
+        //SEI
+        (0x78,Instruction{address_mode:Implied,operation:SEI,cycles:2}),
+        // CLD
+        (0xD8,Instruction{address_mode:Implied,operation:CLD,cycles:2}),
+        // BRK
+        (0x00,Instruction{address_mode:Implied,operation:BRK,cycles:7}),
+        /////////////////////////////////
+        // Load X Register
+        (0xA2,Instruction{address_mode:Immediate,operation:LDX,cycles:2}),
+        // Load A Register
+        (0xA9,Instruction{address_mode:Immediate,operation:LDA,cycles:2}),
+        // Store Accumulator
+        (0x95,Instruction{address_mode:ZeroPageX,operation:STA,cycles:4}),
+        ///////////////////////////
+        /// Register Instructions
+        /// Decrement X
+        (0xCA,Instruction{address_mode:Implied,operation:DEX,cycles:2}),
+        // INCREMENT X
+        (0xE8,Instruction{address_mode:Implied,operation:INX,cycles:2}),
+
+        ///////////////////////////////////
+        // Stack Instructions
+        // Transfer X to Stack Ptr
+        (0x9A,Instruction{address_mode:Implied,operation:TXS,cycles:2}),
+        /////////////// BRANCH INSTRUCTIONS
+        // BNE
+        (0xD0,Instruction{address_mode:Relative,operation:BNE,cycles:2}),
+
+
+        // Add With Carry
+        (0x69,Instruction{address_mode:Immediate,operation:ADC,cycles:2}),
+        (0x65,Instruction{address_mode:ZeroPage,operation:ADC,cycles:3}),
+        (0x75,Instruction{address_mode:ZeroPageX,operation:ADC,cycles:4}),
+        (0x6D,Instruction{address_mode:Absolute,operation:ADC,cycles:4}),
+        (0x7D,Instruction{address_mode:AbsoluteX,operation:ADC,cycles:4}),
+        (0x79,Instruction{address_mode:AbsoluteY,operation:ADC,cycles:4}),
+        (0x61,Instruction{address_mode:IndirectX,operation:ADC,cycles:6}),
+        (0x71,Instruction{address_mode:IndirectY,operation:ADC,cycles:5}),
+        // AND
+    ]);
+}
+
+
+fn get_flag(flags:u8,which_bit:u8) -> u8 {
+    return flags & (1 << which_bit);
+}
+fn set_bit(original_u8:u8,bit_to_set:u8) -> u8 {
+    assert!(bit_to_set < 8);
+    let mask = 1 << bit_to_set;
+    return original_u8 | mask;
+}
+fn unset_bit(original_u8:u8,bit_to_unset:u8) -> u8 {
+    assert!(bit_to_unset < 8);
+    let mask = !(1 << bit_to_unset);
+    return original_u8 & mask;
+}
+#[derive(Hash, Eq, PartialEq, Debug)]
+enum Mode {
+    Null,
+    Implied,
+    Accumulator,
+    Immediate,
+    ZeroPage,
+    ZeroPageX,
+    ZeroPageY,
+    Absolute,
+    AbsoluteIndirect,
+    AbsoluteX,
+    AbsoluteY,
+    IndirectX,
+    IndirectY,
+    Relative,
+}
+#[derive(Hash, Eq, PartialEq, Debug)]
+enum Operation {
+    ADC,	AND,	ASL,	BCC,	BCS,	BEQ,	BIT,	BMI,	BNE,	BPL,	BRK,	BVC,	BVS,	CLC,
+    CLD,	CLI,	CLV,	CMP,	CPX,	CPY,	DEC,	DEX,	DEY,	EOR,	INC,	INX,	INY,	JMP,
+    JSR,	LDA,	LDX,	LDY,	LSR,	NOP,	ORA,	PHA,	PHP,	PLA,	PLP,	ROL,	ROR,	RTI,
+    RTS,	SBC,	SEC,	SED,	SEI,	STA,	STX,	STY,	TAX,	TAY,	TSX,	TXA,	TXS,	TYA,
+}
+
+#[derive(Hash, Eq, PartialEq, Debug)]
+struct Instruction {
+    address_mode: Mode,
+    operation: Operation,
+    cycles: u8,
+}
+
+// Why the machine stopped for good
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum HaltReason {
+    // one of the undocumented JAM/KIL opcodes locked the CPU up
+    Jam{opcode:u8,address:u16},
+    // an opcode the CPU core does not implement yet, under UnknownOpcodePolicy::Error
+    UnknownOpcode{opcode:u8,address:u16},
+    // the debugger or frontend asked to stop
+    Quit,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum RunState {
+    Running,
+    Paused,
+    Halted(HaltReason),
+}
+
+// These lock the 6502 up until the reset line is pulled
+const JAM_OPCODES:[u8;12] = [0x02,0x12,0x22,0x32,0x42,0x52,0x62,0x72,0x92,0xB2,0xD2,0xF2];
+
+// What to do with an opcode the instruction table does not cover yet
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum UnknownOpcodePolicy {
+    // skip it as a NOP of the right length so the rest of the rom can be looked at
+    Nop,
+    // stop on it with PC still pointing at it, in the debugger when there is one
+    Break,
+    // halt the machine for good
+    Error,
+}
+
+impl UnknownOpcodePolicy {
+    fn from_name(name:&str) -> Option<Self> {
+        match name {
+            "nop" => Some(UnknownOpcodePolicy::Nop),
+            "break" => Some(UnknownOpcodePolicy::Break),
+            "error" => Some(UnknownOpcodePolicy::Error),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            UnknownOpcodePolicy::Nop => "nop",
+            UnknownOpcodePolicy::Break => "break",
+            UnknownOpcodePolicy::Error => "error",
+        }
+    }
+}
+
+// What internal RAM holds after a power cycle, real consoles vary so games should not care
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum PowerOnPattern {
+    Zeros,
+    Ones,
+    // four bytes of 0x00 then four of 0xFF, common on front loaders
+    Alternating,
+}
+
+impl PowerOnPattern {
+    fn from_name(name:&str) -> Option<Self> {
+        match name {
+            "zeros" => Some(PowerOnPattern::Zeros),
+            "ones" => Some(PowerOnPattern::Ones),
+            "alternating" => Some(PowerOnPattern::Alternating),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            PowerOnPattern::Zeros => "zeros",
+            PowerOnPattern::Ones => "ones",
+            PowerOnPattern::Alternating => "alternating",
+        }
+    }
+
+    fn byte_at(&self, address:usize) -> u8 {
+        match self {
+            PowerOnPattern::Zeros => 0x00,
+            PowerOnPattern::Ones => 0xFF,
+            PowerOnPattern::Alternating => if address & 0x4 == 0 { 0x00 } else { 0xFF },
+        }
+    }
+}
+
+struct Registers {
+    a_reg: u8,
+    y_reg: u8,
+    x_reg: u8,
+    stack_pointer: u8,
+    program_counter:u16,
+    cpu_flags:u8, // carry 0, zero 1, irq 2 decimal 3, break 4, unused 5, overflow 6, negative 7
+
+}
+struct Emulator {
+    registers: Registers,
+    memory:[u8;65536],
+    fetched_data:u8,
+    address_absolute:u16,
+    address_relative:u16,
+    opcode:u8,
+    cycles:u8,
+    current_mode:Mode,
+    profiler:Option<Profiler>,
+    cdl:Option<CodeDataLog>,
+    controllers:[Controller;2],
+    // expansion port peripheral, nothing plugged in by default
+    expansion:Option<Box<dyn ExpansionDevice>>,
+    total_cycles:u64,
+    debugger:Option<Debugger>,
+    // address and bytes of the last few executed instructions
+    history:VecDeque<(u16,[u8;3])>,
+    power_on_pattern:PowerOnPattern,
+    unknown_opcode:UnknownOpcodePolicy,
+    rom_watch:Option<RomWatch>,
+    // CRC32 of the loaded ROM, recorded in save states
+    rom_crc32:Option<u32>,
+    ppu:Ppu,
+    // named memory values shown in the debugger view
+    watches:Watches,
+    // names for RAM addresses, shown instead of the address
+    ram_map:RamMap,
+    // memory conditions that print a notification when they become true
+    triggers:Triggers,
+    practice:Practice,
+    lag:LagCounter,
+    // when interrupts were raised and taken over the last few frames
+    interrupts:Timeline,
+    // address ranges that must not be written or executed
+    guards:Guards,
+    // input and state hash log being written or replayed
+    session:Option<Session>,
+    io_trace:Option<IoTrace>,
+    // nestest style line per instruction
+    trace:Option<Trace>,
+    // set for VS. System arcade dumps
+    vs:Option<VsSystem>,
+    // per-frame input read from and frame lines written to external scripts
+    automation:Option<Automation>,
+    // lockstep input exchange with another machine running the same ROM
+    netplay:Option<Netplay>,
+    // run at the console's frame rate instead of as fast as possible
+    pacer:Option<Pacer>,
+    run_state:RunState,
+    // set when the PPU wraps to a new frame, run_frame() watches it
+    frame_complete:bool,
+    // CPU cycles an OAM DMA still holds the bus for
+    dma_cycles:u16,
+    // print the machine state and decode chatter for every instruction
+    verbose:bool,
+    // every read and write in order, the bus is plain RAM while this is set
+    #[cfg(feature = "singlestep")]
+    bus_log:Option<Vec<singlestep::BusAccess>>,
+}
+
+impl Emulator {
+    fn new() -> Self {
+        let reg = Registers {
+            a_reg: 0,
+            y_reg: 0,
+            x_reg:0,
+            stack_pointer: 0,
+            program_counter:0,
+            cpu_flags:0,
+        };
+
+        let mem:[u8;65536] = [0;65536];
+
+        return Emulator {
+            registers:reg,
+            memory:mem,
+            current_mode:Null,
+            fetched_data:0,
+            address_absolute:0,
+            address_relative:0,
+            opcode:0,
+            cycles:0,
+            profiler:None,
+            cdl:None,
+            controllers:[Controller::default(),Controller::default()],
+            expansion:None,
+            total_cycles:0,
+            debugger:None,
+            history:VecDeque::with_capacity(snapshot::HISTORY_LENGTH),
+            power_on_pattern:PowerOnPattern::Zeros,
+            unknown_opcode:UnknownOpcodePolicy::Error,
+            rom_watch:None,
+            rom_crc32:None,
+            ppu:Ppu::new(),
+            watches:Watches::default(),
+            ram_map:RamMap::default(),
+            triggers:Triggers::default(),
+            practice:Practice::new(),
+            lag:LagCounter::default(),
+            interrupts:Timeline::default(),
+            guards:Guards::default(),
+            session:None,
+            io_trace:None,
+            trace:None,
+            vs:None,
+            automation:None,
+            netplay:None,
+            pacer:None,
+            run_state:RunState::Running,
+            frame_complete:false,
+            dma_cycles:0,
+            verbose:true,
+            #[cfg(feature = "singlestep")]
+            bus_log:None,
+        };
+    }
+    fn load_rom(&mut self, rom_path:&str) -> std::io::Result<()> {
+        // Load ROM Into Memory.
+        let rom_bytes = fs::read(rom_path)?;
+        self.load_rom_bytes(&rom_bytes);
+        Ok(())
+    }
+
+    fn load_rom_bytes(&mut self, rom_bytes:&[u8]){
+        // TODO READ 16 BYTE HEADER HERE ETC.
+        // Load ROM INTO 0x8000 CATRIDGE WRAM
+        for i in 0..rom_bytes.len() {
+            self.memory[0x8000 + i] = rom_bytes[i];
+            // stop at 32kb
+            // stop if reaching end of PRG ROM SECTION
+            if i + 0x8000 == 0xFFFA {
+                break;
+            }
+            if i == 32768 {
+                break;
+            }
+        }
+        // header byte 6 bit 0 picks the nametable wiring, CHR-ROM follows PRG (and the trainer)
+        if rom_bytes.len() >= 16 && &rom_bytes[0..4] == b"NES\x1A" {
+            self.ppu.mirroring = if rom_bytes[6] & 0x01 != 0 { Mirroring::Vertical } else { Mirroring::Horizontal };
+            // byte 9 bit 0 marks PAL carts
+            self.ppu.region = if rom_bytes[9] & 0x01 != 0 { Region::Pal } else { Region::Ntsc };
+            let trainer = if rom_bytes[6] & 0x04 != 0 { 512 } else { 0 };
+            let chr_start = 16 + trainer + rom_bytes[4] as usize * 16384;
+            let chr_length = (rom_bytes[5] as usize * 8192).min(self.ppu.chr.len());
+            if chr_start + chr_length <= rom_bytes.len() {
+                self.ppu.chr[..chr_length].copy_from_slice(&rom_bytes[chr_start..chr_start + chr_length]);
+            }
+        }
+        self.vs = VsSystem::from_header(rom_bytes);
+        self.rom_crc32 = info::parse(rom_bytes).map(|i| i.crc32);
+        if VsSystem::is_playchoice(rom_bytes) {
+            println!("PlayChoice-10 dump, running it as a regular NES game");
+        }
+        // skip the 16 byte header
+        self.registers.program_counter = 0x8000 + 0x10;
+    }
+
+    // Pull the cartridge: its address space reads back empty and the machine powers down.
+    // CHR, ROM or RAM, is on the cartridge too. The code/data log and file watch
+    // belong to the old game so they go with it.
+    fn eject(&mut self){
+        for byte in self.memory[0x4020..].iter_mut() {
+            *byte = 0;
+        }
+        self.ppu.chr = [0; 0x2000];
+        self.cdl = None;
+        self.rom_watch = None;
+        self.rom_crc32 = None;
+        self.power_cycle();
+    }
+
+    // Swap in another game at runtime. The file is read first so a bad path keeps the current game.
+    fn insert_cartridge(&mut self, rom_path:&str) -> std::io::Result<()> {
+        netplay::refuse_jump(self, "swap cartridges")?;
+        let rom_bytes = fs::read(rom_path)?;
+        self.eject();
+        self.load_rom_bytes(&rom_bytes);
+        Ok(())
+    }
+
+    fn save_state(&self, path:&str) -> Result<(), SaveStateError> {
+        fs::write(path, savestate::encode_with_metadata(self))?;
+        Ok(())
+    }
+
+    fn load_state(&mut self, path:&str) -> Result<(), SaveStateError> {
+        netplay::refuse_jump(self, "load a state")?;
+        let data = fs::read(path)?;
+        let saved_from = savestate::metadata(&data).and_then(|m| m.rom_crc32);
+        if saved_from.is_some() && self.rom_crc32.is_some() && saved_from != self.rom_crc32 {
+            println!("{} was saved from a different ROM, loading it anyway", path);
+        }
+        savestate::decode_into(self, &data)
+    }
+
+    fn snapshot(&self) -> MachineState {
+        snapshot::snapshot(self)
+    }
+    fn read_address(&mut self,address:usize) -> u16 {
+        // lo
+        // hi
+        // result = (hi << 8) | lo;
+        let idx = address as usize;
+        let address_high = self.memory[idx ];
+        let address_low = self.memory[idx + 1];
+        self.registers.program_counter += 1;
+        let addr = ((address_high as u16) << 8) | address_low as u16;
+        return addr;
+    }
+
+    fn read_byte(&mut self, address:usize) -> u8 {
+        #[cfg(feature = "singlestep")]
+        if let Some(log) = self.bus_log.as_mut() {
+            log.push(singlestep::BusAccess{address:address as u16,value:self.memory[address],write:false});
+            return self.memory[address];
+        }
+        if let Some(cdl) = self.cdl.as_mut() {
+            cdl.mark_data(address as u16);
+        }
+        let value = match address {
+            0x2000..=0x3FFF => {
+                // PPUDATA reads the address in v, pattern table reads count as CHR data
+                if let (7, Some(cdl)) = (address & 7, self.cdl.as_mut()) {
+                    if self.ppu.v & 0x3FFF < 0x2000 {
+                        cdl.mark_chr_read(self.ppu.v & 0x3FFF);
+                    }
+                }
+                let value = self.ppu.read_register(address as u16);
+                // 2C05 protection: PPUSTATUS low bits hold the chip's ID instead of open bus
+                match self.vs.as_ref().and_then(|vs| vs.ppu.status_id()) {
+                    Some(id) if address & 7 == 2 => (value & 0xE0) | id,
+                    _ => value,
+                }
+            }
+            0x4016 => {
+                self.lag.controller_read();
+                let value = match self.vs.as_ref() {
+                    Some(vs) => (self.controllers[0].read() & 0x01) | vs.read_4016(),
+                    None => self.controllers[0].read(),
+                };
+                // the Famicom's second controller has a microphone, read live on bit 2
+                let microphone = if self.controllers[1].microphone() { 0x04 } else { 0 };
+                value | microphone | self.expansion.as_mut().map_or(0, |device| device.read(0))
+            }
+            0x4017 => {
+                self.lag.controller_read();
+                let value = match self.vs.as_ref() {
+                    Some(vs) => (self.controllers[1].read() & 0x01) | vs.read_4017(),
+                    None => self.controllers[1].read(),
+                };
+                value | self.expansion.as_mut().map_or(0, |device| device.read(1))
+            }
+            _ => self.memory[address],
+        };
+        if (0x2000..=0x4017).contains(&address) {
+            self.trace_io(address,value,false);
+        }
+        return value;
+    }
+
+    fn trace_io(&mut self, address:usize, value:u8, write:bool) {
+        if let Some(trace) = self.io_trace.as_mut() {
+            trace.record(Access{
+                cycle:self.total_cycles,
+                scanline:self.ppu.scanline,
+                dot:self.ppu.dot,
+                // the instruction doing the access, PC has already moved past it
+                pc:self.history.back().map(|(pc,_)| *pc).unwrap_or(self.registers.program_counter),
+                address:address as u16,
+                value,
+                write,
+            });
+        }
+    }
+
+    fn write_byte(&mut self, address:usize,value:u8) -> bool {
+        if !self.guards.rules.is_empty() {
+            guard::check_write(self,address as u16,value);
+        }
+        if (0x2000..=0x4017).contains(&address) {
+            self.trace_io(address,value,true);
+        }
+        #[cfg(feature = "singlestep")]
+        if let Some(log) = self.bus_log.as_mut() {
+            log.push(singlestep::BusAccess{address:address as u16,value,write:true});
+            self.memory[address] = value;
+            return true;
+        }
+        match address {
+            0x2000..=0x3FFF => {
+                let swap = self.vs.as_ref().is_some_and(|vs| vs.ppu.swaps_ctrl_and_mask());
+                let register = if swap && address & 6 == 0 { address ^ 1 } else { address };
+                self.ppu.write_register(register as u16,value);
+                return true;
+            }
+            // one strobe line latches both controllers
+            0x4016 => {
+                self.controllers[0].write(value);
+                self.controllers[1].write(value);
+                if let Some(device) = self.expansion.as_mut() {
+                    device.write(value);
+                }
+                if let Some(chr) = self.vs.as_mut().and_then(|vs| vs.write_4016(value)) {
+                    self.ppu.chr.copy_from_slice(chr);
+                }
+            }
+            // 256 bytes from page value into OAM, 513 cycles or 514 starting on an odd one
+            0x4014 => {
+                let start = (value as usize) << 8;
+                self.ppu.oam_dma(&self.memory[start..start + 0x100]);
+                self.dma_cycles += 513 + (self.total_cycles % 2) as u16;
+            }
+            _ => {}
+        }
+        self.memory[address] = value;
+        return true;
+    }
+
+    // The stack lives in page one, the pointer wraps within it like the real 6502
+    fn push_u8(&mut self, value:u8) {
+        self.write_byte(0x0100 + self.registers.stack_pointer as usize,value);
+        self.registers.stack_pointer = self.registers.stack_pointer.wrapping_sub(1);
+    }
+
+    fn pull_u8(&mut self) -> u8 {
+        self.registers.stack_pointer = self.registers.stack_pointer.wrapping_add(1);
+        self.read_byte(0x0100 + self.registers.stack_pointer as usize)
+    }
+
+    // high byte goes first so the low byte ends up on top
+    fn push_u16(&mut self, value:u16) {
+        self.push_u8((value >> 8) as u8);
+        self.push_u8((value & 0x00FF) as u8);
+    }
+
+    fn pull_u16(&mut self) -> u16 {
+        let lo = self.pull_u8() as u16;
+        let hi = self.pull_u8() as u16;
+        (hi << 8) | lo
+    }
+
+    fn nmi(&mut self){
+        self.push_u16(self.registers.program_counter);
+        self.registers.cpu_flags = set_bit(self.registers.cpu_flags,4);
+        self.registers.cpu_flags = set_bit(self.registers.cpu_flags,5);
+        self.registers.cpu_flags = set_bit(self.registers.cpu_flags,2);
+        self.push_u8(self.registers.cpu_flags);
+        self.address_absolute = 0xFFFA;
+        let lo:u16 = self.read_byte((self.address_absolute + 0) as usize) as u16;
+        let hi:u16 = self.read_byte((self.address_absolute + 1) as usize) as u16;
+        self.registers.program_counter = (hi << 8) | lo;
+        self.cycles = 8;
+    }
+
+    fn irq(&mut self){
+        if get_flag(self.registers.cpu_flags,2) == 0 {
+            self.push_u16(self.registers.program_counter);
+            self.registers.cpu_flags = set_bit(self.registers.cpu_flags,4);
+            self.registers.cpu_flags = set_bit(self.registers.cpu_flags,5);
+            self.registers.cpu_flags = set_bit(self.registers.cpu_flags,2);
+            self.push_u8(self.registers.cpu_flags);
+            self.address_absolute = 0xFFFE;
+            let lo:u16 = self.read_byte((self.address_absolute + 0) as usize) as u16;
+            let hi:u16 = self.read_byte((self.address_absolute + 1) as usize) as u16;
+            self.registers.program_counter = (hi << 8) | lo;
+            self.cycles = 7;
+        }
+    }
+
+    fn reset(&mut self){
+        self.registers.a_reg = 0;
+        self.registers.x_reg = 0;
+        self.registers.y_reg = 0;
+        self.registers.stack_pointer = 0xFD;
+        // I set, and bits 4-5 that only show up when P is pushed
+        self.registers.cpu_flags = 0x34;
+        self.address_absolute = 0xFFFC;
+        let lo:u16 = self.read_byte((self.address_absolute + 0) as usize) as u16;
+        let hi:u16 = self.read_byte((self.address_absolute + 1) as usize) as u16;
+        self.registers.program_counter = (hi << 8) | lo;
+        self.address_relative = 0x0000;
+        self.address_absolute = 0x0000;
+        self.fetched_data = 0x00;
+        self.cycles = 8;
+    }
+
+    // Reset button: RAM and A/X/Y survive, the CPU skips three stack pushes and sets I.
+    fn soft_reset(&mut self){
+        self.registers.stack_pointer = self.registers.stack_pointer.wrapping_sub(3);
+        self.registers.cpu_flags = set_bit(self.registers.cpu_flags,2);
+        let lo:u16 = self.read_byte(0xFFFC) as u16;
+        let hi:u16 = self.read_byte(0xFFFD) as u16;
+        self.registers.program_counter = (hi << 8) | lo;
+        self.address_relative = 0x0000;
+        self.address_absolute = 0x0000;
+        self.fetched_data = 0x00;
+        self.cycles = 7;
+        self.ppu.reset();
+        self.resume();
+    }
+
+    // Power switch: internal RAM comes back in the configured pattern then a full reset.
+    fn power_cycle(&mut self){
+        for address in 0x0000..0x0800 {
+            self.memory[address] = self.power_on_pattern.byte_at(address);
+        }
+        self.total_cycles = 0;
+        self.dma_cycles = 0;
+        self.history.clear();
+        self.ppu.power_on();
+        self.reset();
+        self.resume();
+    }
+
+    fn start(&mut self){
+        while self.run_frame() {}
+    }
+
+    // Run until the PPU finishes the current frame. Returns false if the machine
+    // paused or halted first.
+    fn run_frame(&mut self) -> bool {
+        self.frame_complete = false;
+        while !self.frame_complete {
+            if self.run_state != RunState::Running {
+                return false;
+            }
+            self.clock();
+        }
+        true
+    }
+
+    // Returns how many frames actually ran.
+    fn run_frames(&mut self, frames:u64) -> u64 {
+        for done in 0..frames {
+            if !self.run_frame() {
+                return done;
+            }
+        }
+        frames
+    }
+
+    // Nothing runs while paused. There is no audio yet, once there is it gets muted here.
+    fn pause(&mut self) {
+        if self.run_state == RunState::Running {
+            self.run_state = RunState::Paused;
+        }
+    }
+
+    // Also how reset and power clear a JAM. A quit is final.
+    fn resume(&mut self) {
+        if self.run_state != RunState::Halted(HaltReason::Quit) {
+            self.run_state = RunState::Running;
+        }
+    }
+
+    fn halt(&mut self, reason:HaltReason) {
+        self.run_state = RunState::Halted(reason);
+    }
+
+    fn print_state(&self) {
+        println!("----- Dump -------");
+        println!("PC 0x{:X}",self.registers.program_counter);
+        println!("SP 0x{:X}",self.registers.stack_pointer as u16 + 0x0100);
+        // top of the stack, most recent push first
+        print!("Stack:");
+        let top = self.registers.stack_pointer as usize + 1;
+        for address in (0x0100 + top..0x0200).take(8) {
+            print!(" {:02X}",self.memory[address]);
+        }
+        println!();
+        println!("A {:X}",self.registers.a_reg);
+        println!("X {:X}",self.registers.x_reg);
+        println!("Y {:X}",self.registers.y_reg);
+        println!("flags: {:#010b}", self.registers.cpu_flags);
+        println!("Relative Address: {:X}",self.address_relative);
+        println!("Absolute Address: {:X}",self.address_absolute);
+        println!("Current Opcode: {:X}",self.opcode);
+        // memory is inspected with the debugger's hex view (m <addr> [len])
+    }
+    fn clock(&mut self){
+        // OAM DMA keeps the CPU off the bus while the PPU runs on
+        if self.cycles == 0 && self.dma_cycles > 0 {
+            self.dma_cycles -= 1;
+            self.cycles = 1;
+        }
+        // the prompt comes before the fetch so edits, resets and power cycles apply to this instruction
+        if self.cycles == 0 {
+            debugger::check_breakpoint(self);
+            state_diff::advance(self,false);
+        }
+        if self.cycles == 0 && self.debugger.as_ref().is_some_and(|d| d.stepping) {
+            debugger::prompt(self);
+            if self.run_state != RunState::Running {
+                return;
+            }
+        }
+        if self.cycles == 0 && self.ppu.nmi_pending {
+            self.ppu.nmi_pending = false;
+            let pc = self.registers.program_counter;
+            self.nmi();
+            self.interrupts.serviced(Source::Nmi,self.position(),pc,self.registers.program_counter);
+        }
+        if self.cycles == 0 {
+            let pc = self.registers.program_counter;
+            if !self.guards.rules.is_empty() && !guard::check_fetch(self,pc) {
+                return;
+            }
+            self.opcode = self.memory[pc as usize];
+            if JAM_OPCODES.contains(&self.opcode) {
+                self.halt(HaltReason::Jam{opcode:self.opcode,address:pc});
+                return;
+            }
+            self.lag.executed(pc);
+            if let Some(cdl) = self.cdl.as_mut() {
+                cdl.begin_instruction(pc,disasm::instruction_length(self.opcode));
+            }
+            if self.history.len() == snapshot::HISTORY_LENGTH {
+                self.history.pop_front();
+            }
+            let bytes = [self.memory[pc as usize],self.memory[pc.wrapping_add(1) as usize],self.memory[pc.wrapping_add(2) as usize]];
+            self.history.push_back((pc,bytes));
+            if let Some(mut trace) = self.trace.take() {
+                trace.write(&trace::line(self));
+                self.trace = Some(trace);
+            }
+            if self.verbose {
+                self.print_state();
+            }
+            if !self.execute_instruction() && !self.unknown_opcode(pc) {
+                return;
+            }
+            debugger::apply_freezes(self);
+            if let Some(profiler) = self.profiler.as_mut() {
+                profiler.record(pc, self.opcode, self.cycles);
+            }
+        }
+        self.cycles -= 1;
+        let dots = self.ppu.region.dots_in_cycle(self.total_cycles);
+        self.total_cycles += 1;
+        for _ in 0..dots {
+            // the dot being processed, the PPU has moved past it once step returns
+            let at = self.position();
+            if self.ppu.step() {
+                self.end_frame();
+            }
+            self.interrupts.observe_nmi(self.ppu.nmi_pending,at);
+        }
+    }
+
+    fn position(&self) -> Position {
+        Position {
+            frame:self.ppu.frame,
+            scanline:self.ppu.scanline,
+            dot:self.ppu.dot,
+            cycle:self.total_cycles,
+        }
+    }
+
+    fn end_frame(&mut self){
+        self.frame_complete = true;
+        self.lag.end_frame();
+        if let Some(vs) = self.vs.as_mut() {
+            vs.end_frame();
+        }
+        // before the session so a recording picks up the scripted input and
+        // the next frame's turbo and macro buttons
+        automation::end_frame(self);
+        for controller in self.controllers.iter_mut() {
+            controller.end_frame();
+        }
+        // the session records what the two players agreed on
+        netplay::end_frame(self);
+        session::end_frame(self);
+        #[cfg(feature = "tui")]
+        tui::end_frame(self);
+        if let Some(device) = self.expansion.as_mut() {
+            device.end_frame();
+        }
+        if self.watches.log_changes {
+            for change in self.watches.end_frame(&self.memory,self.ppu.frame) {
+                println!("{}",change);
+            }
+        }
+        trigger::end_frame(self);
+        state_diff::advance(self,true);
+        hotreload::poll(self);
+        if let Some(pacer) = self.pacer.as_mut() {
+            pacer.end_frame(self.ppu.region);
+        }
+    }
+    fn fetch(&mut self) -> u8 {
+        match self.current_mode {
+            Implied => {
+                return self.read_byte(self.address_absolute as usize);
+            }
+            Immediate => {
+                return self.read_byte(self.address_absolute as usize);
+            }
+            _ => {
+                unreachable!("Unknown Addressing State");
+            }
+        }
+    }
+    /*
+    ADDRESSING MODES PUT VALUE INTO FETCHED AND INCREMENT THE PROGRAM COUNTER
+    */
+    fn implied_mode(&mut self) -> u8{
+        self.fetched_data = self.registers.a_reg;
+        return 0;
+    }
+    fn accumulator_mode(&mut self) -> u8{
+        self.fetched_data = 0;
+        return 0;
+    }
+    fn immediate_mode(&mut self) -> u8 {
+        cpu_log!(self, "immediate");
+        // Increment Program Counter So We Can read
+        self.registers.program_counter += 1;
+        // set target absolute address to program counter;
+        self.address_absolute = self.registers.program_counter;
+        return 0;
+    }
+
+    fn indirect_mode(&mut self) -> u8 {
+        // Increment Program Counter
+        self.registers.program_counter += 1;
+        let low = self.read_byte(self.registers.program_counter as usize) as u16;
+        self.registers.program_counter += 1;
+        let high = self.read_byte(self.registers.program_counter as usize) as u16;
+        // set absolute address
+        let ptr = (high << 8) | low;
+        // Emulating that processor bug
+        if low == 0x00FF {
+            let read1:u16 = self.read_byte((ptr & 0xFF00) as usize) as u16;
+            let read2:u16 = self.read_byte((ptr + 0) as usize) as u16;
+            self.address_absolute = (read1 << 8 ) | read2;
+        } else {
+            let read1:u16 = self.read_byte((ptr + 1) as usize) as u16;
+            let read2:u16 = self.read_byte((ptr + 0) as usize) as u16;
+            self.address_absolute = (read1 << 8 ) | read2;
+        }
+        return 0;
+    }
+
+    fn indirect_mode_page_zero_x(&mut self) -> u8 {
+        // Increment Program Counter
+        self.registers.program_counter += 1;
+        let low = self.read_byte(self.registers.program_counter as usize) as u16;
+        self.registers.program_counter += 1;
+        let high = self.read_byte(self.registers.program_counter as usize) as u16;
+        // set absolute address
+        let ptr = (high << 8) | low;
+        let lo:u16 = (self.read_byte((ptr + self.registers.x_reg as u16) as usize) & 0x00FF) as u16;
+        let hi:u16 = (self.read_byte((ptr + (self.registers.x_reg + 1) as u16) as usize) & 0x00FF) as u16;
+        self.address_absolute = (hi << 8) | lo;
+        return 0;
+    }
+
+    fn indirect_mode_page_zero_y(&mut self) -> u8 {
+        // Increment Program Counter
+        self.registers.program_counter += 1;
+        let low = self.read_byte(self.registers.program_counter as usize) as u16;
+        self.registers.program_counter += 1;
+        let high = self.read_byte(self.registers.program_counter as usize) as u16;
+        // set absolute address
+        let ptr = (high << 8) | low;
+        let lo = self.read_byte((ptr & 0x00FF) as usize) as u16;
+        let hi = self.read_byte(((ptr+1) & 0x00FF) as usize) as u16;
+        self.address_absolute = (hi << 8 )| lo;
+        if (self.address_absolute & 0xFF00) != (high << 8){
+            return 1;
+        }
+        return 0;
+    }
+
+    fn absolute_mode(&mut self) -> u8 {
+        // Increment Program Counter
+        self.registers.program_counter += 1;
+        let low = self.read_byte(self.registers.program_counter as usize) as u16;
+        self.registers.program_counter += 1;
+        let high = self.read_byte(self.registers.program_counter as usize) as u16;
+        // set absolute address
+        self.address_absolute = (high << 8) | low;
+        return 0;
+    }
+
+    fn absolute_mode_x(&mut self) -> u8 {
+        // Increment Program Counter
+        self.registers.program_counter += 1;
+        let low = self.read_byte(self.registers.program_counter as usize) as u16;
+        self.registers.program_counter += 1;
+        let high = self.read_byte(self.registers.program_counter as usize) as u16;
+        // set absolute address
+        self.address_absolute = (high << 8) | low;
+        self.address_absolute += self.registers.x_reg as u16;
+        // Check if we moved to another page if we did return 1 and add to clock cycles.
+        if (self.address_absolute & 0xFF00) != (high << 8){
+            return 1;
+        }
+        return 0;
+    }
+
+    fn absolute_mode_y(&mut self) -> u8 {
+        // Increment Program Counter
+        self.registers.program_counter += 1;
+        let low = self.read_byte(self.registers.program_counter as usize) as u16;
+        self.registers.program_counter += 1;
+        let high = self.read_byte(self.registers.program_counter as usize) as u16;
+        // set absolute address
+        self.address_absolute = (high << 8) | low;
+        self.address_absolute += self.registers.y_reg as u16;
+        // Check if we moved to another page if we did return 1 and add to clock cycles.
+        if (self.address_absolute & 0xFF00) != (high << 8){
+            return 1;
+        }
+        return 0;
+    }
+
+    fn zero_page_mode(&mut self) -> u8 {
+        //0xFF55 ff is page 55 is offset.
+        // Increment pc so we can read the next byte
+        self.registers.program_counter += 1;
+        let val = self.read_byte(self.registers.program_counter as usize);
+        // set absolute address
+        self.address_absolute = (val & 0x00FF) as u16;
+        return 0;
+    }
+
+    fn zero_page_x_mode(&mut self) -> u8 {
+        //0xFF55 ff is page 55 is offset.
+        // Increment pc so we can read the next byte
+        self.registers.program_counter += 1;
+        let val = self.read_byte(self.registers.program_counter as usize) + self.registers.x_reg;
+        // set absolute address
+        self.address_absolute = (val & 0x00FF) as u16;
+        return 0;
+    }
+
+    fn zero_page_y_mode(&mut self) -> u8 {
+        //0xFF55 ff is page 55 is offset.
+        // Increment pc so we can read the next byte
+        self.registers.program_counter += 1;
+        let val = self.read_byte(self.registers.program_counter as usize) + self.registers.y_reg;
+        // set absolute address
+        self.address_absolute = (val & 0x00FF) as u16;
+        return 0;
+    }
+
+    fn relative_mode(&mut self) -> u8 {
+        // Increment Program Counter
+        self.registers.program_counter += 1;
+        let low = self.read_byte(self.registers.program_counter as usize) as u16;
+        self.registers.program_counter += 1;
+        let high = self.read_byte(self.registers.program_counter as usize) as u16;
+        // set relative address
+        self.address_relative = (high << 8) | low;
+        if self.address_relative & 0x80 != 0 {
+            self.address_relative |= 0xFF00;
+        }
+        return 0;
+    }
+
+    /*
+        ACTUAL OPERATIONS
+    */
+
+    fn sei(&mut self) -> u8 {
+        self.registers.cpu_flags = set_bit(self.registers.cpu_flags,2);
+        return 0;
+    }
+
+    fn rti(&mut self) -> u8 {
+        self.registers.cpu_flags = self.pull_u8();
+        // unset flags
+        self.registers.cpu_flags = unset_bit(self.registers.cpu_flags,4);
+        self.registers.cpu_flags = unset_bit(self.registers.cpu_flags,5);
+        self.registers.program_counter = self.pull_u16();
+        cpu_log!(self, "{:X}",self.registers.program_counter);
+        if self.verbose {
+            self.print_state();
+        }
+        return 0;
+    }
+
+    fn brk(&mut self) -> u8 {
+        // BRK skips a padding byte so the return address is opcode + 2
+        self.push_u16(self.registers.program_counter.wrapping_add(2));
+        self.push_u8(self.registers.cpu_flags | (1 << 4) | (1 << 5));
+        self.registers.cpu_flags = set_bit(self.registers.cpu_flags,2);
+        let lo:u16 = self.read_byte(0xFFFE) as u16;
+        let hi:u16 = self.read_byte(0xFFFF) as u16;
+        self.registers.program_counter = (hi << 8) | lo;
+        0
+    }
+
+    /// Set Bits In Flags
+    fn clc(&mut self){
+        self.registers.cpu_flags = unset_bit(self.registers.cpu_flags,0); // clear carry bit zero
+    }
+
+    fn cld(&mut self){
+        self.registers.cpu_flags = unset_bit(self.registers.cpu_flags,3); // decimal bit zero
+    }
+
+    fn sta(&mut self) -> u8 {
+        self.write_byte(self.address_absolute as usize,self.registers.a_reg);
+        return 0;
+    }
+
+    fn inx(&mut self) -> u8 {
+        // we need to wrap here
+        let wrap_x = Wrapping(self.registers.x_reg as u16);
+        let wrap_inc = Wrapping(0x1 as u16);
+        let wrap_x = wrap_x.add(wrap_inc);
+        self.registers.x_reg = wrap_x.0 as u8;
+        //self.registers.x_reg += 1;
+        if self.registers.x_reg == 0 {
+            cpu_log!(self, "Setting ZERO FLAG");
+            self.registers.cpu_flags = set_bit(self.registers.cpu_flags,1)
+        }
+        // negative flag check 7th bit
+        if self.registers.x_reg & (1 << 7) != 0 {
+            self.registers.cpu_flags = unset_bit(self.registers.cpu_flags,7)
+        }
+        return 0;
+    }
+
+    fn dex(&mut self) -> u8 {
+        if self.registers.x_reg == 1 {
+            let breaks = 0;
+        }
+        // we need to wrap here
+        let wrap_x = Wrapping(self.registers.x_reg as u16);
+        let wrap_inc = Wrapping(0x1 as u16);
+        let wrap_x = wrap_x.sub(wrap_inc);
+        self.registers.x_reg = wrap_x.0 as u8;
+        //self.registers.x_reg -= 1;
+        if self.registers.x_reg == 0 {
+            cpu_log!(self, "Setting ZERO FLAG");
+            self.registers.cpu_flags = set_bit(self.registers.cpu_flags,1)
+        } else {
+            self.registers.cpu_flags = unset_bit(self.registers.cpu_flags,1)
+        }
+        // negative flag check 7th bit
+        if self.registers.x_reg & (1 << 7) != 0 {
+            self.registers.cpu_flags = set_bit(self.registers.cpu_flags,7)
+        } else {
+            self.registers.cpu_flags = unset_bit(self.registers.cpu_flags,7)
+        }
+        return 0;
+    }
+
+    fn lda(&mut self) -> u8{
+        let result = self.fetch();
+        self.handle_flags(result as usize);
+        self.registers.a_reg = result;
+        // check if page boundary crossed if so add a cycle
+        if (self.address_absolute & 0xFF00) != (self.registers.program_counter & 0xFF00){
+            self.cycles += 1;
+        }
+        // effects zero and neg bits
+        // zero bit 1
+        if result  == 0 {
+            cpu_log!(self, "Setting ZERO FLAG");
+            self.registers.cpu_flags = set_bit(self.registers.cpu_flags,1)
+        } else {
+            self.registers.cpu_flags = unset_bit(self.registers.cpu_flags,1)
+        }
+        // negative flag check 7th bit
+        if result & (1 << 7) != 0 {
+            self.registers.cpu_flags = set_bit(self.registers.cpu_flags,7)
+        } else {
+            self.registers.cpu_flags = unset_bit(self.registers.cpu_flags,7)
+        }
+        return 0;
+    }
+
+    fn ldx(&mut self) -> u8{
+        let result = self.fetch();
+        self.handle_flags(result as usize);
+        self.registers.x_reg = result;
+        // check if page boundary crossed if so add a cycle
+        if (self.address_absolute & 0xFF00) != (self.registers.program_counter & 0xFF00){
+            self.cycles += 1;
+        }
+        // effects zero and neg bits
+        // zero bit 1
+        if result == 0 {
+            cpu_log!(self, "Setting ZERO FLAG");
+            self.registers.cpu_flags = set_bit(self.registers.cpu_flags,1)
+        } else {
+            self.registers.cpu_flags = unset_bit(self.registers.cpu_flags,1)
+        }
+        // negative flag check 7th bit
+        if result & (1 << 7) != 0 {
+            self.registers.cpu_flags = set_bit(self.registers.cpu_flags,7)
+        } else {
+            self.registers.cpu_flags = unset_bit(self.registers.cpu_flags,7)
+        }
+        return 0;
+    }
+    fn txs(&mut self) -> u8 {
+        self.registers.stack_pointer = self.registers.x_reg;
+        // effects zero and neg bits
+        // zero bit 1
+        // zero bit 1
+        if self.registers.stack_pointer == 0 {
+            cpu_log!(self, "Setting ZERO FLAG");
+            self.registers.cpu_flags = set_bit(self.registers.cpu_flags,1)
+        } else {
+            self.registers.cpu_flags = unset_bit(self.registers.cpu_flags,1)
+        }
+        // negative flag check 7th bit
+        if self.registers.stack_pointer & (1 << 7) != 0 {
+            self.registers.cpu_flags = set_bit(self.registers.cpu_flags,7)
+        } else {
+            self.registers.cpu_flags = unset_bit(self.registers.cpu_flags,7)
+        }
+        return 0;
+    }
+    // push stack
+    // pop stack 0x0100 is start of stack from page zero
+    fn pha(&mut self) -> u8 {
+        self.push_u8(self.registers.a_reg);
+        return 0;
+    }
+    // pop stack 0x0100 is start of stack from page zero
+    fn pla(&mut self) -> u8 {
+        self.registers.a_reg = self.pull_u8();
+        self.handle_flags(self.registers.a_reg as usize);
+        return 0;
+    }
+
+
+    fn subc(&mut self) -> u8 {
+        // Just Do The Sub with carry here
+        let fetched = (self.fetch() as u16) ^ 0x00FF;
+        // actual ADD here
+        let tmp:u16 = self.registers.a_reg as u16 + fetched + get_flag(self.registers.cpu_flags,0) as u16;
+        // Handle flags and overflow below.
+        self.handle_flags(tmp as usize);
+        // Handle overflow flags
+        if (self.registers.a_reg as u16 ^ fetched) & (self.registers.a_reg as u16 ^ tmp) & 0x0080 == 1 {
+            set_bit(self.registers.cpu_flags,6);
+        } else {
+            unset_bit(self.registers.cpu_flags,6);
+        }
+        self.registers.a_reg = (tmp & 0x00FF) as u8;
+        return 1;
+    }
+    fn adc(&mut self) -> u8 {
+        // Just Do The Add With Carry Here:w:
+        let fetched = self.fetch() as u16;
+        // actual ADD here
+        let tmp:u16 = self.registers.a_reg as u16 + fetched + get_flag(self.registers.cpu_flags,0) as u16;
+        // Handle flags and overflow below.
+        self.handle_flags(tmp as usize);
+        // Handle overflow flags
+        if (self.registers.a_reg as u16 ^ fetched) & (self.registers.a_reg as u16 ^ tmp) as u16 & 0x0080 == 1 {
+            set_bit(self.registers.cpu_flags,6);
+        } else {
+            unset_bit(self.registers.cpu_flags,6);
+        }
+        self.registers.a_reg = (tmp & 0x00FF) as u8;
+        return 1;
+    }
+
+    fn bcs(&mut self) -> u8 {
+        // check if carry bit is set
+        // if carry is set we branch
+        if get_flag(self.registers.cpu_flags,0) == 1 {
+            self.cycles += 1;
+            self.address_absolute = self.registers.program_counter + self.address_relative;
+            if (self.address_absolute & 0xFF00) != (self.registers.program_counter & 0xFF00){
+                self.cycles += 1;
+            }
+            self.registers.program_counter = self.address_absolute;
+        }
+        return 0;
+    }
+
+    fn bne(&mut self) -> u8 {
+        // check if zero bit is set
+        // IF ZERO NOT SET WE BRANCH
+        if get_flag(self.registers.cpu_flags,1) == 0 {
+            self.cycles += 1;
+            let wrap_rel = Wrapping(self.address_relative);
+            let wrap_pc = Wrapping(self.registers.program_counter);
+            let wrap_result = wrap_pc.add(wrap_rel);
+            self.address_absolute = wrap_result.0;
+            if (self.address_absolute & 0xFF00) != (self.registers.program_counter & 0xFF00){
+                self.cycles += 1;
+            }
+            self.registers.program_counter = self.address_absolute;
+        }
+        return 0;
+    }
+
+    // AND instruction
+    fn and(&mut self) -> u8 {
+        let result = self.registers.a_reg & self.fetch();
+        self.registers.a_reg = result;
+        self.handle_flags(result as usize);
+        return 1;
+    }
+
+    // Apply the unknown opcode policy to the opcode at pc. False when nothing
+    // ran and the clock should stop here.
+    fn unknown_opcode(&mut self, pc:u16) -> bool {
+        match self.unknown_opcode {
+            UnknownOpcodePolicy::Nop => {
+                cpu_log!(self, "unknown opcode ${:02X} at ${:04X}, skipped as a NOP", self.opcode, pc);
+                self.registers.program_counter = pc.wrapping_add(disasm::instruction_length(self.opcode));
+                self.cycles += 2;
+                true
+            }
+            UnknownOpcodePolicy::Break => {
+                println!("unknown opcode ${:02X} at ${:04X}",self.opcode,pc);
+                match self.debugger.as_mut() {
+                    Some(debugger) => debugger.stepping = true,
+                    None => self.pause(),
+                }
+                false
+            }
+            UnknownOpcodePolicy::Error => {
+                self.halt(HaltReason::UnknownOpcode{opcode:self.opcode,address:pc});
+                false
+            }
+        }
+    }
+
+    // Put PC and the cycle count back to before the fetch of an opcode the core cannot run.
+    fn unsupported(&mut self, pc:u16, cycles:u8) -> bool {
+        self.registers.program_counter = pc;
+        self.cycles = cycles;
+        false
+    }
+
+    // False when the opcode is not implemented, with PC left on it.
+    fn execute_instruction(&mut self) -> bool {
+        let (pc,cycles) = (self.registers.program_counter,self.cycles);
+        match INSTRUCTION_TABLE.get(&self.opcode) {
+            Some(instruction) => {
+                // Fetch Data Based On Addressing Mode
+                match instruction.address_mode {
+                    Implied => {
+                        cpu_log!(self, "implied");
+                        self.cycles += instruction.cycles;
+                        self.implied_mode();
+                        self.current_mode = Implied;
+                    }
+                    Immediate => {
+                        cpu_log!(self, "immediate");
+                        self.cycles += instruction.cycles;
+                        self.immediate_mode();
+                        self.current_mode = Immediate;
+                    }
+                    ZeroPage => {
+                        cpu_log!(self, "zero page");
+                        self.cycles += instruction.cycles;
+                        self.cycles += self.zero_page_mode();
+                        self.current_mode = ZeroPage;
+                    }
+                    ZeroPageX => {
+                        cpu_log!(self, "zero page x");
+                        self.cycles += instruction.cycles;
+                        self.cycles += self.zero_page_x_mode();
+                        self.current_mode = ZeroPageX;
+                    }
+                    ZeroPageY => {
+                        cpu_log!(self, "zero page y");
+                        self.cycles += instruction.cycles;
+                        self.cycles += self.zero_page_y_mode();
+                        self.current_mode = ZeroPageY;
+                    }
+                    Absolute => {
+                        cpu_log!(self, "absolute");
+                        self.cycles += instruction.cycles;
+                        self.cycles += self.absolute_mode();
+                        self.current_mode = Absolute;
+                    }
+                    AbsoluteX => {
+                        cpu_log!(self, "absolute x");
+                        self.cycles += instruction.cycles;
+                        self.cycles += self.absolute_mode_x();
+                        self.current_mode = AbsoluteX;
+                    }
+                    AbsoluteY  => {
+                        cpu_log!(self, "absolute xy");
+                        self.cycles += instruction.cycles;
+                        self.cycles += self.absolute_mode_y();
+                        self.current_mode = AbsoluteY;
+                    }
+                    IndirectX => {
+                        cpu_log!(self, "indirect x");
+                        self.cycles += instruction.cycles;
+                        self.cycles += self.indirect_mode_page_zero_x();
+                        self.current_mode = IndirectX;
+                    }
+                    IndirectY => {
+                        cpu_log!(self, "indirect y");
+                        self.cycles += instruction.cycles;
+                        self.cycles += self.indirect_mode_page_zero_y();
+                        self.current_mode = IndirectY;
+
+                    }
+                    Relative => {
+                        cpu_log!(self, "relative");
+                        self.cycles += instruction.cycles;
+                        self.cycles += self.relative_mode();
+                        self.current_mode = Relative;
+                    }
+                    _ => {
+                        return self.unsupported(pc,cycles);
+                    }
+                }
+                // Match On Opcode
+                // we have to borrow here?
+                match instruction.operation {
+                    RTI => {
+                        cpu_log!(self, "RTI");
+                        self.cycles += self.rti();
+                    }
+                    AND => {
+                        cpu_log!(self, "AND!");
+                        self.cycles += self.and();
+                    }
+                    BRK => {
+                        cpu_log!(self, "BRK!");
+                        let pc = self.registers.program_counter;
+                        self.cycles += self.brk();
+                        self.interrupts.serviced(Source::Brk,self.position(),pc,self.registers.program_counter);
+                        return true;
+                    }
+                    SEI => {
+                        cpu_log!(self, "SEI");
+                        self.sei();
+                    }
+                    CLD => {
+                        cpu_log!(self, "CLD");
+                        self.cld();
+                    }
+                    LDX => {
+                        self.ldx();
+                        cpu_log!(self, "LDX");
+                        self.cycles += self.ldx();
+                    }
+                    TXS => {
+                        cpu_log!(self, "TXS");
+                        self.cycles += self.txs();
+                    }
+                    LDA => {
+                        cpu_log!(self, "LDA");
+                        self.cycles += self.lda();
+                    }
+                    STA => {
+                        cpu_log!(self, "STA");
+                        self.cycles += self.sta();
+                    }
+                    DEX => {
+                        cpu_log!(self, "DEX");
+                        self.cycles += self.dex();
+                    }
+                    INX => {
+                        cpu_log!(self, "INX");
+                        self.cycles += self.inx();
+                    }
+                    BNE => {
+                        cpu_log!(self, "BNE");
+                        self.cycles += self.bne();
+                        return true;
+
+                    }
+                    _ => {
+                        return self.unsupported(pc,cycles);
+                    }
+                }
+            }
+            _ => {
+                return self.unsupported(pc,cycles);
+            }
+        }
+        self.registers.program_counter += 1;
+        true
+    }
+
+    fn handle_flags(&mut self,result:usize) {
+        // carry flag check zero bit
+        if result > 255 {
+            self.registers.cpu_flags = set_bit(self.registers.cpu_flags,0)
+        } else {
+            self.registers.cpu_flags =  unset_bit(self.registers.cpu_flags,0)
+        }
+        // zero bit 1
+        if result == 0 {
+            self.registers.cpu_flags = set_bit(self.registers.cpu_flags,1)
+        } else {
+            self.registers.cpu_flags = unset_bit(self.registers.cpu_flags,1)
+        }
+        // negative flag check 7th bit
+        if result & (1 << 7) != 0 {
+            self.registers.cpu_flags = set_bit(self.registers.cpu_flags,7)
+        } else {
+            self.registers.cpu_flags = unset_bit(self.registers.cpu_flags,7)
+        }
+    }
+}
+
+
+
+// Write a labelled .asm file per PRG bank instead of running the rom.
+fn disasm_command(args:&[String]) {
+    let mut rom_path:Option<&str> = None;
+    let mut out_dir = "disasm";
+    let mut cdl_path:Option<&str> = None;
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--out" => {
+                i += 1;
+                out_dir = args.get(i).map(|a| a.as_str()).unwrap_or(out_dir);
+            }
+            "--cdl" => {
+                i += 1;
+                cdl_path = args.get(i).map(|a| a.as_str());
+            }
+            path => rom_path = Some(path),
+        }
+        i += 1;
+    }
+    let Some(rom_path) = rom_path else {
+        println!("usage: rnes disasm rom --out dir [--cdl file]");
+        return;
+    };
+    match asm_export::export(rom_path, out_dir, cdl_path) {
+        Ok(files) => {
+            for file in files {
+                println!("wrote {}", file);
+            }
+        }
+        Err(e) => println!("failed to disassemble {}: {}", rom_path, e),
+    }
+}
+
+// Describe a ROM's header and hashes, as text or as JSON for scripts.
+fn info_command(args:&[String]) {
+    let json = args.iter().any(|a| a == "--json");
+    let Some(path) = args.iter().find(|a| *a != "--json") else {
+        println!("usage: rnes info rom [--json]");
+        return;
+    };
+    let rom = match fs::read(path) {
+        Ok(rom) => rom,
+        Err(e) => {
+            println!("Failed to read {}: {}",path,e);
+            return;
+        }
+    };
+    match info::parse(&rom) {
+        Some(info) if json => println!("{}",info.to_json()),
+        Some(info) => print!("{}",info.to_text()),
+        None => println!("{} is not an iNES rom",path),
+    }
+}
+
+// List the practice slots saved in a directory with their metadata, as text or as JSON for frontends.
+fn slots_command(args:&[String]) {
+    let json = args.iter().any(|a| a == "--json");
+    let Some(dir) = args.iter().find(|a| *a != "--json") else {
+        println!("usage: rnes slots dir [--json]");
+        return;
+    };
+    if let Err(e) = fs::read_dir(dir) {
+        println!("Failed to read {}: {}",dir,e);
+        return;
+    }
+    let mut practice = Practice::new();
+    practice.dir = Some(dir.clone());
+    let slots = practice.slots();
+    if json {
+        let entries:Vec<String> = slots.iter().map(|slot| {
+            let metadata = slot.metadata.map(|m| m.to_json()).unwrap_or("null".to_string());
+            format!("{{\"name\":{},\"metadata\":{}}}",snapshot::json_string(&slot.name),metadata)
+        }).collect();
+        println!("[{}]",entries.join(","));
+        return;
+    }
+    for slot in slots {
+        match slot.metadata {
+            Some(meta) => {
+                let rom = meta.rom_crc32.map(|crc| format!("{:08x}",crc)).unwrap_or("unknown".to_string());
+                println!("{:<16} {}  played {}  frame {}  rom {}",slot.name,meta.saved_text(),meta.play_text(),meta.frames,rom);
+            }
+            None => println!("{:<16} (no metadata)",slot.name),
+        }
+    }
+}
+
+// A machine set up the way a session header says, before any input.
+fn session_emulator(header:&SessionHeader) -> Result<Emulator,String> {
+    let mut emulator = Emulator::new();
+    emulator.power_on_pattern = PowerOnPattern::from_name(&header.ram_pattern).unwrap_or(PowerOnPattern::Zeros);
+    emulator.unknown_opcode = UnknownOpcodePolicy::from_name(&header.unknown_opcode).unwrap_or(UnknownOpcodePolicy::Error);
+    emulator.practice.seed = header.seed;
+    emulator.ppu.overclock_lines = header.overclock;
+    if let Err(e) = emulator.load_rom(&header.rom) {
+        return Err(format!("Failed to load rom {}: {}",header.rom,e));
+    }
+    if let Some(state) = header.state.as_ref() {
+        if let Err(e) = emulator.load_state(state) {
+            return Err(format!("Failed to load state {}: {}",state,e));
+        }
+    }
+    Ok(emulator)
+}
+
+// Run two identical machines side by side and report the first frame their state differs.
+fn audit_command(args:&[String]) {
+    let mut rom_path:Option<&str> = None;
+    let mut session_path:Option<&str> = None;
+    let mut frames:u64 = 600;
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--frames" => {
+                i += 1;
+                frames = args.get(i).and_then(|a| a.parse().ok()).unwrap_or(frames);
+            }
+            "--session" => {
+                i += 1;
+                session_path = args.get(i).map(|a| a.as_str());
+            }
+            path => rom_path = Some(path),
+        }
+        i += 1;
+    }
+    // a session supplies the rom, settings and input, otherwise nothing is pressed
+    let setup = |path:Option<&str>| -> Result<Emulator,String> {
+        let Some(path) = path else {
+            return session_emulator(&SessionHeader {
+                rom:rom_path.unwrap_or_default().to_string(),
+                ram_pattern:PowerOnPattern::Zeros.name().to_string(),
+                unknown_opcode:UnknownOpcodePolicy::Error.name().to_string(),
+                seed:1,
+                overclock:0,
+                state:None,
+            });
+        };
+        let (header,replay) = session::read(path).map_err(|e| format!("Failed to read session {}: {}",path,e))?;
+        let mut emulator = session_emulator(&header)?;
+        emulator.session = Some(Session::Replaying(replay));
+        Ok(emulator)
+    };
+    if rom_path.is_none() && session_path.is_none() {
+        println!("usage: rnes audit (rom | --session file) [--frames n]");
+        return;
+    }
+    match audit::run(&|| setup(session_path),frames) {
+        audit::Outcome::Matched(frames) => println!("deterministic: state matched on all {} frames",frames),
+        audit::Outcome::Stopped(frame) => println!("deterministic: state matched until both stopped at frame {}",frame),
+        audit::Outcome::Diverged(d) => println!("diverged at frame {} in {}: {}",d.frame,d.component,d.detail),
+        audit::Outcome::Failed(e) => println!("audit failed: {}",e),
+    }
+    // nothing else to compare until these exist
+    println!("not covered: APU and mappers are not emulated yet");
+}
+
+// Run a recorded session again and check it hashes the same as it did the first time.
+fn replay_command(args:&[String]) {
+    let Some(path) = args.first() else {
+        println!("usage: rnes replay session");
+        return;
+    };
+    let (header,replay) = match session::read(path) {
+        Ok(session) => session,
+        Err(e) => {
+            println!("Failed to read session {}: {}",path,e);
+            return;
+        }
+    };
+    let mut emulator = match session_emulator(&header) {
+        Ok(emulator) => emulator,
+        Err(e) => {
+            println!("{}",e);
+            return;
+        }
+    };
+    emulator.session = Some(Session::Replaying(replay));
+    emulator.start();
+    if let Some(Session::Replaying(replay)) = emulator.session.as_ref() {
+        match replay.desync {
+            Some(frame) => println!("desync at frame {} after {} matching hashes",frame,replay.checked),
+            None => println!("replay matched {} hashes through frame {}",replay.checked,emulator.ppu.frame),
+        }
+    }
+}
+
+// Run every rom in a directory headlessly and report which ones boot.
+fn batch_command(args:&[String]) {
+    let mut dir:Option<&str> = None;
+    let mut frames:u64 = 600;
+    let mut out_path:Option<&str> = None;
+    let mut jobs = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--frames" => {
+                i += 1;
+                frames = args.get(i).and_then(|a| a.parse().ok()).unwrap_or(frames);
+            }
+            "--out" => {
+                i += 1;
+                out_path = args.get(i).map(|a| a.as_str());
+            }
+            "--jobs" => {
+                i += 1;
+                jobs = args.get(i).and_then(|a| a.parse().ok()).unwrap_or(jobs);
+            }
+            path => dir = Some(path),
+        }
+        i += 1;
+    }
+    let Some(dir) = dir else {
+        println!("usage: rnes batch dir [--frames n] [--out report.csv|report.json] [--jobs n]");
+        return;
+    };
+    let results = match batch::run(dir,frames,jobs) {
+        Ok(results) => results,
+        Err(e) => {
+            println!("Failed to read {}: {}",dir,e);
+            return;
+        }
+    };
+    let booted = results.iter().filter(|r| r.booted).count();
+    match out_path {
+        Some(path) => {
+            let report = if path.ends_with(".json") { batch::to_json(&results) } else { batch::to_csv(&results) };
+            if let Err(e) = fs::write(path,report) {
+                println!("Failed to write {}: {}",path,e);
+                return;
+            }
+            println!("{} of {} roms ran {} frames, report in {}",booted,results.len(),frames,path);
+        }
+        None => print!("{}",batch::to_csv(&results)),
+    }
+}
+
+// The rnes command line, src/main.rs only calls this.
+pub fn cli() {
+    // TODO parse 16 Byte NES HEADER IN LOAD ROm
+    // usage: rnes [rom] [--load-state file] [--save-state file] [--profile top_n] [--cdl file]
+    //             [--debug] [--dump-state-on-exit file] [--dump-interrupts file] [--ram-pattern zeros|ones|alternating]
+    //             [--watch | --watch-keep-ram | --watch-state file] [--tui]
+    //             [--watches file] [--log-watches] [--triggers file] [--ram-map file]
+    //             [--guards file] [--slot-dir dir] [--lag-point addr]
+    //             [--session file | --no-session] [--io-trace file] [--io-filter regs]
+    //             [--dip hex] [--vs-palette file] [--frames n] [--trace file]
+    //             [--unknown-opcode nop|break|error] [--input-pipe file|-] [--frame-out file|-]
+    //             [--realtime] [--expansion vaus|vaus-famicom|power-pad] [--overclock lines]
+    //             [--no-sprite-limit]
+    //        rnes host port rom [--delay frames] [run options]
+    //        rnes join host:port rom [run options]
+    //        rnes disasm rom --out dir [--cdl file]
+    //        rnes replay session
+    //        rnes info rom [--json]
+    //        rnes slots dir [--json]
+    //        rnes audit (rom | --session file) [--frames n]
+    //        rnes batch dir [--frames n] [--out file] [--jobs n]
+    //        rnes singlestep file_or_dir..   (singlestep feature)
+    let args:Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(|a| a.as_str()) {
+        Some("disasm") => {
+            disasm_command(&args[1..]);
+            return;
+        }
+        Some("replay") => {
+            replay_command(&args[1..]);
+            return;
+        }
+        Some("info") => {
+            info_command(&args[1..]);
+            return;
+        }
+        Some("slots") => {
+            slots_command(&args[1..]);
+            return;
+        }
+        Some("audit") => {
+            audit_command(&args[1..]);
+            return;
+        }
+        Some("batch") => {
+            batch_command(&args[1..]);
+            return;
+        }
+        #[cfg(feature = "singlestep")]
+        Some("singlestep") => {
+            if !singlestep::run(&args[1..]) {
+                std::process::exit(1);
+            }
+            return;
+        }
+        _ => {}
+    }
+    // host and join take the address first and otherwise run like a plain rom
+    let (netplay_target,args) = match args.first().map(|a| a.as_str()) {
+        Some(mode @ ("host" | "join")) => match args.get(1) {
+            Some(address) => (Some((mode == "host",address.clone())),args[2..].to_vec()),
+            None => {
+                println!("usage: rnes host port rom [--delay frames] | rnes join host:port rom");
+                return;
+            }
+        },
+        _ => (None,args),
+    };
+    let mut rom_path = "C:\\Users\\lator\\Desktop\\CC65\\main.nes".to_string();
+    let mut load_state_path:Option<String> = None;
+    let mut save_state_path:Option<String> = None;
+    let mut profile_top:Option<usize> = None;
+    let mut cdl_path:Option<String> = None;
+    let mut debug = false;
+    #[cfg(feature = "tui")]
+    let mut tui = false;
+    let mut dump_state_path:Option<String> = None;
+    let mut dump_interrupts_path:Option<String> = None;
+    let mut power_on_pattern = PowerOnPattern::Zeros;
+    let mut unknown_opcode = UnknownOpcodePolicy::Error;
+    let mut watch:Option<ReloadMode> = None;
+    let mut watches_path:Option<String> = None;
+    let mut log_watches = false;
+    let mut realtime = false;
+    let mut overclock_lines:u16 = 0;
+    let mut sprite_limit = true;
+    let mut netplay_delay = netplay::DEFAULT_DELAY;
+    let mut expansion_name:Option<String> = None;
+    let mut triggers_path:Option<String> = None;
+    let mut ram_map_path:Option<String> = None;
+    let mut guards_path:Option<String> = None;
+    let mut slot_dir:Option<String> = None;
+    let mut lag_point:Option<u16> = None;
+    let mut io_trace_path:Option<String> = None;
+    let mut trace_path:Option<String> = None;
+    let mut input_pipe:Option<String> = None;
+    let mut frame_out:Option<String> = None;
+    let mut io_filter:Option<String> = None;
+    let mut dip:u8 = 0;
+    let mut vs_palette:Option<String> = None;
+    // run this many frames and exit instead of running until the program halts
+    let mut frames:Option<u64> = None;
+    // every run leaves a session log behind so a crash can be replayed
+    let mut session_path:Option<String> = Some("rnes-last.session".to_string());
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--load-state" => {
+                i += 1;
+                load_state_path = args.get(i).cloned();
+            }
+            "--save-state" => {
+                i += 1;
+                save_state_path = args.get(i).cloned();
+            }
+            "--profile" => {
+                i += 1;
+                profile_top = Some(args.get(i).and_then(|n| n.parse().ok()).unwrap_or(20));
+            }
+            "--cdl" => {
+                i += 1;
+                cdl_path = args.get(i).cloned();
+            }
+            "--debug" => {
+                debug = true;
+            }
+            #[cfg(feature = "tui")]
+            "--tui" => {
+                debug = true;
+                tui = true;
+            }
+            "--dump-state-on-exit" => {
+                i += 1;
+                dump_state_path = args.get(i).cloned();
+            }
+            "--dump-interrupts" => {
+                i += 1;
+                dump_interrupts_path = args.get(i).cloned();
+            }
+            "--ram-pattern" => {
+                i += 1;
+                match args.get(i).and_then(|name| PowerOnPattern::from_name(name)) {
+                    Some(pattern) => power_on_pattern = pattern,
+                    None => {
+                        println!("--ram-pattern expects zeros, ones or alternating");
+                        return;
+                    }
+                }
+            }
+            "--unknown-opcode" => {
+                i += 1;
+                match args.get(i).and_then(|name| UnknownOpcodePolicy::from_name(name)) {
+                    Some(policy) => unknown_opcode = policy,
+                    None => {
+                        println!("--unknown-opcode expects nop, break or error");
+                        return;
+                    }
+                }
+            }
+            "--watch" => {
+                watch = Some(ReloadMode::Fresh);
+            }
+            "--watch-keep-ram" => {
+                watch = Some(ReloadMode::KeepRam);
+            }
+            "--watch-state" => {
+                i += 1;
+                watch = args.get(i).map(|state| ReloadMode::State(state.clone()));
+            }
+            "--watches" => {
+                i += 1;
+                watches_path = args.get(i).cloned();
+            }
+            "--log-watches" => {
+                log_watches = true;
+            }
+            "--realtime" => {
+                realtime = true;
+            }
+            "--delay" => {
+                i += 1;
+                match args.get(i).and_then(|n| n.parse().ok()) {
+                    Some(n) => netplay_delay = n,
+                    None => {
+                        println!("--delay expects a number of frames");
+                        return;
+                    }
+                }
+            }
+            "--overclock" => {
+                i += 1;
+                match args.get(i).and_then(|n| n.parse().ok()) {
+                    Some(n) => overclock_lines = n,
+                    None => {
+                        println!("--overclock expects a number of scanlines");
+                        return;
+                    }
+                }
+            }
+            "--no-sprite-limit" => {
+                sprite_limit = false;
+            }
+            "--expansion" => {
+                i += 1;
+                expansion_name = args.get(i).cloned();
+            }
+            "--triggers" => {
+                i += 1;
+                triggers_path = args.get(i).cloned();
+            }
+            "--ram-map" => {
+                i += 1;
+                ram_map_path = args.get(i).cloned();
+            }
+            "--guards" => {
+                i += 1;
+                guards_path = args.get(i).cloned();
+            }
+            "--lag-point" => {
+                i += 1;
+                match args.get(i).and_then(|a| debugger::parse_hex(a)) {
+                    Some(address) => lag_point = Some(address),
+                    None => {
+                        println!("--lag-point expects an address");
+                        return;
+                    }
+                }
+            }
+            "--session" => {
+                i += 1;
+                session_path = args.get(i).cloned();
+            }
+            "--trace" => {
+                i += 1;
+                trace_path = args.get(i).cloned();
+            }
+            "--input-pipe" => {
+                i += 1;
+                input_pipe = args.get(i).cloned();
+            }
+            "--frame-out" => {
+                i += 1;
+                frame_out = args.get(i).cloned();
+            }
+            "--io-trace" => {
+                i += 1;
+                io_trace_path = args.get(i).cloned();
+            }
+            "--io-filter" => {
+                i += 1;
+                io_filter = args.get(i).cloned();
+            }
+            "--dip" => {
+                i += 1;
+                match args.get(i).and_then(|a| debugger::parse_hex(a)) {
+                    Some(value) => dip = value as u8,
+                    None => {
+                        println!("--dip expects the eight switches as a hex byte");
+                        return;
+                    }
+                }
+            }
+            "--frames" => {
+                i += 1;
+                match args.get(i).and_then(|n| n.parse().ok()) {
+                    Some(n) => frames = Some(n),
+                    None => {
+                        println!("--frames expects a number");
+                        return;
+                    }
+                }
+            }
+            "--vs-palette" => {
+                i += 1;
+                vs_palette = args.get(i).cloned();
+            }
+            "--no-session" => {
+                session_path = None;
+            }
+            "--slot-dir" => {
+                i += 1;
+                slot_dir = args.get(i).cloned();
+            }
+            path => {
+                rom_path = path.to_string();
+            }
+        }
+        i += 1;
+    }
+    let mut emulator = Emulator::new();
+    emulator.power_on_pattern = power_on_pattern;
+    emulator.unknown_opcode = unknown_opcode;
+    emulator.ppu.overclock_lines = overclock_lines;
+    emulator.ppu.sprite_limit = sprite_limit;
+    if let Err(e) = emulator.load_rom(&rom_path) {
+        println!("Failed to load rom {}: {}",rom_path,e);
+        return;
+    }
+    if let Some(path) = load_state_path.as_ref() {
+        if let Err(e) = emulator.load_state(path) {
+            println!("Failed to load state {}: {}",path,e);
+            return;
+        }
+    }
+    if let Some(path) = cdl_path.as_ref() {
+        // header bytes 4 and 5 hold PRG size in 16KB units and CHR size in 8KB units
+        let prg_size = emulator.memory[0x8004] as usize * 16384;
+        let chr_size = emulator.memory[0x8005] as usize * 8192;
+        emulator.cdl = Some(CodeDataLog::load(path,prg_size,chr_size));
+    }
+    if let Some(path) = watches_path {
+        match Watches::load(&path) {
+            Ok(watches) => emulator.watches = watches,
+            Err(e) => {
+                println!("Failed to load watches {}: {}",path,e);
+                return;
+            }
+        }
+    }
+    emulator.watches.log_changes = log_watches;
+    // game.map next to game.nes is picked up without the flag
+    let beside_rom = std::path::Path::new(&rom_path).with_extension("map");
+    let ram_map_path = ram_map_path.or_else(|| beside_rom.is_file().then(|| beside_rom.display().to_string()));
+    if let Some(path) = ram_map_path {
+        match RamMap::load(&path) {
+            Ok(map) => emulator.ram_map = map,
+            Err(e) => {
+                println!("Failed to load RAM map {}: {}",path,e);
+                return;
+            }
+        }
+    }
+    if let Some(path) = guards_path {
+        match Guards::load(&path) {
+            Ok(guards) => emulator.guards = guards,
+            Err(e) => {
+                println!("Failed to load guards {}: {}",path,e);
+                return;
+            }
+        }
+    }
+    if let Some(path) = triggers_path {
+        match Triggers::load(&path) {
+            Ok(triggers) => emulator.triggers = triggers,
+            Err(e) => {
+                println!("Failed to load triggers {}: {}",path,e);
+                return;
+            }
+        }
+    }
+    emulator.practice.dir = slot_dir;
+    emulator.lag.end_point = lag_point;
+    if let Some(vs) = emulator.vs.as_mut() {
+        vs.dip = dip;
+        println!("VS. System cabinet, {:?} PPU",vs.ppu);
+        if let Some(path) = vs_palette {
+            if let Err(e) = vs.load_palette_map(&path) {
+                println!("Failed to load VS palette {}: {}",path,e);
+                return;
+            }
+        }
+    }
+    if let Some(path) = trace_path {
+        match Trace::create(&path) {
+            Ok(trace) => emulator.trace = Some(trace),
+            Err(e) => {
+                println!("Failed to start trace {}: {}",path,e);
+                return;
+            }
+        }
+    }
+    if input_pipe.is_some() || frame_out.is_some() {
+        if debug && input_pipe.as_deref() == Some("-") {
+            println!("--input-pipe - and --debug both read stdin, use a file or FIFO for the input");
+            return;
+        }
+        match Automation::open(input_pipe.as_deref(),frame_out.as_deref()) {
+            Ok(automation) => emulator.automation = Some(automation),
+            Err(e) => {
+                println!("Failed to open automation input or output: {}",e);
+                return;
+            }
+        }
+        // a script is driving, per instruction output would only bury the frame lines
+        emulator.verbose = false;
+    }
+    if let Some(path) = io_trace_path {
+        match IoTrace::create(&path,io_filter.as_deref()) {
+            Ok(trace) => emulator.io_trace = Some(trace),
+            Err(e) => {
+                println!("Failed to start io trace {}: {}",path,e);
+                return;
+            }
+        }
+    }
+    if let Some(path) = session_path {
+        let header = SessionHeader {
+            rom:rom_path.clone(),
+            ram_pattern:emulator.power_on_pattern.name().to_string(),
+            unknown_opcode:emulator.unknown_opcode.name().to_string(),
+            seed:emulator.practice.seed,
+            overclock:emulator.ppu.overclock_lines,
+            state:load_state_path,
+        };
+        match Recorder::create(&path,&header) {
+            Ok(recorder) => emulator.session = Some(Session::Recording(recorder)),
+            Err(e) => println!("Failed to create session log {}: {}",path,e),
+        }
+    }
+    if let Some(mode) = watch {
+        emulator.rom_watch = Some(RomWatch::new(&rom_path,mode));
+    }
+    if debug {
+        emulator.debugger = Some(Debugger::new());
+        #[cfg(feature = "tui")]
+        if let Some(debugger) = emulator.debugger.as_mut() {
+            debugger.tui = tui.then(crate::tui::Live::start);
+        }
+    }
+    if let Some(name) = expansion_name {
+        match expansion::from_name(&name) {
+            Some(device) => emulator.expansion = Some(device),
+            None => {
+                println!("Unknown expansion device {}, expected vaus, vaus-famicom or power-pad",name);
+                return;
+            }
+        }
+    }
+    if let Some((host,address)) = netplay_target {
+        let crc = emulator.rom_crc32.unwrap_or(0);
+        let connected = if host {
+            println!("waiting for a player on {}",address);
+            Netplay::host(&address,netplay_delay,crc)
+        } else {
+            Netplay::join(&address,crc)
+        };
+        match connected {
+            Ok(netplay) => {
+                println!("connected, you are player {} with {} frames of input delay",netplay.player + 1,netplay.delay);
+                emulator.netplay = Some(netplay);
+            }
+            Err(e) => {
+                println!("Failed to connect to {}: {}",address,e);
+                return;
+            }
+        }
+    }
+    if realtime {
+        emulator.pacer = Some(Pacer::default());
+    }
+    if profile_top.is_some() {
+        emulator.profiler = Some(Profiler::new());
+    }
+    match frames {
+        Some(frames) => {
+            let ran = emulator.run_frames(frames);
+            if ran < frames {
+                println!("stopped after {} of {} frames",ran,frames);
+            }
+        }
+        None => emulator.start(),
+    }
+    match emulator.run_state {
+        RunState::Halted(HaltReason::Jam{opcode,address}) => println!("CPU jammed on opcode ${:02X} at ${:04X}",opcode,address),
+        RunState::Halted(HaltReason::UnknownOpcode{opcode,address}) => {
+            println!("unknown opcode ${:02X} at ${:04X}, --unknown-opcode nop skips these",opcode,address);
+        }
+        _ => {}
+    }
+    if let (Some(profiler),Some(top)) = (emulator.profiler.as_ref(),profile_top) {
+        print!("{}",profiler.report(&emulator.memory,top));
+    }
+    if let (Some(cdl),Some(path)) = (emulator.cdl.as_ref(),cdl_path) {
+        if let Err(e) = cdl.save(&path) {
+            println!("Failed to write code/data log {}: {}",path,e);
+        }
+    }
+    if let Some(path) = dump_state_path {
+        if let Err(e) = fs::write(&path,emulator.snapshot().to_json()) {
+            println!("Failed to dump state {}: {}",path,e);
+        }
+    }
+    if let Some(path) = dump_interrupts_path {
+        if let Err(e) = fs::write(&path,emulator.interrupts.to_json()) {
+            println!("Failed to dump interrupts {}: {}",path,e);
+        }
+    }
+    if let Some(path) = save_state_path {
+        if let Err(e) = emulator.save_state(&path) {
+            println!("Failed to save state {}: {}",path,e);
+        }
+    }
+    // http://www.6502.org/tutorials/6502opcodes.html#STA
+    //http://www.emulator101.com/6502-addressing-modes.html
+    //https://github.com/Klaus2m5/6502_65C02_functional_tests
+    // https://www.pagetable.com/c64ref/6502/?tab=2#
+}
+
+
+/*match self.opcode {
+      // ADC instruction
+      0x069 | 0x65 | 0x75 | 0x6D | 0x7D | 0x79 | 0x61 | 0x71 => {
+          println!("ADD With Carry!");
+          self.adc(opcode);
+      }
+      // AND
+      0x29 | 0x25 | 0x35 | 0x2D | 0x3D | 0x39 | 0x21 | 0x31 => {
+          println!("AND!");
+      }
+      // ASL (Arithimetic shift left)
+      0x0A | 0x06 | 0x16 | 0x0E | 0x1E => {
+          println!("Arithmetic Shift Left");
+      }
+      // BIT
+      0x24 | 0x2C => {
+          println!("TEST BIT");
+      }
+      // BRANCH INSTRUCTIONS
+      0x10 | 0x30 | 0x50 | 0x70 | 0x90 | 0xB0 | 0xD0 | 0xF0 => {
+          self.registers.program_counter += 1;
+          println!("BRANCH");
+      }
+      // BRK
+      0x00 => {
+          println!("BRK");
+      }
+      // CMP
+      0xC9 | 0xC5 | 0xD5 | 0xCD | 0xDD | 0xD9 | 0xC1 | 0xD1 => {
+          println!("Compare Accumulator!");
+      }
+      // CPX
+      0xE0 | 0xE4 | 0xEC => {
+          println!("Compare X Register");
+      }
+      // CPY
+      0xC0 | 0xC4 | 0xCC => {
+          println!("Compare Y Register");
+      }
+      // DEC
+      0xC6 | 0xD6 | 0xCE | 0xDE => {
+          println!("Decrement!");
+      }
+      // EOR
+      0x49 | 0x45 | 0x55 | 0x4D | 0x5D | 0x59 | 0x41 | 0x51 => {
+          println!("Exclusive OR");
+      }
+      // FLAG INSTRUCTIONS
+      0x18 | 0x38 | 0x58 | 0x78 | 0xB8 | 0xD8 | 0xF8 => {
+          println!("Flag instructions");
+      }
+      // INC MEM
+      0xE6 | 0xF6 | 0xEE | 0xFE => {
+          println!("INC MEM");
+      }
+      // JMP
+      0x4C | 0x6C => {
+          println!("JMP");
+      }
+      // JSR
+      0x20 => {
+          println!("JSR");
+      }
+      // LDA
+      0xA9 | 0xA5 | 0xB5 | 0xAD | 0xBD | 0xB9 | 0xA1 | 0xB1 => {
+          self.registers.program_counter += 1;
+          println!("Load Accumulator");
+      }
+      // LDX
+      0xA2 | 0xA6 | 0xB6 | 0xAE | 0xBE => {
+          // Just for now
+          self.registers.program_counter += 1;
+          println!("Load X Register");
+      }
+      // LDY
+      0xA0 | 0xA4 | 0xB4 | 0xAC | 0xBC => {
+          println!("Load Y Register")
+      }
+      // LSR
+      0x4A | 0x46 | 0x56 | 0x4E | 0x5E => {
+          println!("Load shift right");
+      }
+      // NOP
+      0xEA => {
+          println!("NOP");
+      }
+      // ORA
+      0x09 | 0x05 | 0x15 | 0x0D | 0x1D | 0x19 | 0x01 | 0x11 => {
+          println!("bitwise or");
+      }
+      // Register Instructions
+      0xAA | 0x8A | 0xCA | 0xE8 | 0xA8 | 0x98 | 0x88 | 0xC8 => {
+          println!("register instruction");
+      }
+      // ROL
+      0x2A | 0x26 | 0x36 | 0x2E | 0x3E => {
+          println!("rotate left");
+      }
+      // ROR
+      0x6A | 0x66 | 0x76 | 0x6E | 0x7E => {
+          println!("rotate right");
+      }
+      // RTI
+      0x40 => {
+          println!("return from interrupt");
+      }
+      // RTS
+      0x60 => {
+          println!("return from subroutine");
+      }
+      // SBC
+      0xE9 | 0xE5 | 0xF5 | 0xED | 0xFD| 0xF9 | 0xE1 | 0xF1 => {
+          println!("Subtract with carry")
+      }
+      // STA
+      0x85 | 0x95 | 0x8D | 0x9D | 0x99 | 0x81 | 0x91 => {
+          self.registers.program_counter += 1;
+          println!("Store accumulator");
+      }
+      // Stack instructions
+      0x9A | 0xBA | 0x48 | 0x68 | 0x08 | 0x28 => {
+          println!("stack instruction");
+      }
+      // STX
+      0x86 | 0x96 | 0x8E => {
+          println!("Store X register");
+      }
+      // STY
+      0x84 | 0x94 | 0x8C => {
+          println!("Store Y register");
+      }
+      // Unknown Opcode?
+      _ => unreachable!("Unknown Opcode!")
+  }*/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn machine() -> Emulator {
+        let mut emulator = Emulator::new();
+        emulator.verbose = false;
+        emulator
+    }
+
+    #[test]
+    fn oam_dma_copies_a_page_and_stalls_the_cpu() {
+        let mut emulator = machine();
+        for (i, byte) in emulator.memory[0x0200..0x0300].iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+        emulator.write_byte(0x4014, 0x02);
+        assert_eq!(emulator.ppu.oam[..4], [0, 1, 2, 3]);
+        assert_eq!(emulator.ppu.oam[0xFF], 0xFF);
+        assert_eq!(emulator.dma_cycles, 513);
+        let pc = emulator.registers.program_counter;
+        for _ in 0..513 {
+            emulator.clock();
+        }
+        // nothing ran while the DMA held the bus
+        assert_eq!((emulator.registers.program_counter, emulator.total_cycles), (pc, 513));
+    }
+
+    // program at $8010, the rest of the cartridge is empty
+    fn running(program: &[u8]) -> Emulator {
+        let mut emulator = machine();
+        emulator.memory[0x8010..0x8010 + program.len()].copy_from_slice(program);
+        emulator.registers.program_counter = 0x8010;
+        emulator
+    }
+
+    // LDX #$01, BNE to itself
+    const SPIN: [u8; 4] = [0xA2, 0x01, 0xD0, 0xFE];
+
+    // LDA absolute, which the core does not have, then the spin loop
+    fn unknown_then_spin() -> Vec<u8> {
+        [&[0xAD, 0x00, 0x02][..], &SPIN].concat()
+    }
+
+    #[test]
+    fn unknown_opcodes_skip_as_a_nop() {
+        let mut emulator = running(&unknown_then_spin());
+        emulator.unknown_opcode = UnknownOpcodePolicy::Nop;
+        assert!(emulator.run_frame());
+        assert_eq!(emulator.run_state, RunState::Running);
+        assert_eq!(emulator.registers.program_counter, 0x8015);
+    }
+
+    #[test]
+    fn unknown_opcodes_break_with_pc_on_them() {
+        let mut emulator = running(&unknown_then_spin());
+        emulator.unknown_opcode = UnknownOpcodePolicy::Break;
+        // without a debugger to stop in the machine pauses
+        assert!(!emulator.run_frame());
+        assert_eq!(emulator.run_state, RunState::Paused);
+        assert_eq!(emulator.registers.program_counter, 0x8010);
+    }
+
+    #[test]
+    fn unknown_opcodes_halt_under_error() {
+        let mut emulator = running(&unknown_then_spin());
+        emulator.unknown_opcode = UnknownOpcodePolicy::Error;
+        assert!(!emulator.run_frame());
+        assert_eq!(emulator.run_state, RunState::Halted(HaltReason::UnknownOpcode{opcode:0xAD,address:0x8010}));
+        assert_eq!((emulator.registers.program_counter, emulator.ppu.frame), (0x8010, 0));
+        assert_eq!(emulator.run_frames(3), 0);
+    }
+
+    #[test]
+    fn jam_opcodes_halt() {
+        let mut emulator = running(&[0xA2, 0x01, 0x02]);
+        assert!(!emulator.run_frame());
+        assert_eq!(emulator.run_state, RunState::Halted(HaltReason::Jam{opcode:0x02,address:0x8012}));
+        assert_eq!(emulator.registers.x_reg, 0x01);
+    }
+
+    #[test]
+    fn run_frames_counts_the_frames_that_finished() {
+        let mut emulator = running(&SPIN);
+        assert_eq!(emulator.run_frames(3), 3);
+        assert_eq!(emulator.ppu.frame, 3);
+        // the loop jams on its next pass
+        emulator.memory[0x8012] = 0x02;
+        assert_eq!(emulator.run_frames(5), 0);
+        assert!(matches!(emulator.run_state, RunState::Halted(HaltReason::Jam{..})));
+        assert_eq!(emulator.ppu.frame, 3);
+    }
+
+    #[test]
+    fn pause_stops_frames_until_resume() {
+        let mut emulator = running(&SPIN);
+        emulator.pause();
+        assert_eq!(emulator.run_state, RunState::Paused);
+        assert!(!emulator.run_frame());
+        assert_eq!((emulator.ppu.frame, emulator.total_cycles), (0, 0));
+        emulator.resume();
+        assert_eq!(emulator.run_frames(2), 2);
+        // pausing a halted machine keeps it halted, and a quit sticks through resume
+        emulator.halt(HaltReason::Quit);
+        emulator.pause();
+        emulator.resume();
+        assert_eq!(emulator.run_state, RunState::Halted(HaltReason::Quit));
+    }
+
+    // one bank NROM image, chr_fill is None for CHR-RAM
+    fn rom_file(name: &str, chr_fill: Option<u8>) -> String {
+        let mut rom = b"NES\x1A\x01".to_vec();
+        rom.push(chr_fill.is_some() as u8);
+        rom.resize(16, 0);
+        rom.extend(vec![0xEA; 16384]);
+        if let Some(fill) = chr_fill {
+            rom.extend(vec![fill; 8192]);
+        }
+        let path = std::env::temp_dir().join(format!("rnes-{}-{}.nes", name, std::process::id()));
+        fs::write(&path, rom).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn soft_reset_keeps_ram_and_registers_and_sets_i() {
+        let mut emulator = machine();
+        emulator.memory[0xFFFC..0xFFFE].copy_from_slice(&[0x34, 0x12]);
+        emulator.memory[0x0300] = 0xAB;
+        emulator.registers.a_reg = 0x42;
+        emulator.registers.stack_pointer = 0xFD;
+        emulator.registers.cpu_flags = 0x00;
+        emulator.ppu.ctrl = 0x80;
+        emulator.soft_reset();
+        let regs = &emulator.registers;
+        assert_eq!((regs.program_counter, regs.stack_pointer, regs.a_reg), (0x1234, 0xFA, 0x42));
+        assert_eq!(regs.cpu_flags & 0x04, 0x04);
+        assert_eq!((emulator.memory[0x0300], emulator.ppu.ctrl), (0xAB, 0));
+    }
+
+    #[test]
+    fn power_cycle_starts_over_with_i_set() {
+        let mut emulator = machine();
+        emulator.memory[0xFFFC..0xFFFE].copy_from_slice(&[0x34, 0x12]);
+        emulator.memory[0x0300] = 0xAB;
+        emulator.registers.cpu_flags = 0xC3;
+        emulator.total_cycles = 1000;
+        emulator.ppu.frame = 9;
+        emulator.power_cycle();
+        let regs = &emulator.registers;
+        assert_eq!((regs.program_counter, regs.stack_pointer, regs.cpu_flags), (0x1234, 0xFD, 0x34));
+        assert_eq!((emulator.memory[0x0300], emulator.total_cycles, emulator.ppu.frame), (0, 0, 0));
+    }
+
+    #[test]
+    fn inserting_a_chr_ram_cartridge_clears_the_old_chr() {
+        let chr_rom = rom_file("chr-rom", Some(0x55));
+        let chr_ram = rom_file("chr-ram", None);
+        let mut emulator = machine();
+        emulator.insert_cartridge(&chr_rom).unwrap();
+        assert_eq!((emulator.ppu.chr[0], emulator.registers.program_counter), (0x55, 0x8010));
+        emulator.insert_cartridge(&chr_ram).unwrap();
+        assert!(emulator.ppu.chr.iter().all(|&b| b == 0));
+        emulator.eject();
+        assert_eq!((emulator.memory[0x8010], emulator.rom_crc32), (0, None));
+        // a bad path keeps the game that is in
+        emulator.insert_cartridge(&chr_rom).unwrap();
+        assert!(emulator.insert_cartridge("missing.nes").is_err());
+        assert_eq!(emulator.ppu.chr[0], 0x55);
+        fs::remove_file(chr_rom).unwrap();
+        fs::remove_file(chr_ram).unwrap();
+    }
+}
@@ -0,0 +1,313 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
+use crate::Emulator;
+
+/* Netplay Packets, UDP, numbers little endian
+    H CRC                   join: hello with the CRC32 of the guest's ROM
+    W DELAY CRC             host: welcome with the frames of input delay and the host's ROM CRC32
+    I NEED FIRST N BUTTONS  inputs: the sender has the other side's buttons for every frame
+                            before NEED, then its own buttons for N frames from FIRST on
+   Lockstep with input delay: what a player holds at the end of frame F is pressed on
+   frame F + DELAY on both machines, and no frame starts until both players' buttons
+   for it are in. Frames before the first exchange press nothing. Inputs packets repeat
+   everything the other side has not confirmed, so a lost packet costs a resend.
+   The host is player 1 and the guest player 2, locally the frontend always drives
+   controller 1.
+   There is no prediction and no rollback: a frame waits until the other side's
+   buttons arrive. Both machines have to step through the same frames, so state
+   loads, power cycles and cartridge swaps are refused while a game is on.
+*/
+
+// wait this long for a packet before sending ours again
+const RESEND: Duration = Duration::from_millis(16);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(60);
+// a peer quiet this long, stopped at a debugger prompt say, ends the game
+const PEER_TIMEOUT: Duration = Duration::from_secs(5);
+pub const DEFAULT_DELAY: u8 = 2;
+// buttons resent per packet at most
+const MAX_INPUTS: usize = 255;
+
+pub struct Netplay {
+    socket: UdpSocket,
+    peer: SocketAddr,
+    // controller port the local player drives, 0 on the host and 1 on the guest
+    pub player: usize,
+    pub delay: u8,
+    crc: u32,
+    // buttons by the frame they are pressed on
+    local: BTreeMap<u64, u8>,
+    remote: BTreeMap<u64, u8>,
+    // first frame the peer is missing our buttons for
+    peer_needs: u64,
+    // first frame we are missing the peer's buttons for
+    received: u64,
+    // first frame with real input, frames before it press nothing
+    start: Option<u64>,
+    // frame of the last exchange, the next one has to follow it
+    last: Option<u64>,
+}
+
+fn is_timeout(e: &io::Error) -> bool {
+    matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut)
+}
+
+fn different_rom() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "the other player is running a different ROM")
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+fn u64_at(bytes: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+}
+
+impl Netplay {
+    fn new(socket: UdpSocket, peer: SocketAddr, player: usize, delay: u8, crc: u32) -> io::Result<Self> {
+        socket.set_read_timeout(Some(RESEND))?;
+        Ok(Netplay {
+            socket,
+            peer,
+            player,
+            delay,
+            crc,
+            local: BTreeMap::new(),
+            remote: BTreeMap::new(),
+            peer_needs: 0,
+            received: 0,
+            start: None,
+            last: None,
+        })
+    }
+
+    // Wait for a guest on address, a port alone listens on every interface.
+    pub fn host(address: &str, delay: u8, crc: u32) -> io::Result<Self> {
+        let address = match address.parse::<u16>() {
+            Ok(port) => format!("0.0.0.0:{}", port),
+            Err(_) => address.to_string(),
+        };
+        let socket = UdpSocket::bind(address)?;
+        socket.set_read_timeout(Some(RESEND))?;
+        let deadline = Instant::now() + CONNECT_TIMEOUT;
+        let mut buffer = [0u8; 512];
+        while Instant::now() < deadline {
+            match socket.recv_from(&mut buffer) {
+                Ok((5, from)) if buffer[0] == b'H' => {
+                    let netplay = Netplay::new(socket, from, 0, delay, crc)?;
+                    netplay.welcome()?;
+                    if u32_at(&buffer, 1) != crc {
+                        return Err(different_rom());
+                    }
+                    return Ok(netplay);
+                }
+                Ok(_) => {}
+                Err(e) if is_timeout(&e) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Err(io::Error::new(io::ErrorKind::TimedOut, "no one joined"))
+    }
+
+    // Connect to a host, which picks the input delay.
+    pub fn join(address: &str, crc: u32) -> io::Result<Self> {
+        let peer = address
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("cannot resolve {}", address)))?;
+        let socket = UdpSocket::bind(if peer.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" })?;
+        socket.set_read_timeout(Some(RESEND))?;
+        let mut hello = vec![b'H'];
+        hello.extend(crc.to_le_bytes());
+        let deadline = Instant::now() + CONNECT_TIMEOUT;
+        let mut buffer = [0u8; 512];
+        while Instant::now() < deadline {
+            socket.send_to(&hello, peer)?;
+            match socket.recv_from(&mut buffer) {
+                Ok((6, from)) if from == peer && buffer[0] == b'W' => {
+                    if u32_at(&buffer, 2) != crc {
+                        return Err(different_rom());
+                    }
+                    return Netplay::new(socket, peer, 1, buffer[1], crc);
+                }
+                Ok(_) => {}
+                Err(e) if is_timeout(&e) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Err(io::Error::new(io::ErrorKind::TimedOut, format!("no answer from {}", address)))
+    }
+
+    fn welcome(&self) -> io::Result<()> {
+        let mut packet = vec![b'W', self.delay];
+        packet.extend(self.crc.to_le_bytes());
+        self.socket.send_to(&packet, self.peer).map(|_| ())
+    }
+
+    // Every button of ours the peer has not confirmed yet.
+    fn send_inputs(&self) -> io::Result<()> {
+        let pending: Vec<(u64, u8)> = self.local.range(self.peer_needs..).take(MAX_INPUTS).map(|(f, b)| (*f, *b)).collect();
+        let first = pending.first().map(|(f, _)| *f).unwrap_or(self.peer_needs);
+        let mut packet = vec![b'I'];
+        packet.extend(self.received.to_le_bytes());
+        packet.extend(first.to_le_bytes());
+        packet.push(pending.len() as u8);
+        packet.extend(pending.iter().map(|(_, b)| *b));
+        self.socket.send_to(&packet, self.peer).map(|_| ())
+    }
+
+    // One packet from the peer, false when none came before the resend time.
+    fn receive(&mut self) -> io::Result<bool> {
+        let mut buffer = [0u8; 512];
+        let (length, from) = match self.socket.recv_from(&mut buffer) {
+            Ok(received) => received,
+            Err(e) if is_timeout(&e) => return Ok(false),
+            Err(e) => return Err(e),
+        };
+        if from != self.peer {
+            return Ok(true);
+        }
+        match buffer[0] {
+            // our welcome got lost and the guest is still saying hello
+            b'H' if self.player == 0 => self.welcome()?,
+            b'I' if length >= 18 && length == 18 + buffer[17] as usize => {
+                self.peer_needs = self.peer_needs.max(u64_at(&buffer, 1));
+                let first = u64_at(&buffer, 9);
+                for (i, buttons) in buffer[18..length].iter().enumerate() {
+                    let frame = first + i as u64;
+                    if frame >= self.received {
+                        self.remote.insert(frame, *buttons);
+                    }
+                }
+                self.advance_received();
+            }
+            _ => {}
+        }
+        Ok(true)
+    }
+
+    fn advance_received(&mut self) {
+        if let Some(start) = self.start {
+            self.received = self.received.max(start);
+            while self.remote.contains_key(&self.received) {
+                self.received += 1;
+            }
+        }
+    }
+
+    // Hand over the local buttons at the end of the frame before frame, and
+    // wait for both players' buttons for frame, indexed by controller port.
+    pub fn exchange(&mut self, frame: u64, own: u8) -> io::Result<[u8; 2]> {
+        if let Some(last) = self.last.filter(|last| frame != last + 1) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("frame jumped from {} to {}, the games are out of sync", last, frame)));
+        }
+        self.last = Some(frame);
+        let start = *self.start.get_or_insert(frame + self.delay as u64);
+        self.local.insert(frame + self.delay as u64, own);
+        self.advance_received();
+        self.send_inputs()?;
+        let mut heard = Instant::now();
+        while frame >= start && !self.remote.contains_key(&frame) {
+            if heard.elapsed() > PEER_TIMEOUT {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "no input from the other player for 5 seconds"));
+            }
+            match self.receive()? {
+                true => heard = Instant::now(),
+                false => self.send_inputs()?,
+            }
+        }
+        let (local, remote) = match (frame >= start, self.local.get(&frame), self.remote.get(&frame)) {
+            (false, _, _) => (0, 0),
+            (true, Some(local), Some(remote)) => (*local, *remote),
+            (true, _, _) => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("no buttons for frame {}", frame))),
+        };
+        self.remote = self.remote.split_off(&(frame + 1));
+        self.local = self.local.split_off(&self.peer_needs.min(frame + 1));
+        let mut buttons = [0; 2];
+        buttons[self.player] = local;
+        buttons[1 - self.player] = remote;
+        Ok(buttons)
+    }
+}
+
+// Anything that moves the frame count out from under the other player is
+// refused while netplay runs.
+pub fn refuse_jump(emulator: &Emulator, what: &str) -> io::Result<()> {
+    match emulator.netplay {
+        Some(_) => Err(io::Error::new(io::ErrorKind::Unsupported, format!("cannot {} during netplay", what))),
+        None => Ok(()),
+    }
+}
+
+// Called once per frame after the controllers, before the session records
+// them. A peer that goes away pauses the game.
+pub fn end_frame(emulator: &mut Emulator) {
+    let Some(netplay) = emulator.netplay.as_mut() else {
+        return;
+    };
+    for controller in emulator.controllers.iter_mut() {
+        controller.force(None);
+    }
+    let own = emulator.controllers[0].buttons();
+    match netplay.exchange(emulator.ppu.frame, own) {
+        Ok(buttons) => {
+            for (controller, buttons) in emulator.controllers.iter_mut().zip(buttons) {
+                controller.force(Some(buttons));
+            }
+        }
+        Err(e) => {
+            println!("netplay stopped: {}", e);
+            emulator.netplay = None;
+            emulator.pause();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn pair(delay: u8) -> (Netplay, Netplay) {
+        let a = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let b = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let (a_address, b_address) = (a.local_addr().unwrap(), b.local_addr().unwrap());
+        (Netplay::new(a, b_address, 0, delay, 0).unwrap(), Netplay::new(b, a_address, 1, delay, 0).unwrap())
+    }
+
+    #[test]
+    fn both_sides_press_the_same_buttons_after_the_delay() {
+        let (mut host, mut guest) = pair(2);
+        // the guest holds frame * 2 on each frame, the host frame
+        let guest = thread::spawn(move || (1..8).map(|f| guest.exchange(f, f as u8 * 2).unwrap()).collect::<Vec<_>>());
+        let seen: Vec<[u8; 2]> = (1..8).map(|f| host.exchange(f, f as u8).unwrap()).collect();
+        assert_eq!(seen, guest.join().unwrap());
+        // frames 1 and 2 are inside the delay, frame 3 presses what frame 1 ended with
+        assert_eq!(seen[..4], [[0, 0], [0, 0], [1, 2], [2, 4]]);
+    }
+
+    #[test]
+    fn a_frame_jump_is_an_error_instead_of_a_hang() {
+        let (mut host, _guest) = pair(2);
+        // frames inside the delay need nothing from the peer
+        host.exchange(1, 0).unwrap();
+        assert!(host.exchange(10, 0).is_err());
+        let (mut host, _guest) = pair(2);
+        host.exchange(1, 0).unwrap();
+        host.exchange(2, 0).unwrap();
+        assert!(host.exchange(1, 0).is_err());
+    }
+
+    #[test]
+    fn frame_jumping_commands_are_refused() {
+        let mut emulator = Emulator::new();
+        emulator.verbose = false;
+        emulator.netplay = Some(pair(2).0);
+        assert!(emulator.load_state("missing.rnss").is_err());
+        assert!(emulator.insert_cartridge("missing.nes").is_err());
+        emulator.netplay = None;
+        assert!(refuse_jump(&emulator, "power cycle").is_ok());
+    }
+}
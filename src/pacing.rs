@@ -0,0 +1,29 @@
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::palette::Region;
+
+// further behind than this, after a debugger stop say, pacing starts over instead of racing to catch up
+const MAX_BEHIND: Duration = Duration::from_millis(100);
+
+// Holds emulation to the console's own frame rate instead of a rounded host
+// 60 Hz. Each deadline is the previous one plus the exact frame length, so
+// sleep overshoot does not add up into drift.
+#[derive(Default)]
+pub struct Pacer {
+    next: Option<Instant>,
+}
+
+impl Pacer {
+    // Called once per frame, sleeps until the frame is due.
+    pub fn end_frame(&mut self, region: Region) {
+        let now = Instant::now();
+        let deadline = match self.next {
+            Some(deadline) if now.saturating_duration_since(deadline) < MAX_BEHIND => deadline,
+            _ => now,
+        };
+        if deadline > now {
+            thread::sleep(deadline - now);
+        }
+        self.next = Some(deadline + Duration::from_secs_f64(1.0 / region.frame_rate()));
+    }
+}
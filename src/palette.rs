@@ -0,0 +1,114 @@
+// 2C02 system palette, RGB for each of the 64 color indexes
+pub const SYSTEM_PALETTE: [(u8, u8, u8); 64] = [
+    (84, 84, 84), (0, 30, 116), (8, 16, 144), (48, 0, 136), (68, 0, 100), (92, 0, 48), (84, 4, 0), (60, 24, 0),
+    (32, 42, 0), (8, 58, 0), (0, 64, 0), (0, 60, 0), (0, 50, 60), (0, 0, 0), (0, 0, 0), (0, 0, 0),
+    (152, 150, 152), (8, 76, 196), (48, 50, 236), (92, 30, 228), (136, 20, 176), (160, 20, 100), (152, 34, 32), (120, 60, 0),
+    (84, 90, 0), (40, 114, 0), (8, 124, 0), (0, 118, 40), (0, 102, 120), (0, 0, 0), (0, 0, 0), (0, 0, 0),
+    (236, 238, 236), (76, 154, 236), (120, 124, 236), (176, 98, 236), (228, 84, 236), (236, 88, 180), (236, 106, 100), (212, 136, 32),
+    (160, 170, 0), (116, 196, 0), (76, 208, 32), (56, 204, 108), (56, 180, 204), (60, 60, 60), (0, 0, 0), (0, 0, 0),
+    (236, 238, 236), (168, 204, 236), (188, 188, 236), (212, 178, 236), (236, 174, 236), (236, 174, 212), (236, 180, 176), (228, 196, 144),
+    (204, 210, 120), (180, 222, 120), (168, 226, 144), (152, 226, 180), (160, 214, 228), (160, 162, 160), (0, 0, 0), (0, 0, 0),
+];
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Region {
+    Ntsc,
+    Pal,
+}
+
+impl Region {
+    // frames per second of the real console
+    pub fn frame_rate(&self) -> f64 {
+        match self {
+            Region::Ntsc => 60.0988,
+            Region::Pal => 50.007,
+        }
+    }
+
+    // scanlines in a frame, vblank starts on scanline 241 on both
+    pub fn scanlines(&self) -> u16 {
+        match self {
+            Region::Ntsc => 262,
+            Region::Pal => 312,
+        }
+    }
+
+    // PPU dots run during CPU cycle number cycle, NTSC runs 3 per cycle and
+    // PAL 3.2, which comes out as 16 dots every 5 cycles
+    pub fn dots_in_cycle(&self, cycle: u64) -> u64 {
+        match self {
+            Region::Ntsc => 3,
+            Region::Pal => (cycle + 1) * 16 / 5 - cycle * 16 / 5,
+        }
+    }
+}
+
+const MASK_GRAYSCALE: u8 = 0x01;
+// each emphasis bit dims the two other channels to roughly this fraction
+const ATTENUATION: f32 = 0.816;
+
+// PPUMASK bits 5-7 in (red, green, blue) order, the PAL PPU swaps red and green
+fn emphasis(mask: u8, region: Region) -> (bool, bool, bool) {
+    let bit5 = mask & 0x20 != 0;
+    let bit6 = mask & 0x40 != 0;
+    let bit7 = mask & 0x80 != 0;
+    match region {
+        Region::Ntsc => (bit5, bit6, bit7),
+        Region::Pal => (bit6, bit5, bit7),
+    }
+}
+
+// Turn a palette RAM entry into RGB the way the PPU outputs it under PPUMASK.
+pub fn lookup(index: u8, mask: u8, region: Region) -> (u8, u8, u8) {
+    let mut index = index & 0x3F;
+    if mask & MASK_GRAYSCALE != 0 {
+        // grayscale keeps only the brightness column
+        index &= 0x30;
+    }
+    let (r, g, b) = SYSTEM_PALETTE[index as usize];
+    let (emphasize_r, emphasize_g, emphasize_b) = emphasis(mask, region);
+    // a channel is dimmed once for every other channel emphasized, so all three bits darken everything
+    let dim = |value: u8, others: [bool; 2]| {
+        let times = others.iter().filter(|&&on| on).count() as i32;
+        (value as f32 * ATTENUATION.powi(times)) as u8
+    };
+    (
+        dim(r, [emphasize_g, emphasize_b]),
+        dim(g, [emphasize_r, emphasize_b]),
+        dim(b, [emphasize_r, emphasize_g]),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grayscale_keeps_the_brightness_column() {
+        assert_eq!(lookup(0x16, MASK_GRAYSCALE, Region::Ntsc), SYSTEM_PALETTE[0x10]);
+        assert_eq!(lookup(0x2A, MASK_GRAYSCALE, Region::Ntsc), SYSTEM_PALETTE[0x20]);
+        // only the low six bits index the palette
+        assert_eq!(lookup(0x56, 0, Region::Ntsc), SYSTEM_PALETTE[0x16]);
+    }
+
+    #[test]
+    fn emphasis_dims_the_other_channels() {
+        let (r, g, b) = SYSTEM_PALETTE[0x30];
+        let dim = |value: u8| (value as f32 * ATTENUATION) as u8;
+        let dim2 = |value: u8| (value as f32 * ATTENUATION * ATTENUATION) as u8;
+        assert_eq!(lookup(0x30, 0x00, Region::Ntsc), (r, g, b));
+        assert_eq!(lookup(0x30, 0x20, Region::Ntsc), (r, dim(g), dim(b)));
+        assert_eq!(lookup(0x30, 0x80, Region::Ntsc), (dim(r), dim(g), b));
+        // two bits multiply on the channel neither of them emphasizes
+        assert_eq!(lookup(0x30, 0x60, Region::Ntsc), (dim(r), dim(g), dim2(b)));
+        // all three darken every channel
+        assert_eq!(lookup(0x30, 0xE0, Region::Ntsc), (dim2(r), dim2(g), dim2(b)));
+        // bit 5 is green and bit 6 red on PAL, blue stays on bit 7
+        assert_eq!(lookup(0x30, 0x20, Region::Pal), (dim(r), g, dim(b)));
+        assert_eq!(lookup(0x30, 0x40, Region::Pal), (r, dim(g), dim(b)));
+        assert_eq!(lookup(0x30, 0xA0, Region::Pal), (dim2(r), dim(g), dim(b)));
+        assert_eq!(lookup(0x30, 0xE0, Region::Pal), lookup(0x30, 0xE0, Region::Ntsc));
+        // grayscale applies before emphasis
+        assert_eq!(lookup(0x36, 0x41, Region::Ntsc), lookup(0x30, 0x40, Region::Ntsc));
+    }
+}
@@ -0,0 +1,674 @@
+use crate::palette::{self, Region};
+
+/* PPU Registers, mirrored every 8 bytes through 0x3FFF
+    0x2000 PPUCTRL   write
+    0x2001 PPUMASK   write
+    0x2002 PPUSTATUS read
+    0x2003 OAMADDR   write
+    0x2004 OAMDATA   read/write
+    0x2005 PPUSCROLL write x2
+    0x2006 PPUADDR   write x2
+    0x2007 PPUDATA   read/write
+*/
+
+// NTSC frame is 262 scanlines of 341 dots and PAL 312, vblank starts on scanline 241
+// and the last scanline of the frame is the pre-render line
+pub const DOTS_PER_SCANLINE: u16 = 341;
+pub const VBLANK_SCANLINE: u16 = 241;
+
+const STATUS_VBLANK: u8 = 0x80;
+const STATUS_SPRITE_OVERFLOW: u8 = 0x20;
+const CTRL_NMI_ENABLE: u8 = 0x80;
+const CTRL_SPRITE_16: u8 = 0x20;
+const CTRL_INCREMENT_32: u8 = 0x04;
+// sprites the hardware draws on one scanline
+pub const SPRITES_PER_LINE: usize = 8;
+// show background / show sprites
+const MASK_RENDERING: u8 = 0x18;
+const MASK_BACKGROUND: u8 = 0x08;
+const MASK_SPRITES: u8 = 0x10;
+// background / sprites also in the leftmost 8 pixels
+const MASK_LEFT_BACKGROUND: u8 = 0x02;
+const MASK_LEFT_SPRITES: u8 = 0x04;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Mirroring {
+    Horizontal,
+    Vertical,
+}
+
+pub struct Ppu {
+    pub ctrl: u8,
+    pub mask: u8,
+    pub status: u8,
+    pub scanline: u16,
+    pub dot: u16,
+    pub frame: u64,
+    // first/second write toggle shared by $2005 and $2006
+    pub write_latch: bool,
+    // last value driven on the PPU data bus, write-only registers read back as this
+    pub open_bus: u8,
+    pub nmi_pending: bool,
+    // $2002 was read the dot before vblank, so the flag stays clear this frame
+    pub suppress_vblank: bool,
+    // current and temporary VRAM address (loopy v and t) and fine x scroll
+    pub v: u16,
+    pub t: u16,
+    pub fine_x: u8,
+    // $2007 reads lag one behind, this holds the byte the next read returns
+    pub read_buffer: u8,
+    pub mirroring: Mirroring,
+    // pattern tables, CHR-ROM is copied in at load and treated as RAM
+    pub chr: [u8; 0x2000],
+    // two physical nametables, mirrored into four by the cartridge wiring
+    pub nametables: [u8; 0x800],
+    pub palette: [u8; 32],
+    pub region: Region,
+    // sprite memory, four bytes per sprite: Y, tile, attributes, X
+    pub oam: [u8; 256],
+    pub oam_addr: u8,
+    // OAM indexes of the sprites found for the next scanline
+    pub line_sprites: Vec<u8>,
+    // false keeps every sprite on a line instead of the first eight, the
+    // overflow flag still goes up so games that poll it behave the same
+    pub sprite_limit: bool,
+    // idle scanlines run after the post-render line before vblank, the CPU
+    // gets the extra time while the picture and vblank stay as they were
+    pub overclock_lines: u16,
+    // of those, how many this frame has already run
+    pub idle_lines: u16,
+}
+
+impl Default for Ppu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Ppu {
+    pub fn new() -> Self {
+        Ppu {
+            ctrl: 0,
+            mask: 0,
+            status: 0,
+            scanline: 0,
+            dot: 0,
+            frame: 0,
+            write_latch: false,
+            open_bus: 0,
+            nmi_pending: false,
+            suppress_vblank: false,
+            v: 0,
+            t: 0,
+            fine_x: 0,
+            read_buffer: 0,
+            mirroring: Mirroring::Horizontal,
+            chr: [0; 0x2000],
+            nametables: [0; 0x800],
+            palette: [0; 32],
+            region: Region::Ntsc,
+            oam: [0; 256],
+            oam_addr: 0,
+            line_sprites: Vec::new(),
+            sprite_limit: true,
+            overclock_lines: 0,
+            idle_lines: 0,
+        }
+    }
+
+    // Power switch with the same cartridge in: CHR, the nametable wiring and
+    // the region come from the ROM and the overclock and sprite limit from the
+    // user, everything else starts over.
+    pub fn power_on(&mut self) {
+        let chr = self.chr;
+        let mirroring = self.mirroring;
+        let region = self.region;
+        let overclock_lines = self.overclock_lines;
+        let sprite_limit = self.sprite_limit;
+        *self = Ppu::new();
+        self.chr = chr;
+        self.mirroring = mirroring;
+        self.region = region;
+        self.overclock_lines = overclock_lines;
+        self.sprite_limit = sprite_limit;
+    }
+
+    // The reset line clears the control, mask and $2005/$2006 latch and the
+    // read buffer, the rest of the chip keeps its state and keeps counting.
+    pub fn reset(&mut self) {
+        self.ctrl = 0;
+        self.mask = 0;
+        self.write_latch = false;
+        self.read_buffer = 0;
+    }
+
+    // Advance one dot, returns true when a new frame starts.
+    pub fn step(&mut self) -> bool {
+        if self.scanline == VBLANK_SCANLINE && self.dot == 1 {
+            if !self.suppress_vblank {
+                self.status |= STATUS_VBLANK;
+                if self.ctrl & CTRL_NMI_ENABLE != 0 {
+                    self.nmi_pending = true;
+                }
+            }
+            self.suppress_vblank = false;
+        }
+        let pre_render = self.region.scanlines() - 1;
+        if self.scanline == pre_render && self.dot == 1 {
+            // vblank, sprite 0 hit and sprite overflow all clear here
+            self.status &= 0x1F;
+        }
+        if self.rendering() {
+            self.step_scroll();
+            if self.scanline < VBLANK_SCANLINE - 1 && self.dot == 257 {
+                self.evaluate_sprites();
+            }
+            // odd NTSC frames drop the last pre-render dot while rendering is on, PAL never does
+            if self.region == Region::Ntsc && self.scanline == pre_render && self.dot == 339 && self.frame % 2 == 1 {
+                self.dot = 340;
+            }
+        }
+        self.dot += 1;
+        if self.dot == DOTS_PER_SCANLINE {
+            self.dot = 0;
+            // the post-render line repeats once per overclock line
+            if self.scanline == VBLANK_SCANLINE - 1 && self.idle_lines < self.overclock_lines {
+                self.idle_lines += 1;
+                return false;
+            }
+            self.idle_lines = 0;
+            self.scanline += 1;
+            if self.scanline == self.region.scanlines() {
+                self.scanline = 0;
+                self.frame += 1;
+                return true;
+            }
+        }
+        false
+    }
+
+    pub fn read_register(&mut self, register: u16) -> u8 {
+        match register & 0x7 {
+            2 => self.read_status(),
+            4 => {
+                // attribute bits 2-4 do not exist and read back as 0
+                let value = self.oam[self.oam_addr as usize];
+                self.open_bus = if self.oam_addr & 3 == 2 { value & 0xE3 } else { value };
+                self.open_bus
+            }
+            7 => self.read_data(),
+            _ => self.open_bus,
+        }
+    }
+
+    pub fn write_register(&mut self, register: u16, value: u8) {
+        self.open_bus = value;
+        match register & 0x7 {
+            0 => {
+                let was_enabled = self.ctrl & CTRL_NMI_ENABLE != 0;
+                self.ctrl = value;
+                // nametable select lands in t
+                self.t = (self.t & !0x0C00) | ((value as u16 & 0x3) << 10);
+                // turning NMI on while the vblank flag is still up fires one right away
+                if !was_enabled && value & CTRL_NMI_ENABLE != 0 && self.status & STATUS_VBLANK != 0 {
+                    self.nmi_pending = true;
+                }
+            }
+            1 => self.mask = value,
+            3 => self.oam_addr = value,
+            4 => {
+                self.oam[self.oam_addr as usize] = value;
+                self.oam_addr = self.oam_addr.wrapping_add(1);
+            }
+            5 => {
+                if !self.write_latch {
+                    self.t = (self.t & !0x001F) | (value as u16 >> 3);
+                    self.fine_x = value & 0x7;
+                } else {
+                    self.t = (self.t & !0x73E0) | ((value as u16 & 0x7) << 12) | ((value as u16 >> 3) << 5);
+                }
+                self.write_latch = !self.write_latch;
+            }
+            6 => {
+                if !self.write_latch {
+                    // high byte, bit 14 is cleared
+                    self.t = (self.t & 0x00FF) | ((value as u16 & 0x3F) << 8);
+                } else {
+                    self.t = (self.t & 0xFF00) | value as u16;
+                    self.v = self.t;
+                }
+                self.write_latch = !self.write_latch;
+            }
+            7 => {
+                self.write_vram(self.v, value);
+                self.increment_v();
+            }
+            _ => {}
+        }
+    }
+
+    // $4014: a page of CPU memory copied through OAMDATA, starting at OAMADDR.
+    pub fn oam_dma(&mut self, page: &[u8]) {
+        for &value in page {
+            self.oam[self.oam_addr as usize] = value;
+            self.oam_addr = self.oam_addr.wrapping_add(1);
+        }
+    }
+
+    // Secondary OAM: the sprites whose Y range covers the next scanline, in
+    // OAM order. A ninth one sets the overflow flag. The hardware's buggy
+    // overflow search that also misfires on some lines is not modelled.
+    fn evaluate_sprites(&mut self) {
+        let height = if self.ctrl & CTRL_SPRITE_16 != 0 { 16 } else { 8 };
+        self.line_sprites.clear();
+        for sprite in 0..64 {
+            let y = self.oam[sprite * 4] as u16;
+            if self.scanline.wrapping_sub(y) >= height {
+                continue;
+            }
+            if self.line_sprites.len() == SPRITES_PER_LINE {
+                self.status |= STATUS_SPRITE_OVERFLOW;
+                if self.sprite_limit {
+                    break;
+                }
+            }
+            self.line_sprites.push(sprite as u8);
+        }
+    }
+
+    fn read_status(&mut self) -> u8 {
+        if self.scanline == VBLANK_SCANLINE {
+            // the flag goes up while dot 1 is processed, reads land before the PPU steps
+            match self.dot {
+                // just before the set: reads clear and the flag never goes up this frame
+                0 | 1 => self.suppress_vblank = true,
+                // right after the set: reads set but the NMI is lost
+                2 | 3 => self.nmi_pending = false,
+                _ => {}
+            }
+        }
+        // low five bits are whatever was last on the bus
+        let value = (self.status & 0xE0) | (self.open_bus & 0x1F);
+        self.status &= !STATUS_VBLANK;
+        self.write_latch = false;
+        self.open_bus = value;
+        value
+    }
+
+    // RGB for a palette RAM entry (0-31) after grayscale and color emphasis
+    pub fn color(&self, entry: usize) -> (u8, u8, u8) {
+        palette::lookup(self.palette[Ppu::palette_index(entry as u16)], self.mask, self.region)
+    }
+
+    // The background fetches walk v across the screen. They only happen with
+    // PPUMASK bits 3 or 4 set, so turning rendering off mid-frame freezes v
+    // wherever it got to, which is what games toggling it for status bars rely on.
+    fn step_scroll(&mut self) {
+        let fetching = (1..=256).contains(&self.dot) || (328..=336).contains(&self.dot);
+        if fetching && self.dot.is_multiple_of(8) {
+            self.increment_coarse_x();
+        }
+        if self.dot == 256 {
+            self.increment_y();
+        }
+        if self.dot == 257 {
+            // horizontal bits come back from t for the next line
+            self.v = (self.v & !0x041F) | (self.t & 0x041F);
+        }
+        if self.scanline == self.region.scanlines() - 1 && (280..=304).contains(&self.dot) {
+            self.v = (self.v & !0x7BE0) | (self.t & 0x7BE0);
+        }
+    }
+
+    fn rendering(&self) -> bool {
+        self.mask & MASK_RENDERING != 0 && (self.scanline < 240 || self.scanline == self.region.scanlines() - 1)
+    }
+
+    // Whether the background shows at pixel x of a line, bit 1 off hides it
+    // in the leftmost 8 pixels.
+    pub fn shows_background(&self, x: u16) -> bool {
+        self.mask & MASK_BACKGROUND != 0 && (x >= 8 || self.mask & MASK_LEFT_BACKGROUND != 0)
+    }
+
+    // Same for sprites with bit 2.
+    pub fn shows_sprites(&self, x: u16) -> bool {
+        self.mask & MASK_SPRITES != 0 && (x >= 8 || self.mask & MASK_LEFT_SPRITES != 0)
+    }
+
+    // "background on, sprites on (left 8 hidden)"
+    pub fn mask_text(&self) -> String {
+        let layer = |shown: fn(&Ppu, u16) -> bool| match (shown(self, 8), shown(self, 0)) {
+            (false, _) => "off",
+            (true, true) => "on",
+            (true, false) => "on (left 8 hidden)",
+        };
+        format!("background {}, sprites {}", layer(Ppu::shows_background), layer(Ppu::shows_sprites))
+    }
+
+    fn read_data(&mut self) -> u8 {
+        let address = self.v & 0x3FFF;
+        let value = if address >= 0x3F00 {
+            // palette reads skip the buffer, which picks up the nametable byte underneath
+            self.read_buffer = self.read_vram(address - 0x1000);
+            (self.read_vram(address) & 0x3F) | (self.open_bus & 0xC0)
+        } else {
+            let value = self.read_buffer;
+            self.read_buffer = self.read_vram(address);
+            value
+        };
+        self.increment_v();
+        self.open_bus = value;
+        value
+    }
+
+    // +1 or +32 per PPUCTRL bit 2, but while rendering the access bumps
+    // coarse x and y together like the render pipeline does
+    fn increment_v(&mut self) {
+        if self.rendering() {
+            self.increment_coarse_x();
+            self.increment_y();
+        } else if self.ctrl & CTRL_INCREMENT_32 != 0 {
+            self.v = self.v.wrapping_add(32) & 0x7FFF;
+        } else {
+            self.v = self.v.wrapping_add(1) & 0x7FFF;
+        }
+    }
+
+    fn increment_coarse_x(&mut self) {
+        if self.v & 0x001F == 31 {
+            self.v &= !0x001F;
+            self.v ^= 0x0400;
+        } else {
+            self.v += 1;
+        }
+    }
+
+    fn increment_y(&mut self) {
+        if self.v & 0x7000 != 0x7000 {
+            self.v += 0x1000;
+            return;
+        }
+        self.v &= !0x7000;
+        let mut coarse_y = (self.v & 0x03E0) >> 5;
+        if coarse_y == 29 {
+            coarse_y = 0;
+            self.v ^= 0x0800;
+        } else if coarse_y == 31 {
+            coarse_y = 0;
+        } else {
+            coarse_y += 1;
+        }
+        self.v = (self.v & !0x03E0) | (coarse_y << 5);
+    }
+
+    fn nametable_index(&self, address: u16) -> usize {
+        let table = (address >> 10) & 0x3;
+        let offset = (address & 0x03FF) as usize;
+        let physical = match self.mirroring {
+            Mirroring::Horizontal => table >> 1,
+            Mirroring::Vertical => table & 1,
+        };
+        physical as usize * 0x400 + offset
+    }
+
+    fn palette_index(address: u16) -> usize {
+        let index = (address & 0x1F) as usize;
+        // sprite backdrop entries mirror the background ones
+        if index >= 16 && index.is_multiple_of(4) {
+            index - 16
+        } else {
+            index
+        }
+    }
+
+    pub fn read_vram(&self, address: u16) -> u8 {
+        let address = address & 0x3FFF;
+        match address {
+            0x0000..=0x1FFF => self.chr[address as usize],
+            0x2000..=0x3EFF => self.nametables[self.nametable_index(address)],
+            _ => self.palette[Ppu::palette_index(address)],
+        }
+    }
+
+    pub fn write_vram(&mut self, address: u16, value: u8) {
+        let address = address & 0x3FFF;
+        match address {
+            0x0000..=0x1FFF => self.chr[address as usize] = value,
+            0x2000..=0x3EFF => {
+                let index = self.nametable_index(address);
+                self.nametables[index] = value;
+            }
+            _ => self.palette[Ppu::palette_index(address)] = value,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // dots from the start of a frame to the start of the next
+    fn frame_dots(ppu: &mut Ppu) -> u32 {
+        let mut dots = 1;
+        while !ppu.step() {
+            dots += 1;
+        }
+        dots
+    }
+
+    #[test]
+    fn pal_frames_are_312_scanlines() {
+        let mut ppu = Ppu::new();
+        assert_eq!(frame_dots(&mut ppu), 262 * 341);
+        ppu.region = Region::Pal;
+        assert_eq!(frame_dots(&mut ppu), 312 * 341);
+        // 3.2 dots per CPU cycle
+        assert_eq!((0..5).map(|c| Region::Pal.dots_in_cycle(c)).sum::<u64>(), 16);
+    }
+
+    #[test]
+    fn overclock_lines_run_before_vblank() {
+        let mut ppu = Ppu::new();
+        ppu.overclock_lines = 10;
+        assert_eq!(frame_dots(&mut ppu), 272 * 341);
+        // vblank still starts on scanline 241, after the idle lines
+        let mut dots = 0;
+        while ppu.status & STATUS_VBLANK == 0 {
+            ppu.step();
+            dots += 1;
+        }
+        assert_eq!(ppu.scanline, VBLANK_SCANLINE);
+        assert_eq!(dots, (241 + 10) * 341 + 2);
+    }
+
+    // step until the next dot to be processed is scanline/dot
+    fn run_to(ppu: &mut Ppu, scanline: u16, dot: u16) {
+        while (ppu.scanline, ppu.dot) != (scanline, dot) {
+            ppu.step();
+        }
+    }
+
+    #[test]
+    fn status_read_clears_vblank_and_the_latch() {
+        let mut ppu = Ppu::new();
+        ppu.ctrl = CTRL_NMI_ENABLE;
+        run_to(&mut ppu, 250, 0);
+        assert!(ppu.nmi_pending);
+        ppu.write_register(6, 0x21);
+        ppu.open_bus = 0x1F;
+        assert_eq!(ppu.read_register(2), 0x9F);
+        assert!(!ppu.write_latch);
+        assert_eq!(ppu.read_register(2) & STATUS_VBLANK, 0);
+    }
+
+    #[test]
+    fn status_read_just_before_vblank_suppresses_it() {
+        let mut ppu = Ppu::new();
+        ppu.ctrl = CTRL_NMI_ENABLE;
+        run_to(&mut ppu, VBLANK_SCANLINE, 1);
+        assert_eq!(ppu.read_register(2) & STATUS_VBLANK, 0);
+        run_to(&mut ppu, 250, 0);
+        assert_eq!(ppu.status & STATUS_VBLANK, 0);
+        assert!(!ppu.nmi_pending);
+    }
+
+    #[test]
+    fn status_read_just_after_vblank_cancels_the_nmi() {
+        let mut ppu = Ppu::new();
+        ppu.ctrl = CTRL_NMI_ENABLE;
+        run_to(&mut ppu, VBLANK_SCANLINE, 2);
+        assert_ne!(ppu.read_register(2) & STATUS_VBLANK, 0);
+        assert!(!ppu.nmi_pending);
+    }
+
+    fn set_address(ppu: &mut Ppu, address: u16) {
+        ppu.write_register(6, (address >> 8) as u8);
+        ppu.write_register(6, address as u8);
+    }
+
+    #[test]
+    fn data_reads_lag_one_behind_the_buffer() {
+        let mut ppu = Ppu::new();
+        ppu.write_vram(0x2000, 0x11);
+        ppu.write_vram(0x2001, 0x22);
+        set_address(&mut ppu, 0x2000);
+        // the first read returns the stale buffer
+        assert_eq!(ppu.read_register(7), 0x00);
+        assert_eq!(ppu.read_register(7), 0x11);
+        assert_eq!(ppu.read_register(7), 0x22);
+    }
+
+    #[test]
+    fn palette_reads_skip_the_buffer() {
+        let mut ppu = Ppu::new();
+        ppu.write_vram(0x3F01, 0x2C);
+        ppu.write_vram(0x2F01, 0x99);
+        set_address(&mut ppu, 0x3F01);
+        ppu.open_bus = 0xC0;
+        // top two bits come from open bus
+        assert_eq!(ppu.read_register(7), 0xEC);
+        // the buffer picked up the nametable byte under the palette
+        assert_eq!(ppu.read_buffer, 0x99);
+    }
+
+    #[test]
+    fn data_access_increments_by_1_or_32() {
+        let mut ppu = Ppu::new();
+        set_address(&mut ppu, 0x2000);
+        ppu.write_register(7, 0xAA);
+        assert_eq!(ppu.v, 0x2001);
+        ppu.write_register(0, CTRL_INCREMENT_32);
+        ppu.write_register(7, 0xBB);
+        assert_eq!(ppu.v, 0x2021);
+        ppu.read_register(7);
+        assert_eq!(ppu.v, 0x2041);
+        assert_eq!((ppu.read_vram(0x2000), ppu.read_vram(0x2001)), (0xAA, 0xBB));
+    }
+
+    #[test]
+    fn sprite_backdrop_entries_mirror_the_background() {
+        let mut ppu = Ppu::new();
+        for (mirror, entry) in [(0x3F10, 0x3F00), (0x3F14, 0x3F04), (0x3F18, 0x3F08), (0x3F1C, 0x3F0C)] {
+            ppu.write_vram(mirror, mirror as u8);
+            assert_eq!(ppu.read_vram(entry), mirror as u8);
+        }
+        ppu.write_vram(0x3F11, 0x05);
+        assert_ne!(ppu.read_vram(0x3F01), 0x05);
+        // palette RAM repeats every 32 bytes up to $3FFF
+        assert_eq!(ppu.read_vram(0x3FF1), 0x05);
+    }
+
+    // run to dot 258 of scanline, after sprite evaluation for the line below it
+    fn evaluated(ppu: &mut Ppu, scanline: u16) {
+        while !(ppu.scanline == scanline && ppu.dot == 258) {
+            ppu.step();
+        }
+    }
+
+    #[test]
+    fn a_ninth_sprite_is_dropped_and_flags_overflow() {
+        let mut ppu = Ppu::new();
+        ppu.mask = 0x10;
+        // ten sprites at Y 20, one at 40
+        for sprite in 0..10 {
+            ppu.oam[sprite * 4] = 20;
+        }
+        ppu.oam[10 * 4] = 40;
+        for sprite in 11..64 {
+            ppu.oam[sprite * 4] = 0xF0;
+        }
+        evaluated(&mut ppu, 24);
+        assert_eq!(ppu.line_sprites, (0..8).collect::<Vec<u8>>());
+        assert_eq!(ppu.status & STATUS_SPRITE_OVERFLOW, STATUS_SPRITE_OVERFLOW);
+        // eight pixel sprites end after 8 lines, 16 pixel ones reach line 40
+        evaluated(&mut ppu, 28);
+        assert!(ppu.line_sprites.is_empty());
+        ppu.ctrl = CTRL_SPRITE_16;
+        evaluated(&mut ppu, 35);
+        assert_eq!(ppu.line_sprites.len(), 8);
+        evaluated(&mut ppu, 40);
+        assert_eq!(ppu.line_sprites, vec![10]);
+    }
+
+    #[test]
+    fn without_the_limit_every_sprite_stays_and_overflow_still_sets() {
+        let mut ppu = Ppu::new();
+        ppu.mask = 0x10;
+        ppu.sprite_limit = false;
+        ppu.oam = [0xF0; 256];
+        for sprite in 0..12 {
+            ppu.oam[sprite * 4] = 100;
+        }
+        evaluated(&mut ppu, 100);
+        assert_eq!(ppu.line_sprites.len(), 12);
+        assert_eq!(ppu.status & STATUS_SPRITE_OVERFLOW, STATUS_SPRITE_OVERFLOW);
+        // the limit is the user's and survives a power cycle
+        ppu.power_on();
+        assert!(!ppu.sprite_limit);
+    }
+
+    #[test]
+    fn oamaddr_oamdata_and_dma_fill_sprite_memory() {
+        let mut ppu = Ppu::new();
+        ppu.write_register(0x2003, 0xFE);
+        ppu.write_register(0x2004, 0x11);
+        ppu.write_register(0x2004, 0x22);
+        // the address wraps
+        assert_eq!((ppu.oam[0xFE], ppu.oam[0xFF], ppu.oam_addr), (0x11, 0x22, 0x00));
+        ppu.write_register(0x2003, 0x02);
+        ppu.oam_dma(&[0xFF; 256]);
+        assert_eq!(ppu.oam_addr, 0x02);
+        // attribute bytes read back without bits 2-4
+        assert_eq!(ppu.read_register(0x2004), 0xE3);
+        ppu.write_register(0x2003, 0x01);
+        assert_eq!(ppu.read_register(0x2004), 0xFF);
+    }
+
+    #[test]
+    fn left_column_bits_clip_the_first_8_pixels() {
+        let mut ppu = Ppu::new();
+        ppu.mask = MASK_RENDERING;
+        assert!(!ppu.shows_background(7) && !ppu.shows_sprites(0));
+        assert!(ppu.shows_background(8) && ppu.shows_sprites(255));
+        ppu.mask = MASK_BACKGROUND | MASK_LEFT_BACKGROUND | MASK_LEFT_SPRITES;
+        assert!(ppu.shows_background(0));
+        // the left column bit alone does not turn sprites on
+        assert!(!ppu.shows_sprites(0) && !ppu.shows_sprites(8));
+        assert_eq!(ppu.mask_text(), "background on, sprites off");
+        ppu.mask = MASK_RENDERING | MASK_LEFT_BACKGROUND;
+        assert_eq!(ppu.mask_text(), "background on, sprites on (left 8 hidden)");
+    }
+
+    #[test]
+    fn no_sprite_evaluation_with_rendering_off() {
+        let mut ppu = Ppu::new();
+        ppu.mask = MASK_BACKGROUND;
+        evaluated(&mut ppu, 3);
+        assert_eq!(ppu.line_sprites.len(), 8);
+        // turning both bits off mid-frame stops evaluation where it was
+        ppu.mask = 0;
+        ppu.oam = [0xF0; 256];
+        evaluated(&mut ppu, 4);
+        assert_eq!((ppu.line_sprites.len(), ppu.status & STATUS_SPRITE_OVERFLOW), (8, STATUS_SPRITE_OVERFLOW));
+    }
+}
@@ -0,0 +1,151 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::netplay;
+use crate::savestate::{self, Metadata, SaveStateError};
+use crate::Emulator;
+
+// Named save states for practicing one section of a game over and over.
+pub struct Practice {
+    slots: BTreeMap<String, Vec<u8>>,
+    // slot loaded or saved most recently, what a plain reload goes back to
+    pub last: Option<String>,
+    // when set slots are also written to <dir>/<name>.state and read back from there
+    pub dir: Option<String>,
+    // RAM bytes scrambled after every load so the game's RNG differs per attempt
+    pub rng: Vec<u16>,
+    // frame the current attempt started on
+    pub start_frame: u64,
+    pub seed: u64,
+}
+
+// A slot as a load picker shows it.
+pub struct SlotInfo {
+    pub name: String,
+    // None for slots saved before states carried metadata
+    pub metadata: Option<Metadata>,
+}
+
+impl Practice {
+    pub fn new() -> Self {
+        let seed = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or(0);
+        Practice {
+            slots: BTreeMap::new(),
+            last: None,
+            dir: None,
+            rng: Vec::new(),
+            start_frame: 0,
+            // xorshift gets stuck on zero
+            seed: seed | 1,
+        }
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.slots.keys().cloned().collect();
+        if let Some(dir) = self.dir.as_ref() {
+            for entry in fs::read_dir(dir).into_iter().flatten().flatten() {
+                let path = entry.path();
+                if path.extension().is_some_and(|e| e == "state") {
+                    if let Some(stem) = path.file_stem() {
+                        let name = stem.to_string_lossy().to_string();
+                        if !names.contains(&name) {
+                            names.push(name);
+                        }
+                    }
+                }
+            }
+        }
+        names.sort();
+        names
+    }
+
+    // Every slot with its metadata, sorted by name.
+    pub fn slots(&self) -> Vec<SlotInfo> {
+        self.names()
+            .into_iter()
+            .map(|name| {
+                let metadata = match (self.slots.get(&name), self.slot_path(&name).ok().flatten()) {
+                    (Some(data), _) => savestate::metadata(data),
+                    (None, Some(path)) => fs::read(path).ok().and_then(|data| savestate::metadata(&data)),
+                    (None, None) => None,
+                };
+                SlotInfo { name, metadata }
+            })
+            .collect()
+    }
+
+    // A name that could climb out of the slot directory is refused.
+    fn slot_path(&self, name: &str) -> io::Result<Option<String>> {
+        if name.is_empty() || name.contains(['/', '\\']) || name.contains("..") {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("bad slot name {:?}", name)));
+        }
+        Ok(self.dir.as_ref().map(|dir| Path::new(dir).join(format!("{}.state", name)).display().to_string()))
+    }
+
+    fn next_random(&mut self) -> u8 {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 7;
+        self.seed ^= self.seed << 17;
+        self.seed as u8
+    }
+}
+
+impl Default for Practice {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn save_slot(emulator: &mut Emulator, name: &str) -> Result<(), SaveStateError> {
+    let path = emulator.practice.slot_path(name)?;
+    let data = savestate::encode_with_metadata(emulator);
+    if let Some(path) = path {
+        fs::write(path, &data)?;
+    }
+    let practice = &mut emulator.practice;
+    practice.slots.insert(name.to_string(), data);
+    practice.last = Some(name.to_string());
+    practice.start_frame = emulator.ppu.frame;
+    Ok(())
+}
+
+// Load the named slot, or the last one used, and return the name that was loaded.
+pub fn load_slot(emulator: &mut Emulator, name: Option<&str>) -> Result<String, SaveStateError> {
+    netplay::refuse_jump(emulator, "load a slot")?;
+    let practice = &mut emulator.practice;
+    let name = match name.map(|n| n.to_string()).or_else(|| practice.last.clone()) {
+        Some(name) => name,
+        None => return Err(io::Error::new(io::ErrorKind::NotFound, "no slot saved yet").into()),
+    };
+    let data = match (practice.slots.get(&name), practice.slot_path(&name)?) {
+        (Some(data), _) => data.clone(),
+        (None, Some(path)) => fs::read(path)?,
+        (None, None) => return Err(io::Error::new(io::ErrorKind::NotFound, format!("no slot named {}", name)).into()),
+    };
+    savestate::decode_into(emulator, &data)?;
+    let practice = &mut emulator.practice;
+    for address in practice.rng.clone() {
+        emulator.memory[address as usize] = practice.next_random();
+    }
+    practice.last = Some(name.clone());
+    practice.start_frame = emulator.ppu.frame;
+    Ok(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slot_names_stay_inside_the_slot_directory() {
+        let mut practice = Practice::new();
+        practice.dir = Some("slots".to_string());
+        for name in ["", "../../x", "a/b", "a\\b", ".."] {
+            assert!(practice.slot_path(name).is_err(), "{:?} accepted", name);
+        }
+        let path = practice.slot_path("level-2").unwrap().unwrap();
+        assert_eq!(Path::new(&path), Path::new("slots").join("level-2.state"));
+    }
+}
@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+use crate::disasm::disassemble;
+use crate::disasm::decode;
+
+#[derive(Default, Clone, Copy)]
+pub struct Counter {
+    pub executions: u64,
+    pub cycles: u64,
+}
+
+// Counts executions and cycles per opcode and per PRG address.
+pub struct Profiler {
+    pub opcodes: [Counter; 256],
+    pub addresses: HashMap<u16, Counter>,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Profiler {
+            opcodes: [Counter::default(); 256],
+            addresses: HashMap::new(),
+        }
+    }
+
+    pub fn record(&mut self, address: u16, opcode: u8, cycles: u8) {
+        let op = &mut self.opcodes[opcode as usize];
+        op.executions += 1;
+        op.cycles += cycles as u64;
+        let at = self.addresses.entry(address).or_default();
+        at.executions += 1;
+        at.cycles += cycles as u64;
+    }
+
+    pub fn total_cycles(&self) -> u64 {
+        self.opcodes.iter().map(|c| c.cycles).sum()
+    }
+
+    // Hot spot report: opcodes by cycles spent and the top N addresses with disassembly.
+    pub fn report(&self, memory: &[u8], top: usize) -> String {
+        let total = self.total_cycles().max(1);
+        let mut out = String::new();
+        out.push_str("----- Opcode Profile -------\n");
+        out.push_str("OP  NAME   EXECUTIONS       CYCLES      %\n");
+        let mut opcodes: Vec<(usize, &Counter)> = self.opcodes.iter().enumerate().filter(|(_, c)| c.executions > 0).collect();
+        opcodes.sort_by_key(|(_, c)| std::cmp::Reverse(c.cycles));
+        for (opcode, counter) in opcodes {
+            let name = decode(opcode as u8).map(|(mnemonic, _)| mnemonic).unwrap_or("???");
+            out.push_str(&format!("{:02X}  {:<4} {:>12} {:>12} {:>6.2}\n", opcode, name, counter.executions, counter.cycles, counter.cycles as f64 * 100.0 / total as f64));
+        }
+        out.push_str(&format!("----- Top {} Addresses -------\n", top));
+        out.push_str("ADDR   EXECUTIONS       CYCLES      %  INSTRUCTION\n");
+        let mut addresses: Vec<(&u16, &Counter)> = self.addresses.iter().collect();
+        addresses.sort_by(|a, b| b.1.cycles.cmp(&a.1.cycles).then(a.0.cmp(b.0)));
+        for (address, counter) in addresses.into_iter().take(top) {
+            let (text, _) = disassemble(memory, *address);
+            out.push_str(&format!("${:04X} {:>12} {:>12} {:>6.2}  {}\n", address, counter.executions, counter.cycles, counter.cycles as f64 * 100.0 / total as f64, text));
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // LDX #$01 at $8000, then a BNE back to it
+    fn profiled() -> (Profiler, Vec<u8>) {
+        let mut memory = vec![0u8; 0x10000];
+        memory[0x8000..0x8004].copy_from_slice(&[0xA2, 0x01, 0xD0, 0xFC]);
+        let mut profiler = Profiler::new();
+        for _ in 0..3 {
+            profiler.record(0x8000, 0xA2, 2);
+            profiler.record(0x8002, 0xD0, 3);
+        }
+        profiler.record(0x8002, 0xD0, 2);
+        (profiler, memory)
+    }
+
+    #[test]
+    fn counts_executions_and_cycles_per_opcode_and_address() {
+        let (profiler, _) = profiled();
+        let ldx = profiler.opcodes[0xA2];
+        let bne = profiler.opcodes[0xD0];
+        assert_eq!((ldx.executions, ldx.cycles), (3, 6));
+        assert_eq!((bne.executions, bne.cycles), (4, 11));
+        assert_eq!(profiler.opcodes.iter().filter(|c| c.executions > 0).count(), 2);
+        assert_eq!(profiler.addresses[&0x8002].cycles, 11);
+        assert_eq!(profiler.total_cycles(), 17);
+    }
+
+    #[test]
+    fn report_puts_the_most_cycles_first() {
+        let (profiler, memory) = profiled();
+        let report = profiler.report(&memory, 1);
+        let lines: Vec<&str> = report.lines().collect();
+        assert!(lines[2].starts_with("D0  BNE"), "{}", lines[2]);
+        assert!(lines[2].ends_with("64.71"), "{}", lines[2]);
+        assert!(lines[3].starts_with("A2  LDX"), "{}", lines[3]);
+        assert_eq!(lines[4], "----- Top 1 Addresses -------");
+        // only the top address makes it
+        assert!(lines[6].starts_with("$8002"), "{}", lines[6]);
+        assert!(lines[6].ends_with("BNE $8000"), "{}", lines[6]);
+        assert_eq!(lines.len(), 7);
+    }
+
+    #[test]
+    fn address_ties_sort_by_address() {
+        let mut profiler = Profiler::new();
+        profiler.record(0x9000, 0xEA, 2);
+        profiler.record(0x8000, 0xEA, 2);
+        let report = profiler.report(&vec![0xEA; 0x10000], 2);
+        let addresses: Vec<&str> = report.lines().skip(5).map(|line| &line[..5]).collect();
+        assert_eq!(addresses, ["$8000", "$9000"]);
+    }
+}
@@ -0,0 +1,144 @@
+use std::fs;
+use std::io;
+use crate::debugger::parse_hex;
+use crate::disasm::disassemble_named;
+use crate::watch::WatchType;
+
+/* RAM Map, one name per line, # starts a comment
+    player_x = $0086            a byte, read as u8
+    x_speed = $0057:s8          with a type like a watch, u8, s8 or u16
+    score = $07DD-$07E2         a range, addresses inside show as score+2
+   Names are what the debugger, watches, traces and guard reports show in
+   place of the address.
+*/
+
+pub struct Label {
+    pub name: String,
+    pub start: u16,
+    pub end: u16,
+    pub kind: WatchType,
+}
+
+#[derive(Default)]
+pub struct RamMap {
+    pub labels: Vec<Label>,
+}
+
+impl Label {
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let (name, target) = line.split_once('=').ok_or_else(|| format!("expected name = address, got {}", line))?;
+        let name = name.trim();
+        if name.is_empty() || name.contains(char::is_whitespace) || name.contains('+') || parse_hex(name).is_some() {
+            return Err(format!("bad name {:?}", name));
+        }
+        let (range, kind) = match target.trim().split_once(':') {
+            Some((range, kind)) => (range, WatchType::from_name(kind.trim()).ok_or_else(|| format!("unknown type {}, expected u8, s8 or u16", kind.trim()))?),
+            None => (target.trim(), WatchType::U8),
+        };
+        let (start, end) = match range.split_once('-') {
+            Some((start, end)) => (parse_hex(start.trim()), parse_hex(end.trim())),
+            None => (parse_hex(range.trim()), parse_hex(range.trim())),
+        };
+        let (start, end) = start.zip(end).filter(|(s, e)| s <= e).ok_or_else(|| format!("bad address {}", range.trim()))?;
+        Ok(Label {
+            name: name.to_string(),
+            start,
+            end,
+            kind,
+        })
+    }
+}
+
+impl RamMap {
+    pub fn load(path: &str) -> io::Result<Self> {
+        let mut map = RamMap::default();
+        for (number, line) in fs::read_to_string(path)?.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let label = Label::parse(line).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", number + 1, e)))?;
+            map.labels.push(label);
+        }
+        Ok(map)
+    }
+
+    pub fn find(&self, name: &str) -> Option<&Label> {
+        self.labels.iter().find(|l| l.name == name)
+    }
+
+    // The name of an address, with an offset when it is inside a range.
+    pub fn name(&self, address: u16) -> Option<String> {
+        let label = self.labels.iter().find(|l| (l.start..=l.end).contains(&address))?;
+        match address - label.start {
+            0 => Some(label.name.clone()),
+            offset => Some(format!("{}+{}", label.name, offset)),
+        }
+    }
+
+    // A name, name+offset or hex address typed by the user.
+    pub fn address(&self, text: &str) -> Option<u16> {
+        let (name, offset) = match text.split_once('+') {
+            Some((name, offset)) => (name, offset.parse::<u16>().ok()?),
+            None => (text, 0),
+        };
+        match self.find(name) {
+            Some(label) => Some(label.start.wrapping_add(offset)),
+            None => parse_hex(text),
+        }
+    }
+
+    // The instruction at address with its operand named.
+    pub fn disassemble(&self, memory: &[u8], address: u16) -> (String, u16) {
+        let read = |offset: u16| memory[address.wrapping_add(offset) as usize];
+        disassemble_named([read(0), read(1), read(2)], address, &|a| self.name(a))
+    }
+
+    // "$0086 (player_x)", or just the address when it has no name.
+    pub fn describe(&self, address: u16) -> String {
+        match self.name(address) {
+            Some(name) => format!("${:04X} ({})", address, name),
+            None => format!("${:04X}", address),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map() -> RamMap {
+        let lines = ["player_x = $0086", "x_speed = $0057:s8", "score = $07DD-$07E2"];
+        RamMap {
+            labels: lines.iter().map(|l| Label::parse(l).unwrap()).collect(),
+        }
+    }
+
+    #[test]
+    fn parses_bytes_types_and_ranges() {
+        let map = map();
+        assert_eq!(map.find("x_speed").map(|l| l.kind), Some(WatchType::S8));
+        let score = map.find("score").unwrap();
+        assert_eq!((score.start, score.end, score.kind), (0x7DD, 0x7E2, WatchType::U8));
+    }
+
+    #[test]
+    fn names_addresses_both_ways() {
+        let map = map();
+        assert_eq!(map.name(0x86).as_deref(), Some("player_x"));
+        assert_eq!(map.name(0x7DF).as_deref(), Some("score+2"));
+        assert_eq!(map.name(0x87), None);
+        assert_eq!(map.address("score+2"), Some(0x7DF));
+        assert_eq!(map.address("player_x"), Some(0x86));
+        assert_eq!(map.address("$0300"), Some(0x300));
+        assert_eq!(map.describe(0x86), "$0086 (player_x)");
+    }
+
+    #[test]
+    fn rejects_bad_labels() {
+        // a name that reads as hex would shadow addresses typed in the debugger
+        for line in ["player_x $0086", "beef = $0010", "a+b = $0010", "two words = $0010", "x = $0020-$0010", "x = $0010:u32"] {
+            assert!(Label::parse(line).is_err(), "{:?} accepted", line);
+        }
+    }
+}
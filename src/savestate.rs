@@ -0,0 +1,618 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::Mode::*;
+use crate::ppu::{Mirroring, Ppu};
+use crate::snapshot::json_string;
+use crate::{Emulator, Mode};
+
+/* Save State Layout
+    0x0
+    -- MAGIC "RNSS"
+    0x4
+    -- FORMAT VERSION (u16 little endian)
+    0x6
+    -- SECTIONS, repeated until end of file
+        TAG (4 bytes) | LENGTH (u32 little endian) | PAYLOAD (LENGTH bytes)
+   Files and practice slots also carry a META section describing the state
+   for a load picker, decode checks it is there but does not apply it.
+*/
+pub const MAGIC: &[u8; 4] = b"RNSS";
+pub const VERSION: u16 = 5;
+// magic and version, the sections start after it
+pub const HEADER_LEN: usize = 6;
+
+const CPU_TAG: [u8; 4] = *b"CPU\0";
+const RAM_TAG: [u8; 4] = *b"RAM\0";
+const PPU_TAG: [u8; 4] = *b"PPU\0";
+const VRAM_TAG: [u8; 4] = *b"VRAM";
+const META_TAG: [u8; 4] = *b"META";
+const OAM_TAG: [u8; 4] = *b"OAM\0";
+const CPU_LEN: usize = 15;
+const PPU_LEN: usize = 19;
+// v, t, fine x, read buffer, mirroring, then chr, nametables and palette
+const VRAM_LEN: usize = 7 + 0x2000 + 0x800 + 32;
+// OAMADDR, DMA cycles left, then sprite memory
+const OAM_LEN: usize = 1 + 2 + 256;
+// rom crc known, rom crc, saved at, frames, play seconds
+const META_LEN: usize = 1 + 4 + 8 + 8 + 8;
+
+#[derive(Debug)]
+pub enum SaveStateError {
+    Io(std::io::Error),
+    BadMagic,
+    Truncated,
+    // state was written by a newer rnes than this one
+    NewerVersion(u16),
+    // state is older than any migration shim we still carry
+    UnsupportedVersion(u16),
+    MissingSection([u8; 4]),
+    BadSection([u8; 4]),
+}
+
+impl fmt::Display for SaveStateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SaveStateError::Io(e) => write!(f, "save state io error: {}", e),
+            SaveStateError::BadMagic => write!(f, "not an rnes save state (bad magic)"),
+            SaveStateError::Truncated => write!(f, "save state is truncated"),
+            SaveStateError::NewerVersion(v) => write!(f, "save state version {} is newer than supported version {}, update rnes", v, VERSION),
+            SaveStateError::UnsupportedVersion(v) => write!(f, "save state version {} is no longer supported", v),
+            SaveStateError::MissingSection(tag) => write!(f, "save state is missing section {}", tag_name(tag)),
+            SaveStateError::BadSection(tag) => write!(f, "save state section {} is malformed", tag_name(tag)),
+        }
+    }
+}
+
+impl From<std::io::Error> for SaveStateError {
+    fn from(e: std::io::Error) -> Self {
+        SaveStateError::Io(e)
+    }
+}
+
+pub fn tag_name(tag: &[u8; 4]) -> String {
+    String::from_utf8_lossy(tag).trim_end_matches('\0').to_string()
+}
+
+fn mode_to_u8(mode: &Mode) -> u8 {
+    match mode {
+        Null => 0,
+        Implied => 1,
+        Accumulator => 2,
+        Immediate => 3,
+        ZeroPage => 4,
+        ZeroPageX => 5,
+        ZeroPageY => 6,
+        Absolute => 7,
+        AbsoluteIndirect => 8,
+        AbsoluteX => 9,
+        AbsoluteY => 10,
+        IndirectX => 11,
+        IndirectY => 12,
+        Relative => 13,
+    }
+}
+
+fn mode_from_u8(value: u8) -> Option<Mode> {
+    let mode = match value {
+        0 => Null,
+        1 => Implied,
+        2 => Accumulator,
+        3 => Immediate,
+        4 => ZeroPage,
+        5 => ZeroPageX,
+        6 => ZeroPageY,
+        7 => Absolute,
+        8 => AbsoluteIndirect,
+        9 => AbsoluteX,
+        10 => AbsoluteY,
+        11 => IndirectX,
+        12 => IndirectY,
+        13 => Relative,
+        _ => return None,
+    };
+    Some(mode)
+}
+
+fn push_section(out: &mut Vec<u8>, tag: [u8; 4], payload: &[u8]) {
+    out.extend_from_slice(&tag);
+    out.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    out.extend_from_slice(payload);
+}
+
+fn ppu_section(ppu: &Ppu) -> Vec<u8> {
+    let mut section = Vec::with_capacity(PPU_LEN);
+    section.push(ppu.ctrl);
+    section.push(ppu.mask);
+    section.push(ppu.status);
+    section.extend_from_slice(&ppu.scanline.to_le_bytes());
+    section.extend_from_slice(&ppu.dot.to_le_bytes());
+    section.extend_from_slice(&ppu.frame.to_le_bytes());
+    section.push(ppu.write_latch as u8);
+    section.push(ppu.open_bus);
+    section.push(ppu.nmi_pending as u8);
+    section.push(ppu.suppress_vblank as u8);
+    section
+}
+
+fn vram_section(ppu: &Ppu) -> Vec<u8> {
+    let mut vram = Vec::with_capacity(VRAM_LEN);
+    vram.extend_from_slice(&ppu.v.to_le_bytes());
+    vram.extend_from_slice(&ppu.t.to_le_bytes());
+    vram.push(ppu.fine_x);
+    vram.push(ppu.read_buffer);
+    vram.push((ppu.mirroring == Mirroring::Vertical) as u8);
+    vram.extend_from_slice(&ppu.chr);
+    vram.extend_from_slice(&ppu.nametables);
+    vram.extend_from_slice(&ppu.palette);
+    vram
+}
+
+fn oam_section(ppu: &Ppu, dma_cycles: u16) -> Vec<u8> {
+    let mut oam = Vec::with_capacity(OAM_LEN);
+    oam.push(ppu.oam_addr);
+    oam.extend_from_slice(&dma_cycles.to_le_bytes());
+    oam.extend_from_slice(&ppu.oam);
+    oam
+}
+
+pub fn encode(emulator: &Emulator) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&VERSION.to_le_bytes());
+
+    let regs = &emulator.registers;
+    let mut cpu = Vec::with_capacity(CPU_LEN);
+    cpu.push(regs.a_reg);
+    cpu.push(regs.x_reg);
+    cpu.push(regs.y_reg);
+    cpu.push(regs.stack_pointer);
+    cpu.extend_from_slice(&regs.program_counter.to_le_bytes());
+    cpu.push(regs.cpu_flags);
+    cpu.push(emulator.fetched_data);
+    cpu.extend_from_slice(&emulator.address_absolute.to_le_bytes());
+    cpu.extend_from_slice(&emulator.address_relative.to_le_bytes());
+    cpu.push(emulator.opcode);
+    cpu.push(emulator.cycles);
+    cpu.push(mode_to_u8(&emulator.current_mode));
+    push_section(&mut out, CPU_TAG, &cpu);
+
+    push_section(&mut out, RAM_TAG, &emulator.memory);
+
+    push_section(&mut out, PPU_TAG, &ppu_section(&emulator.ppu));
+
+    push_section(&mut out, VRAM_TAG, &vram_section(&emulator.ppu));
+    push_section(&mut out, OAM_TAG, &oam_section(&emulator.ppu, emulator.dma_cycles));
+    out
+}
+
+// When and from what a state was saved. It is not machine state, so encode
+// leaves it out and state hashes match from run to run.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Metadata {
+    // CRC32 of the ROM after its header, None when no iNES ROM was loaded
+    pub rom_crc32: Option<u32>,
+    // unix seconds
+    pub saved_at: u64,
+    pub frames: u64,
+    pub play_seconds: u64,
+}
+
+// days since 1970-01-01 to year, month, day (Howard Hinnant's civil_from_days)
+fn civil_date(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    (year, month, day)
+}
+
+impl Metadata {
+    pub fn of(emulator: &Emulator) -> Self {
+        Metadata {
+            rom_crc32: emulator.rom_crc32,
+            saved_at: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+            frames: emulator.ppu.frame,
+            play_seconds: (emulator.ppu.frame as f64 / emulator.ppu.region.frame_rate()) as u64,
+        }
+    }
+
+    fn to_bytes(self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(META_LEN);
+        out.push(self.rom_crc32.is_some() as u8);
+        out.extend_from_slice(&self.rom_crc32.unwrap_or(0).to_le_bytes());
+        out.extend_from_slice(&self.saved_at.to_le_bytes());
+        out.extend_from_slice(&self.frames.to_le_bytes());
+        out.extend_from_slice(&self.play_seconds.to_le_bytes());
+        out
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != META_LEN {
+            return None;
+        }
+        let u64_at = |at: usize| u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap_or_default());
+        let crc = u32::from_le_bytes([bytes[1], bytes[2], bytes[3], bytes[4]]);
+        Some(Metadata {
+            rom_crc32: (bytes[0] != 0).then_some(crc),
+            saved_at: u64_at(5),
+            frames: u64_at(13),
+            play_seconds: u64_at(21),
+        })
+    }
+
+    // "2026-10-16 14:03 UTC"
+    pub fn saved_text(&self) -> String {
+        let minutes = self.saved_at / 60;
+        let (year, month, day) = civil_date((minutes / 1440) as i64);
+        format!("{:04}-{:02}-{:02} {:02}:{:02} UTC", year, month, day, minutes / 60 % 24, minutes % 60)
+    }
+
+    // "1:02:03"
+    pub fn play_text(&self) -> String {
+        let s = self.play_seconds;
+        format!("{}:{:02}:{:02}", s / 3600, s / 60 % 60, s % 60)
+    }
+
+    pub fn to_json(self) -> String {
+        format!(
+            "{{\"rom_crc32\":{},\"saved_at\":{},\"saved\":{},\"frames\":{},\"play_seconds\":{}}}",
+            self.rom_crc32.map(|crc| json_string(&format!("{:08x}", crc))).unwrap_or("null".to_string()),
+            self.saved_at,
+            json_string(&self.saved_text()),
+            self.frames,
+            self.play_seconds
+        )
+    }
+}
+
+// What goes into state files and practice slots, encode plus a META section.
+// decode_into only takes this form, plain encode is for hashes and comparisons.
+pub fn encode_with_metadata(emulator: &Emulator) -> Vec<u8> {
+    let mut out = encode(emulator);
+    push_section(&mut out, META_TAG, &Metadata::of(emulator).to_bytes());
+    out
+}
+
+// The META section of a state, None for states written before it existed or that do not parse.
+pub fn metadata(data: &[u8]) -> Option<Metadata> {
+    if data.len() < HEADER_LEN || &data[0..4] != MAGIC {
+        return None;
+    }
+    let sections = sections(&data[HEADER_LEN..]).ok()?;
+    sections.into_iter().find(|(tag, _)| *tag == META_TAG).and_then(|(_, payload)| Metadata::from_bytes(payload))
+}
+
+// tag and payload
+pub type Section<'a> = ([u8; 4], &'a [u8]);
+
+// The sections of a state in file order, data starts after the magic and version.
+pub fn sections(data: &[u8]) -> Result<Vec<Section<'_>>, SaveStateError> {
+    let mut sections = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        if pos + 8 > data.len() {
+            return Err(SaveStateError::Truncated);
+        }
+        let tag = [data[pos], data[pos + 1], data[pos + 2], data[pos + 3]];
+        let len = u32::from_le_bytes([data[pos + 4], data[pos + 5], data[pos + 6], data[pos + 7]]) as usize;
+        pos += 8;
+        if pos + len > data.len() {
+            return Err(SaveStateError::Truncated);
+        }
+        sections.push((tag, &data[pos..pos + len]));
+        pos += len;
+    }
+    Ok(sections)
+}
+
+fn split_sections(data: &[u8]) -> Result<HashMap<[u8; 4], Vec<u8>>, SaveStateError> {
+    Ok(sections(data)?.into_iter().map(|(tag, payload)| (tag, payload.to_vec())).collect())
+}
+
+// Bring sections written by an older format version up to VERSION, one
+// version at a time. Every layout change bumps VERSION and adds an arm here
+// that turns the sections of the version before it into the next one.
+//   1  CPU and RAM
+//   2  adds PPU
+//   3  adds VRAM
+//   4  adds META
+//   5  adds OAM
+// current is the PPU the state is loading into, older states keep its video memory.
+fn migrate(current: &Ppu, version: u16, mut sections: HashMap<[u8; 4], Vec<u8>>) -> Result<HashMap<[u8; 4], Vec<u8>>, SaveStateError> {
+    if version > VERSION {
+        return Err(SaveStateError::NewerVersion(version));
+    }
+    for from in version..VERSION {
+        match from {
+            // states from before the PPU existed bring it up powered on, builds
+            // that wrote the section before the bump already have one
+            1 => {
+                sections.entry(PPU_TAG).or_insert_with(|| ppu_section(&Ppu::new()));
+            }
+            // video memory belongs to the cartridge and console, keep what is
+            // loaded with the address latches powered on
+            2 => {
+                sections.entry(VRAM_TAG).or_insert_with(|| {
+                    let mut ppu = Ppu::new();
+                    ppu.mirroring = current.mirroring;
+                    ppu.chr = current.chr;
+                    ppu.nametables = current.nametables;
+                    ppu.palette = current.palette;
+                    vram_section(&ppu)
+                });
+            }
+            // nothing is known about when or from what older states were saved
+            3 => {
+                let unknown = Metadata { rom_crc32: None, saved_at: 0, frames: 0, play_seconds: 0 };
+                sections.entry(META_TAG).or_insert_with(|| unknown.to_bytes());
+            }
+            // sprite memory was not emulated, it comes up empty
+            4 => {
+                sections.entry(OAM_TAG).or_insert_with(|| oam_section(&Ppu::new(), 0));
+            }
+            v => return Err(SaveStateError::UnsupportedVersion(v)),
+        }
+    }
+    Ok(sections)
+}
+
+pub fn decode_into(emulator: &mut Emulator, data: &[u8]) -> Result<(), SaveStateError> {
+    if data.len() < HEADER_LEN {
+        return Err(SaveStateError::Truncated);
+    }
+    if &data[0..4] != MAGIC {
+        return Err(SaveStateError::BadMagic);
+    }
+    let version = u16::from_le_bytes([data[4], data[5]]);
+    let sections = migrate(&emulator.ppu, version, split_sections(&data[HEADER_LEN..])?)?;
+
+    // validate everything before touching the emulator so a bad state never half loads
+    let cpu = sections.get(&CPU_TAG).ok_or(SaveStateError::MissingSection(CPU_TAG))?;
+    if cpu.len() != CPU_LEN {
+        return Err(SaveStateError::BadSection(CPU_TAG));
+    }
+    let mode = mode_from_u8(cpu[14]).ok_or(SaveStateError::BadSection(CPU_TAG))?;
+    let ram = sections.get(&RAM_TAG).ok_or(SaveStateError::MissingSection(RAM_TAG))?;
+    if ram.len() != emulator.memory.len() {
+        return Err(SaveStateError::BadSection(RAM_TAG));
+    }
+    let p = sections.get(&PPU_TAG).ok_or(SaveStateError::MissingSection(PPU_TAG))?;
+    if p.len() != PPU_LEN {
+        return Err(SaveStateError::BadSection(PPU_TAG));
+    }
+    let v = sections.get(&VRAM_TAG).ok_or(SaveStateError::MissingSection(VRAM_TAG))?;
+    if v.len() != VRAM_LEN {
+        return Err(SaveStateError::BadSection(VRAM_TAG));
+    }
+    let oam = sections.get(&OAM_TAG).ok_or(SaveStateError::MissingSection(OAM_TAG))?;
+    if oam.len() != OAM_LEN {
+        return Err(SaveStateError::BadSection(OAM_TAG));
+    }
+    let meta = sections.get(&META_TAG).ok_or(SaveStateError::MissingSection(META_TAG))?;
+    if Metadata::from_bytes(meta).is_none() {
+        return Err(SaveStateError::BadSection(META_TAG));
+    }
+
+    emulator.registers.a_reg = cpu[0];
+    emulator.registers.x_reg = cpu[1];
+    emulator.registers.y_reg = cpu[2];
+    emulator.registers.stack_pointer = cpu[3];
+    emulator.registers.program_counter = u16::from_le_bytes([cpu[4], cpu[5]]);
+    emulator.registers.cpu_flags = cpu[6];
+    emulator.fetched_data = cpu[7];
+    emulator.address_absolute = u16::from_le_bytes([cpu[8], cpu[9]]);
+    emulator.address_relative = u16::from_le_bytes([cpu[10], cpu[11]]);
+    emulator.opcode = cpu[12];
+    emulator.cycles = cpu[13];
+    emulator.current_mode = mode;
+    emulator.memory.copy_from_slice(ram);
+    emulator.ppu.power_on();
+    emulator.ppu.ctrl = p[0];
+    emulator.ppu.mask = p[1];
+    emulator.ppu.status = p[2];
+    emulator.ppu.scanline = u16::from_le_bytes([p[3], p[4]]);
+    emulator.ppu.dot = u16::from_le_bytes([p[5], p[6]]);
+    emulator.ppu.frame = u64::from_le_bytes([p[7], p[8], p[9], p[10], p[11], p[12], p[13], p[14]]);
+    emulator.ppu.write_latch = p[15] != 0;
+    emulator.ppu.open_bus = p[16];
+    emulator.ppu.nmi_pending = p[17] != 0;
+    emulator.ppu.suppress_vblank = p[18] != 0;
+    let ppu = &mut emulator.ppu;
+    ppu.v = u16::from_le_bytes([v[0], v[1]]);
+    ppu.t = u16::from_le_bytes([v[2], v[3]]);
+    ppu.fine_x = v[4];
+    ppu.read_buffer = v[5];
+    ppu.mirroring = if v[6] != 0 { Mirroring::Vertical } else { Mirroring::Horizontal };
+    let (chr, rest) = v[7..].split_at(0x2000);
+    let (nametables, palette) = rest.split_at(0x800);
+    ppu.chr.copy_from_slice(chr);
+    ppu.nametables.copy_from_slice(nametables);
+    ppu.palette.copy_from_slice(palette);
+    ppu.oam_addr = oam[0];
+    ppu.oam.copy_from_slice(&oam[3..]);
+    emulator.dma_cycles = u16::from_le_bytes([oam[1], oam[2]]);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn machine() -> Emulator {
+        let mut emulator = Emulator::new();
+        emulator.verbose = false;
+        emulator.registers.a_reg = 0x12;
+        emulator.registers.x_reg = 0x34;
+        emulator.registers.program_counter = 0x8123;
+        emulator.registers.cpu_flags = 0x24;
+        emulator.memory[0x0300] = 0xAB;
+        emulator.ppu.ctrl = 0x90;
+        emulator.ppu.scanline = 100;
+        emulator.ppu.dot = 200;
+        emulator.ppu.frame = 77;
+        emulator.ppu.v = 0x2345;
+        emulator.ppu.mirroring = Mirroring::Vertical;
+        emulator.ppu.chr[0x10] = 0x55;
+        emulator.ppu.nametables[0x400] = 0x66;
+        emulator.ppu.palette[3] = 0x17;
+        emulator.ppu.oam[0x41] = 0x88;
+        emulator
+    }
+
+    // data rewritten as an older version that did not have the dropped sections
+    fn as_version(data: &[u8], version: u16, dropped: &[[u8; 4]]) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        out.extend_from_slice(&version.to_le_bytes());
+        for (tag, payload) in sections(&data[HEADER_LEN..]).unwrap() {
+            if !dropped.contains(&tag) {
+                push_section(&mut out, tag, payload);
+            }
+        }
+        out
+    }
+
+    fn loaded(data: &[u8]) -> Result<Emulator, SaveStateError> {
+        let mut emulator = Emulator::new();
+        emulator.verbose = false;
+        decode_into(&mut emulator, data)?;
+        Ok(emulator)
+    }
+
+    #[test]
+    fn round_trip_restores_cpu_ram_and_ppu() {
+        let data = encode_with_metadata(&machine());
+        let emulator = loaded(&data).unwrap();
+        assert_eq!(emulator.registers.program_counter, 0x8123);
+        assert_eq!(emulator.memory[0x0300], 0xAB);
+        assert_eq!((emulator.ppu.scanline, emulator.ppu.dot, emulator.ppu.frame), (100, 200, 77));
+        assert_eq!(emulator.ppu.mirroring, Mirroring::Vertical);
+        assert_eq!(emulator.ppu.oam[0x41], 0x88);
+        assert_eq!(encode(&emulator), encode(&machine()));
+    }
+
+    #[test]
+    fn rejects_bad_magic() {
+        let mut data = encode(&machine());
+        data[0] = b'X';
+        assert!(matches!(loaded(&data), Err(SaveStateError::BadMagic)));
+    }
+
+    #[test]
+    fn rejects_a_newer_version() {
+        let mut data = encode(&machine());
+        data[4..6].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert!(matches!(loaded(&data), Err(SaveStateError::NewerVersion(v)) if v == VERSION + 1));
+    }
+
+    #[test]
+    fn rejects_a_truncated_section_without_touching_the_machine() {
+        let data = encode(&machine());
+        let mut emulator = Emulator::new();
+        emulator.memory[0x0300] = 0x01;
+        assert!(matches!(decode_into(&mut emulator, &data[..data.len() - 1]), Err(SaveStateError::Truncated)));
+        assert!(matches!(decode_into(&mut emulator, &data[..4]), Err(SaveStateError::Truncated)));
+        assert_eq!(emulator.memory[0x0300], 0x01);
+    }
+
+    #[test]
+    fn metadata_rides_along_and_decode_requires_it() {
+        let mut emulator = machine();
+        emulator.rom_crc32 = Some(0xDEADBEEF);
+        let data = encode_with_metadata(&emulator);
+        let meta = metadata(&data).unwrap();
+        assert_eq!((meta.rom_crc32, meta.frames), (Some(0xDEADBEEF), 77));
+        assert_eq!(metadata(&encode(&emulator)), None);
+        assert_eq!(encode(&loaded(&data).unwrap()), encode(&emulator));
+        assert!(matches!(loaded(&encode(&emulator)), Err(SaveStateError::MissingSection(META_TAG))));
+    }
+
+    #[test]
+    fn version_1_states_bring_the_ppu_up_powered_on() {
+        let data = as_version(&encode(&machine()), 1, &[PPU_TAG]);
+        let emulator = loaded(&data).unwrap();
+        assert_eq!(emulator.registers.program_counter, 0x8123);
+        assert_eq!((emulator.ppu.ctrl, emulator.ppu.scanline, emulator.ppu.frame), (0, 0, 0));
+    }
+
+    #[test]
+    fn version_2_states_keep_the_loaded_video_memory() {
+        let data = as_version(&encode(&machine()), 2, &[VRAM_TAG]);
+        let mut emulator = Emulator::new();
+        emulator.verbose = false;
+        emulator.ppu.chr[0x10] = 0x99;
+        emulator.ppu.palette[3] = 0x21;
+        emulator.ppu.v = 0x1111;
+        decode_into(&mut emulator, &data).unwrap();
+        assert_eq!(emulator.ppu.frame, 77);
+        assert_eq!((emulator.ppu.chr[0x10], emulator.ppu.palette[3], emulator.ppu.v), (0x99, 0x21, 0));
+    }
+
+    #[test]
+    fn version_3_states_load_without_metadata() {
+        let data = as_version(&encode(&machine()), 3, &[]);
+        assert_eq!(encode(&loaded(&data).unwrap()), encode(&machine()));
+        assert_eq!(metadata(&data), None);
+    }
+
+    #[test]
+    fn version_4_states_start_with_empty_sprite_memory() {
+        let data = as_version(&encode_with_metadata(&machine()), 4, &[OAM_TAG]);
+        let emulator = loaded(&data).unwrap();
+        assert_eq!((emulator.ppu.oam[0x41], emulator.ppu.palette[3]), (0, 0x17));
+    }
+
+    #[test]
+    fn current_states_must_have_every_section() {
+        for tag in [PPU_TAG, VRAM_TAG, OAM_TAG, META_TAG] {
+            let data = as_version(&encode_with_metadata(&machine()), VERSION, &[tag]);
+            assert!(matches!(loaded(&data), Err(SaveStateError::MissingSection(t)) if t == tag));
+        }
+    }
+
+    // The state the version 1 build wrote after running LDX #$05, INX, LDA #$42
+    // from a bare iNES image: header, the CPU section, then all of memory with
+    // the ROM file loaded at $8000.
+    fn version_1_blob() -> Vec<u8> {
+        let mut blob = vec![
+            b'R', b'N', b'S', b'S', 0x01, 0x00,
+            b'C', b'P', b'U', 0x00, 0x0F, 0x00, 0x00, 0x00,
+            0x42, 0x06, 0x00, 0x00, 0x15, 0x80, 0x00, 0x00, 0x14, 0x80, 0x00, 0x00, 0xA9, 0x01, 0x03,
+            b'R', b'A', b'M', 0x00, 0x00, 0x00, 0x01, 0x00,
+        ];
+        let mut memory = vec![0; 0x10000];
+        let rom = [0x4E, 0x45, 0x53, 0x1A, 0x01, 0x01, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xA2, 0x05, 0xE8, 0xA9, 0x42];
+        memory[0x8000..0x8000 + rom.len()].copy_from_slice(&rom);
+        blob.extend_from_slice(&memory);
+        blob
+    }
+
+    #[test]
+    fn a_version_1_file_migrates_to_the_current_layout() {
+        let mut emulator = Emulator::new();
+        emulator.verbose = false;
+        emulator.ppu.chr[0x20] = 0x3C;
+        decode_into(&mut emulator, &version_1_blob()).unwrap();
+        let regs = &emulator.registers;
+        assert_eq!((regs.a_reg, regs.x_reg, regs.program_counter), (0x42, 0x06, 0x8015));
+        assert_eq!((emulator.opcode, mode_to_u8(&emulator.current_mode)), (0xA9, mode_to_u8(&Immediate)));
+        assert_eq!(emulator.memory[0x8011], 0x05);
+        assert_eq!((emulator.ppu.frame, emulator.ppu.chr[0x20]), (0, 0x3C));
+        // once loaded it saves as a current state and loads back the same
+        let current = encode_with_metadata(&emulator);
+        assert_eq!(u16::from_le_bytes([current[4], current[5]]), VERSION);
+        assert_eq!(encode(&loaded(&current).unwrap()), encode(&emulator));
+    }
+
+    #[test]
+    fn rejects_a_version_older_than_any_shim() {
+        let mut data = version_1_blob();
+        data[4] = 0;
+        assert!(matches!(loaded(&data), Err(SaveStateError::UnsupportedVersion(0))));
+    }
+}
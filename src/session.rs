@@ -0,0 +1,240 @@
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use crate::savestate;
+use crate::Emulator;
+
+/* Session Log Layout, plain text one record per line
+    rnes-session VERSION
+    rom PATH
+    ram-pattern NAME
+    unknown-opcode POLICY       nop, break or error
+    seed N
+    overclock N                 idle scanlines added after the post-render line
+    state PATH                  (only when the run started from a save state)
+    input FRAME P1 P2           buttons the console sees from FRAME on, turbo and macros
+                                included, written when they change
+    mic FRAME 0|1               Famicom microphone from FRAME on, written when it changes
+    lag FRAME                   the game did not poll input in the frame ending at FRAME, replay
+                                leaves these to the hashes and only marks them
+    hash FRAME H                hash of the whole machine at the end of FRAME
+*/
+pub const VERSION: u32 = 1;
+// frames between state hashes
+const HASH_INTERVAL: u64 = 60;
+
+pub struct SessionHeader {
+    pub rom: String,
+    pub ram_pattern: String,
+    pub unknown_opcode: String,
+    pub seed: u64,
+    pub overclock: u16,
+    pub state: Option<String>,
+}
+
+pub struct Recorder {
+    out: BufWriter<File>,
+    last_input: [u8; 2],
+    last_microphone: bool,
+}
+
+pub struct Replay {
+    inputs: VecDeque<(u64, [u8; 2])>,
+    microphone: VecDeque<(u64, bool)>,
+    hashes: VecDeque<(u64, u64)>,
+    pub checked: usize,
+    pub desync: Option<u64>,
+}
+
+pub enum Session {
+    Recording(Recorder),
+    Replaying(Replay),
+}
+
+// FNV-1a over the save state encoding, covers CPU, RAM and PPU in one go.
+pub fn state_hash(emulator: &Emulator) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in savestate::encode(emulator) {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+impl Recorder {
+    pub fn create(path: &str, header: &SessionHeader) -> io::Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+        writeln!(out, "rnes-session {}", VERSION)?;
+        writeln!(out, "rom {}", header.rom)?;
+        writeln!(out, "ram-pattern {}", header.ram_pattern)?;
+        writeln!(out, "unknown-opcode {}", header.unknown_opcode)?;
+        writeln!(out, "seed {}", header.seed)?;
+        writeln!(out, "overclock {}", header.overclock)?;
+        if let Some(state) = header.state.as_ref() {
+            writeln!(out, "state {}", state)?;
+        }
+        out.flush()?;
+        Ok(Recorder { out, last_input: [0; 2], last_microphone: false })
+    }
+}
+
+fn bad_line(number: usize, line: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("line {}: cannot parse {:?}", number + 1, line))
+}
+
+pub fn read(path: &str) -> io::Result<(SessionHeader, Replay)> {
+    let text = fs::read_to_string(path)?;
+    let mut header = SessionHeader {
+        rom: String::new(),
+        ram_pattern: "zeros".to_string(),
+        unknown_opcode: "error".to_string(),
+        seed: 1,
+        overclock: 0,
+        state: None,
+    };
+    let mut replay = Replay {
+        inputs: VecDeque::new(),
+        microphone: VecDeque::new(),
+        hashes: VecDeque::new(),
+        checked: 0,
+        desync: None,
+    };
+    for (number, line) in text.lines().enumerate() {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let value = |i: usize| words.get(i).and_then(|w| w.parse::<u64>().ok()).ok_or_else(|| bad_line(number, line));
+        match words.as_slice() {
+            [] => {}
+            ["rnes-session", version] => {
+                if version.parse::<u32>().ok() != Some(VERSION) {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, format!("session version {} is not supported", version)));
+                }
+            }
+            ["rom", ..] => header.rom = line["rom".len()..].trim().to_string(),
+            ["state", ..] => header.state = Some(line["state".len()..].trim().to_string()),
+            ["ram-pattern", name] => header.ram_pattern = name.to_string(),
+            ["unknown-opcode", name] => header.unknown_opcode = name.to_string(),
+            ["seed", _] => header.seed = value(1)?,
+            ["overclock", _] => header.overclock = value(1)? as u16,
+            ["input", _, _, _] => replay.inputs.push_back((value(1)?, [value(2)? as u8, value(3)? as u8])),
+            ["mic", _, _] => replay.microphone.push_back((value(1)?, value(2)? != 0)),
+            ["lag", _] => {
+                value(1)?;
+            }
+            ["hash", _, hash] => {
+                let hash = u64::from_str_radix(hash, 16).map_err(|_| bad_line(number, line))?;
+                replay.hashes.push_back((value(1)?, hash));
+            }
+            _ => return Err(bad_line(number, line)),
+        }
+    }
+    Ok((header, replay))
+}
+
+// Called once per frame. A replay pauses the machine when it desyncs or runs out.
+pub fn end_frame(emulator: &mut Emulator) {
+    let frame = emulator.ppu.frame;
+    // what the console sees, so a replay needs no turbo or macro setup of its own
+    let held = [emulator.controllers[0].buttons(), emulator.controllers[1].buttons()];
+    let microphone = emulator.controllers[1].microphone();
+    let lagged = emulator.lag.last_frame_lagged;
+    let hash_due = frame.is_multiple_of(HASH_INTERVAL);
+    let hash = if hash_due && emulator.session.is_some() { state_hash(emulator) } else { 0 };
+    match emulator.session.as_mut() {
+        Some(Session::Recording(recorder)) => {
+            // a full disk should not stop the game, the log just ends early
+            let mut write = || -> io::Result<()> {
+                if held != recorder.last_input {
+                    writeln!(recorder.out, "input {} {} {}", frame, held[0], held[1])?;
+                    recorder.last_input = held;
+                }
+                if microphone != recorder.last_microphone {
+                    writeln!(recorder.out, "mic {} {}", frame, microphone as u8)?;
+                    recorder.last_microphone = microphone;
+                }
+                if lagged {
+                    writeln!(recorder.out, "lag {}", frame)?;
+                }
+                if hash_due {
+                    writeln!(recorder.out, "hash {} {:016x}", frame, hash)?;
+                    recorder.out.flush()?;
+                }
+                Ok(())
+            };
+            if let Err(e) = write() {
+                println!("Failed to write session log: {}", e);
+                emulator.session = None;
+            }
+        }
+        Some(Session::Replaying(replay)) => {
+            while replay.hashes.front().is_some_and(|(f, _)| *f < frame) {
+                replay.hashes.pop_front();
+            }
+            if let Some((_, expected)) = replay.hashes.front().filter(|(f, _)| *f == frame && hash_due) {
+                if *expected != hash {
+                    replay.desync = Some(frame);
+                    emulator.pause();
+                    return;
+                }
+                replay.checked += 1;
+                replay.hashes.pop_front();
+            }
+            let mut input = None;
+            while replay.inputs.front().is_some_and(|(f, _)| *f <= frame) {
+                input = replay.inputs.pop_front().map(|(_, buttons)| buttons);
+            }
+            let mut microphone = None;
+            while replay.microphone.front().is_some_and(|(f, _)| *f <= frame) {
+                microphone = replay.microphone.pop_front().map(|(_, on)| on);
+            }
+            let finished = replay.inputs.is_empty() && replay.microphone.is_empty() && replay.hashes.is_empty();
+            if let Some(buttons) = input {
+                for (controller, buttons) in emulator.controllers.iter_mut().zip(buttons) {
+                    controller.release(0xFF);
+                    controller.press(buttons);
+                }
+            }
+            if let Some(on) = microphone {
+                emulator.controllers[1].set_microphone(on);
+            }
+            if finished {
+                emulator.pause();
+            }
+        }
+        None => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn read_text(name: &str, text: &str) -> io::Result<(SessionHeader, Replay)> {
+        let path = env::temp_dir().join(format!("rnes-session-test-{}-{}", std::process::id(), name));
+        fs::write(&path, text)?;
+        let result = read(&path.display().to_string());
+        let _ = fs::remove_file(&path);
+        result
+    }
+
+    #[test]
+    fn reads_header_inputs_and_hashes() {
+        let text = "rnes-session 1\nrom games/balloon fight.nes\nram-pattern ones\nunknown-opcode nop\nseed 42\noverclock 20\n\
+                    input 3 8 0\nmic 5 1\nlag 6\nhash 60 00000000deadbeef\n";
+        let (header, replay) = read_text("good", text).unwrap();
+        assert_eq!(header.rom, "games/balloon fight.nes");
+        assert_eq!((header.ram_pattern.as_str(), header.unknown_opcode.as_str()), ("ones", "nop"));
+        assert_eq!((header.seed, header.overclock, header.state), (42, 20, None));
+        assert_eq!(replay.inputs, [(3, [8, 0])]);
+        assert_eq!(replay.microphone, [(5, true)]);
+        assert_eq!(replay.hashes, [(60, 0xdeadbeef)]);
+    }
+
+    #[test]
+    fn rejects_other_versions_and_bad_lines() {
+        assert_eq!(read_text("version", "rnes-session 99\n").err().map(|e| e.kind()), Some(io::ErrorKind::InvalidData));
+        let error = read_text("line", "rnes-session 1\ninput 3 x 0\n").err().unwrap();
+        assert!(error.to_string().starts_with("line 2:"), "{}", error);
+        assert!(read_text("unknown", "rnes-session 1\nfrobnicate\n").is_err());
+    }
+}
@@ -0,0 +1,426 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use crate::Emulator;
+
+// Runner for the 6502 SingleStepTests (https://github.com/SingleStepTests/65x02).
+// Each file holds the cases for one opcode: a starting machine, the machine
+// after one instruction, and every bus cycle in between.
+
+// how many failing cases per file are printed in full
+const SHOWN_FAILURES: usize = 3;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct BusAccess {
+    pub address: u16,
+    pub value: u8,
+    pub write: bool,
+}
+
+// Just enough JSON for the test files.
+enum Json {
+    // true, false and null, the tests never need their value
+    Literal,
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(BTreeMap<String, Json>),
+}
+
+impl Json {
+    fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(map) => map.get(key),
+            _ => None,
+        }
+    }
+
+    fn number(&self) -> Option<f64> {
+        match self {
+            Json::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    fn array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+}
+
+struct Parser<'a> {
+    text: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        while self.pos < self.text.len() && self.text[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    fn error(&self, what: &str) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, format!("{} at byte {}", what, self.pos))
+    }
+
+    fn expect(&mut self, byte: u8) -> io::Result<()> {
+        self.skip_whitespace();
+        if self.text.get(self.pos) != Some(&byte) {
+            return Err(self.error(&format!("expected '{}'", byte as char)));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn literal(&mut self, word: &str) -> io::Result<Json> {
+        if !self.text[self.pos..].starts_with(word.as_bytes()) {
+            return Err(self.error("unexpected token"));
+        }
+        self.pos += word.len();
+        Ok(Json::Literal)
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        self.expect(b'"')?;
+        let mut out = String::new();
+        loop {
+            match self.text.get(self.pos) {
+                None => return Err(self.error("unterminated string")),
+                Some(b'"') => {
+                    self.pos += 1;
+                    return Ok(out);
+                }
+                Some(b'\\') => {
+                    // test names never need escapes beyond passing them through
+                    out.push(*self.text.get(self.pos + 1).ok_or_else(|| self.error("unterminated string"))? as char);
+                    self.pos += 2;
+                }
+                Some(c) => {
+                    out.push(*c as char);
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+
+    fn value(&mut self) -> io::Result<Json> {
+        self.skip_whitespace();
+        match self.text.get(self.pos) {
+            Some(b'{') => {
+                self.pos += 1;
+                let mut map = BTreeMap::new();
+                self.skip_whitespace();
+                if self.text.get(self.pos) == Some(&b'}') {
+                    self.pos += 1;
+                    return Ok(Json::Object(map));
+                }
+                loop {
+                    let key = self.string()?;
+                    self.expect(b':')?;
+                    map.insert(key, self.value()?);
+                    self.skip_whitespace();
+                    match self.text.get(self.pos) {
+                        Some(b',') => self.pos += 1,
+                        Some(b'}') => {
+                            self.pos += 1;
+                            return Ok(Json::Object(map));
+                        }
+                        _ => return Err(self.error("expected ',' or '}'")),
+                    }
+                }
+            }
+            Some(b'[') => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                self.skip_whitespace();
+                if self.text.get(self.pos) == Some(&b']') {
+                    self.pos += 1;
+                    return Ok(Json::Array(items));
+                }
+                loop {
+                    items.push(self.value()?);
+                    self.skip_whitespace();
+                    match self.text.get(self.pos) {
+                        Some(b',') => self.pos += 1,
+                        Some(b']') => {
+                            self.pos += 1;
+                            return Ok(Json::Array(items));
+                        }
+                        _ => return Err(self.error("expected ',' or ']'")),
+                    }
+                }
+            }
+            Some(b'"') => Ok(Json::String(self.string()?)),
+            Some(b't') => self.literal("true"),
+            Some(b'f') => self.literal("false"),
+            Some(b'n') => self.literal("null"),
+            Some(_) => {
+                let start = self.pos;
+                while self.pos < self.text.len() && matches!(self.text[self.pos], b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9') {
+                    self.pos += 1;
+                }
+                let text = std::str::from_utf8(&self.text[start..self.pos]).unwrap_or("");
+                text.parse().map(Json::Number).map_err(|_| self.error("bad number"))
+            }
+            None => Err(self.error("unexpected end of file")),
+        }
+    }
+}
+
+fn parse(text: &str) -> io::Result<Json> {
+    Parser { text: text.as_bytes(), pos: 0 }.value()
+}
+
+#[derive(PartialEq, Debug)]
+struct CpuState {
+    pc: u16,
+    s: u8,
+    a: u8,
+    x: u8,
+    y: u8,
+    p: u8,
+    ram: Vec<(u16, u8)>,
+}
+
+struct Case {
+    name: String,
+    initial: CpuState,
+    expected: CpuState,
+    cycles: Vec<BusAccess>,
+}
+
+fn bad_case() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "test case is missing fields")
+}
+
+fn cpu_state(json: &Json) -> io::Result<CpuState> {
+    let field = |key: &str| json.get(key).and_then(|v| v.number()).ok_or_else(bad_case);
+    let mut ram = Vec::new();
+    for pair in json.get("ram").and_then(|r| r.array()).ok_or_else(bad_case)? {
+        let pair = pair.array().ok_or_else(bad_case)?;
+        let value = |i: usize| pair.get(i).and_then(|v| v.number()).ok_or_else(bad_case);
+        ram.push((value(0)? as u16, value(1)? as u8));
+    }
+    Ok(CpuState {
+        pc: field("pc")? as u16,
+        s: field("s")? as u8,
+        a: field("a")? as u8,
+        x: field("x")? as u8,
+        y: field("y")? as u8,
+        p: field("p")? as u8,
+        ram,
+    })
+}
+
+fn load_cases(path: &Path) -> io::Result<Vec<Case>> {
+    let json = parse(&fs::read_to_string(path)?)?;
+    let mut cases = Vec::new();
+    for case in json.array().ok_or_else(bad_case)? {
+        let mut cycles = Vec::new();
+        for cycle in case.get("cycles").and_then(|c| c.array()).ok_or_else(bad_case)? {
+            let cycle = cycle.array().ok_or_else(bad_case)?;
+            let address = cycle.first().and_then(|v| v.number()).ok_or_else(bad_case)? as u16;
+            let value = cycle.get(1).and_then(|v| v.number()).ok_or_else(bad_case)? as u8;
+            let write = matches!(cycle.get(2), Some(Json::String(kind)) if kind == "write");
+            cycles.push(BusAccess { address, value, write });
+        }
+        cases.push(Case {
+            name: match case.get("name") {
+                Some(Json::String(name)) => name.clone(),
+                _ => String::new(),
+            },
+            initial: cpu_state(case.get("initial").ok_or_else(bad_case)?)?,
+            expected: cpu_state(case.get("final").ok_or_else(bad_case)?)?,
+            cycles,
+        });
+    }
+    Ok(cases)
+}
+
+// Run one case, returns what differed from the expected result.
+fn run_case(case: &Case) -> Vec<String> {
+    let mut emulator = Emulator::new();
+    emulator.verbose = false;
+    let start = &case.initial;
+    emulator.registers.program_counter = start.pc;
+    emulator.registers.stack_pointer = start.s;
+    emulator.registers.a_reg = start.a;
+    emulator.registers.x_reg = start.x;
+    emulator.registers.y_reg = start.y;
+    emulator.registers.cpu_flags = start.p;
+    for (address, value) in &start.ram {
+        emulator.memory[*address as usize] = *value;
+    }
+    // the tests assume plain RAM across the whole address space
+    emulator.bus_log = Some(Vec::new());
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let pc = emulator.registers.program_counter;
+        emulator.opcode = emulator.memory[pc as usize];
+        emulator.bus_log.as_mut().unwrap().push(BusAccess { address: pc, value: emulator.opcode, write: false });
+        let implemented = emulator.execute_instruction();
+        (emulator, implemented)
+    }));
+    let emulator = match result {
+        Ok((emulator, true)) => emulator,
+        Ok((_, false)) => return vec!["opcode not implemented".to_string()],
+        Err(_) => return vec!["panicked".to_string()],
+    };
+
+    let mut problems = Vec::new();
+    let expected = &case.expected;
+    let regs = &emulator.registers;
+    let mut compare = |name: &str, got: u16, want: u16| {
+        if got != want {
+            problems.push(format!("{} is ${:02X}, expected ${:02X}", name, got, want));
+        }
+    };
+    compare("pc", regs.program_counter, expected.pc);
+    compare("s", regs.stack_pointer as u16, expected.s as u16);
+    compare("a", regs.a_reg as u16, expected.a as u16);
+    compare("x", regs.x_reg as u16, expected.x as u16);
+    compare("y", regs.y_reg as u16, expected.y as u16);
+    compare("p", regs.cpu_flags as u16, expected.p as u16);
+    compare("cycles", emulator.cycles as u16, case.cycles.len() as u16);
+    for (address, value) in &expected.ram {
+        let got = emulator.memory[*address as usize];
+        if got != *value {
+            problems.push(format!("${:04X} is ${:02X}, expected ${:02X}", address, got, value));
+        }
+    }
+    let bus = emulator.bus_log.as_deref().unwrap_or(&[]);
+    if bus != case.cycles.as_slice() {
+        let show = |accesses: &[BusAccess]| {
+            accesses
+                .iter()
+                .map(|a| format!("{}${:04X}={:02X}", if a.write { "w" } else { "r" }, a.address, a.value))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        problems.push(format!("bus [{}], expected [{}]", show(bus), show(&case.cycles)));
+    }
+    problems
+}
+
+fn json_files(path: &str) -> io::Result<Vec<std::path::PathBuf>> {
+    let path = Path::new(path);
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let mut files: Vec<_> = fs::read_dir(path)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|p| p.extension().is_some_and(|e| e == "json"))
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+// Run every case in the given files or directories, prints a line per file
+// and returns false if anything failed.
+pub fn run(paths: &[String]) -> bool {
+    // unimplemented opcodes panic, the case is reported as failed instead
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let mut all_passed = true;
+    let (mut total, mut total_passed) = (0, 0);
+    for path in paths {
+        let files = match json_files(path) {
+            Ok(files) => files,
+            Err(e) => {
+                println!("{}: {}", path, e);
+                all_passed = false;
+                continue;
+            }
+        };
+        for file in files {
+            let cases = match load_cases(&file) {
+                Ok(cases) => cases,
+                Err(e) => {
+                    println!("{}: {}", file.display(), e);
+                    all_passed = false;
+                    continue;
+                }
+            };
+            let mut failures = Vec::new();
+            for case in &cases {
+                let problems = run_case(case);
+                if !problems.is_empty() {
+                    failures.push((case.name.as_str(), problems));
+                }
+            }
+            total += cases.len();
+            total_passed += cases.len() - failures.len();
+            println!("{}: {}/{} passed", file.display(), cases.len() - failures.len(), cases.len());
+            for (name, problems) in failures.iter().take(SHOWN_FAILURES) {
+                println!("  {}: {}", name, problems.join(", "));
+            }
+            all_passed &= failures.is_empty();
+        }
+    }
+    panic::set_hook(hook);
+    println!("{}/{} cases passed", total_passed, total);
+    all_passed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a few cases in the SingleStepTests format, RNES_SINGLESTEP points at the full suite
+    const VENDORED: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/singlestep");
+
+    fn vendored_case() -> Case {
+        load_cases(&Path::new(VENDORED).join("a9.json")).unwrap().remove(0)
+    }
+
+    #[test]
+    fn parses_the_json_the_tests_use() {
+        let json = parse(r#" {"name": "a\"b", "list": [1, -2.5e1, true, null, []], "empty": {}} "#).unwrap();
+        assert!(matches!(json.get("name"), Some(Json::String(name)) if name == "a\"b"));
+        let list = json.get("list").and_then(|l| l.array()).unwrap();
+        assert_eq!((list[0].number(), list[1].number()), (Some(1.0), Some(-25.0)));
+        assert!(matches!(list[2], Json::Literal) && matches!(list[4], Json::Array(ref items) if items.is_empty()));
+        assert!(matches!(json.get("empty"), Some(Json::Object(map)) if map.is_empty()));
+        assert!(parse("[1, 2").is_err());
+        assert!(parse(r#"{"a" 1}"#).is_err());
+    }
+
+    #[test]
+    fn loads_states_and_bus_cycles() {
+        let case = vendored_case();
+        assert_eq!(case.name, "a9 80 00");
+        assert_eq!((case.initial.pc, case.initial.p, case.expected.a), (0x0400, 0x24, 0x80));
+        assert_eq!(case.initial.ram, vec![(0x0400, 0xA9), (0x0401, 0x80)]);
+        assert_eq!(case.cycles[1], BusAccess { address: 0x0401, value: 0x80, write: false });
+        assert!(run_case(&case).is_empty());
+    }
+
+    #[test]
+    fn reports_registers_ram_and_the_bus_that_differ() {
+        let mut case = vendored_case();
+        case.expected.a = 0x81;
+        case.expected.ram.push((0x0500, 0x01));
+        assert_eq!(run_case(&case), vec!["a is $80, expected $81", "$0500 is $00, expected $01"]);
+    }
+
+    #[test]
+    fn reports_a_cycle_count_that_differs() {
+        let mut case = vendored_case();
+        case.cycles.pop();
+        let problems = run_case(&case);
+        assert_eq!(problems[0], "cycles is $02, expected $01");
+        assert_eq!(problems[1], "bus [r$0400=A9 r$0401=80], expected [r$0400=A9]");
+    }
+
+    #[cfg(feature = "singlestep")]
+    #[test]
+    fn vendored_cases_pass() {
+        let path = std::env::var("RNES_SINGLESTEP").unwrap_or(VENDORED.to_string());
+        assert!(run(&[path]));
+    }
+}
@@ -0,0 +1,145 @@
+use crate::disasm::disassemble_bytes;
+use crate::{get_flag, Emulator};
+
+// how many executed instructions a snapshot carries
+pub const HISTORY_LENGTH: usize = 32;
+
+pub struct Flags {
+    pub carry: bool,
+    pub zero: bool,
+    pub interrupt_disable: bool,
+    pub decimal: bool,
+    pub break_command: bool,
+    pub unused: bool,
+    pub overflow: bool,
+    pub negative: bool,
+}
+
+pub struct ExecutedInstruction {
+    pub address: u16,
+    pub bytes: [u8; 3],
+    pub text: String,
+}
+
+// Structured copy of the machine for tools, instead of parsing print_state() output.
+pub struct MachineState {
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub stack_pointer: u8,
+    pub program_counter: u16,
+    pub status: u8,
+    pub flags: Flags,
+    pub opcode: u8,
+    pub cycles_remaining: u8,
+    pub total_cycles: u64,
+    pub frame: u64,
+    pub scanline: u16,
+    pub dot: u16,
+    pub lag_frames: u64,
+    pub history: Vec<ExecutedInstruction>,
+}
+
+pub fn snapshot(emulator: &Emulator) -> MachineState {
+    let status = emulator.registers.cpu_flags;
+    let flag = |bit| get_flag(status, bit) != 0;
+    MachineState {
+        a: emulator.registers.a_reg,
+        x: emulator.registers.x_reg,
+        y: emulator.registers.y_reg,
+        stack_pointer: emulator.registers.stack_pointer,
+        program_counter: emulator.registers.program_counter,
+        status,
+        flags: Flags {
+            carry: flag(0),
+            zero: flag(1),
+            interrupt_disable: flag(2),
+            decimal: flag(3),
+            break_command: flag(4),
+            unused: flag(5),
+            overflow: flag(6),
+            negative: flag(7),
+        },
+        opcode: emulator.opcode,
+        cycles_remaining: emulator.cycles,
+        total_cycles: emulator.total_cycles,
+        frame: emulator.ppu.frame,
+        scanline: emulator.ppu.scanline,
+        dot: emulator.ppu.dot,
+        lag_frames: emulator.lag.frames,
+        history: emulator
+            .history
+            .iter()
+            .map(|(address, bytes)| ExecutedInstruction {
+                address: *address,
+                bytes: *bytes,
+                text: disassemble_bytes(*bytes, *address).0,
+            })
+            .collect(),
+    }
+}
+
+pub fn json_string(text: &str) -> String {
+    let mut out = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+impl MachineState {
+    pub fn to_json(&self) -> String {
+        let f = &self.flags;
+        let history: Vec<String> = self
+            .history
+            .iter()
+            .map(|i| {
+                format!(
+                    "{{\"address\":{},\"bytes\":[{},{},{}],\"text\":{}}}",
+                    i.address, i.bytes[0], i.bytes[1], i.bytes[2], json_string(&i.text)
+                )
+            })
+            .collect();
+        format!(
+            "{{\"registers\":{{\"a\":{},\"x\":{},\"y\":{},\"sp\":{},\"pc\":{},\"status\":{}}},\
+\"flags\":{{\"carry\":{},\"zero\":{},\"interrupt_disable\":{},\"decimal\":{},\"break\":{},\"unused\":{},\"overflow\":{},\"negative\":{}}},\
+\"opcode\":{},\"cycles_remaining\":{},\"total_cycles\":{},\"frame\":{},\"scanline\":{},\"dot\":{},\"lag_frames\":{},\"history\":[{}]}}",
+            self.a, self.x, self.y, self.stack_pointer, self.program_counter, self.status,
+            f.carry, f.zero, f.interrupt_disable, f.decimal, f.break_command, f.unused, f.overflow, f.negative,
+            self.opcode, self.cycles_remaining, self.total_cycles, self.frame, self.scanline, self.dot, self.lag_frames,
+            history.join(",")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_string_escapes_quotes_backslashes_and_control_characters() {
+        assert_eq!(json_string("LDA #$01"), "\"LDA #$01\"");
+        assert_eq!(json_string("say \"hi\""), "\"say \\\"hi\\\"\"");
+        assert_eq!(json_string("C:\\roms"), "\"C:\\\\roms\"");
+        assert_eq!(json_string("a\nb\tc\u{1f}"), "\"a\\u000ab\\u0009c\\u001f\"");
+        // only the C0 controls need escaping
+        assert_eq!(json_string("\u{7f}é"), "\"\u{7f}é\"");
+        assert_eq!(json_string(""), "\"\"");
+    }
+
+    #[test]
+    fn to_json_carries_the_history_text() {
+        let mut emulator = Emulator::new();
+        emulator.registers.a_reg = 0x42;
+        emulator.history.push_back((0x8000, [0xA9, 0x42, 0x00]));
+        let json = snapshot(&emulator).to_json();
+        assert!(json.starts_with("{\"registers\":{\"a\":66,"), "{}", json);
+        assert!(json.ends_with("\"history\":[{\"address\":32768,\"bytes\":[169,66,0],\"text\":\"LDA #$42\"}]}"), "{}", json);
+    }
+}
@@ -0,0 +1,217 @@
+use crate::snapshot::{snapshot, MachineState};
+use crate::Emulator;
+
+// changed bytes listed per memory region before the rest are only counted
+const BYTES_SHOWN: usize = 16;
+
+// CPU address space in the groups a diff reports
+const REGIONS: [(&str, u16, u16); 9] = [
+    ("zero page", 0x0000, 0x00FF),
+    ("stack", 0x0100, 0x01FF),
+    ("RAM", 0x0200, 0x07FF),
+    ("RAM mirrors", 0x0800, 0x1FFF),
+    ("PPU registers", 0x2000, 0x3FFF),
+    ("APU and IO registers", 0x4000, 0x401F),
+    ("expansion", 0x4020, 0x5FFF),
+    ("cartridge WRAM", 0x6000, 0x7FFF),
+    ("cartridge PRG", 0x8000, 0xFFFF),
+];
+
+const FLAG_NAMES: [char; 8] = ['C', 'Z', 'I', 'D', 'B', '-', 'V', 'N'];
+
+// What the machine looked like when the diff started.
+pub struct Baseline {
+    state: MachineState,
+    memory: Vec<u8>,
+    ppu_registers: [(&'static str, u16); 7],
+    chr: Vec<u8>,
+    nametables: Vec<u8>,
+    palette: Vec<u8>,
+}
+
+// A diff waiting for its run to finish.
+pub struct PendingDiff {
+    pub baseline: Baseline,
+    pub total: u64,
+    pub remaining: u64,
+    // counting frames instead of instructions
+    pub frames: bool,
+}
+
+fn ppu_registers(emulator: &Emulator) -> [(&'static str, u16); 7] {
+    let ppu = &emulator.ppu;
+    [
+        ("PPUCTRL", ppu.ctrl as u16),
+        ("PPUMASK", ppu.mask as u16),
+        ("PPUSTATUS", ppu.status as u16),
+        ("v", ppu.v),
+        ("t", ppu.t),
+        ("fine x", ppu.fine_x as u16),
+        ("write latch", ppu.write_latch as u16),
+    ]
+}
+
+pub fn capture(emulator: &Emulator) -> Baseline {
+    Baseline {
+        state: snapshot(emulator),
+        memory: emulator.memory.to_vec(),
+        ppu_registers: ppu_registers(emulator),
+        chr: emulator.ppu.chr.to_vec(),
+        nametables: emulator.ppu.nametables.to_vec(),
+        palette: emulator.ppu.palette.to_vec(),
+    }
+}
+
+// Changed bytes of one block, the first few listed and the rest counted.
+fn byte_lines(title: &str, base: u16, before: &[u8], after: &[u8], name: &dyn Fn(u16) -> String) -> Vec<String> {
+    let changed: Vec<usize> = (0..before.len()).filter(|i| before[*i] != after[*i]).collect();
+    if changed.is_empty() {
+        return Vec::new();
+    }
+    let mut lines = vec![format!("{}: {} bytes", title, changed.len())];
+    for i in changed.iter().take(BYTES_SHOWN) {
+        lines.push(format!("  {}  {:02X} -> {:02X}", name(base.wrapping_add(*i as u16)), before[*i], after[*i]));
+    }
+    if changed.len() > BYTES_SHOWN {
+        lines.push(format!("  ... {} more", changed.len() - BYTES_SHOWN));
+    }
+    lines
+}
+
+// Everything that differs between the baseline and the machine now.
+pub fn diff(before: &Baseline, emulator: &Emulator) -> Vec<String> {
+    let now = snapshot(emulator);
+    let old = &before.state;
+    let mut lines = vec![format!(
+        "{} cycles, {} frames, scanline {} dot {} -> scanline {} dot {}",
+        now.total_cycles - old.total_cycles,
+        now.frame - old.frame,
+        old.scanline,
+        old.dot,
+        now.scanline,
+        now.dot
+    )];
+
+    let registers = [("A", old.a, now.a), ("X", old.x, now.x), ("Y", old.y, now.y), ("SP", old.stack_pointer, now.stack_pointer)];
+    let mut changes: Vec<String> = registers.iter().filter(|(_, a, b)| a != b).map(|(name, a, b)| format!("{} {:02X} -> {:02X}", name, a, b)).collect();
+    if old.program_counter != now.program_counter {
+        changes.push(format!("PC {:04X} -> {:04X}", old.program_counter, now.program_counter));
+    }
+    if !changes.is_empty() {
+        lines.push(format!("registers: {}", changes.join(", ")));
+    }
+    let flags: Vec<String> = (0..8)
+        .filter(|bit| (old.status ^ now.status) & (1 << bit) != 0)
+        .map(|bit| format!("{} {} -> {}", FLAG_NAMES[bit], old.status >> bit & 1, now.status >> bit & 1))
+        .collect();
+    if !flags.is_empty() {
+        lines.push(format!("flags: {}", flags.join(", ")));
+    }
+
+    let name = |address: u16| emulator.ram_map.describe(address);
+    for (title, start, end) in REGIONS {
+        let range = start as usize..=end as usize;
+        lines.extend(byte_lines(title, start, &before.memory[range.clone()], &emulator.memory[range], &name));
+    }
+
+    let ppu: Vec<String> = before
+        .ppu_registers
+        .iter()
+        .zip(ppu_registers(emulator))
+        .filter(|((_, a), (_, b))| a != b)
+        .map(|((name, a), (_, b))| format!("{} {:02X} -> {:02X}", name, a, b))
+        .collect();
+    if !ppu.is_empty() {
+        lines.push(format!("PPU: {}", ppu.join(", ")));
+    }
+    let vram = |address: u16| format!("${:04X}", address);
+    lines.extend(byte_lines("pattern tables", 0x0000, &before.chr, &emulator.ppu.chr, &vram));
+    lines.extend(byte_lines("nametables", 0x2000, &before.nametables, &emulator.ppu.nametables, &vram));
+    lines.extend(byte_lines("palette", 0x3F00, &before.palette, &emulator.ppu.palette, &vram));
+    lines
+}
+
+fn finish(emulator: &mut Emulator, stopped_early: bool) {
+    let Some(pending) = emulator.debugger.as_mut().and_then(|d| d.diff.take()) else {
+        return;
+    };
+    let unit = if pending.frames { "frames" } else { "instructions" };
+    let ran = pending.total - pending.remaining;
+    match stopped_early {
+        true => println!("stopped after {} of {} {}, diff so far:", ran, pending.total, unit),
+        false => println!("diff after {} {}:", ran, unit),
+    }
+    for line in diff(&pending.baseline, emulator) {
+        println!("{}", line);
+    }
+    if let Some(debugger) = emulator.debugger.as_mut() {
+        debugger.stepping = true;
+    }
+}
+
+// Called before each instruction and at the end of each frame while a diff is
+// running. Prints it once the count runs out or something else stops the run.
+pub fn advance(emulator: &mut Emulator, frame_ended: bool) {
+    let Some(debugger) = emulator.debugger.as_mut() else {
+        return;
+    };
+    let stepping = debugger.stepping;
+    let Some(pending) = debugger.diff.as_mut() else {
+        return;
+    };
+    if pending.frames == frame_ended {
+        pending.remaining -= 1;
+    }
+    if pending.remaining == 0 {
+        finish(emulator, false);
+    } else if stepping {
+        finish(emulator, true);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_registers_zero_page_and_palette_changes() {
+        let mut emulator = Emulator::new();
+        let baseline = capture(&emulator);
+        assert_eq!(diff(&baseline, &emulator), ["0 cycles, 0 frames, scanline 0 dot 0 -> scanline 0 dot 0"]);
+
+        emulator.registers.a_reg = 0x42;
+        emulator.registers.program_counter = 0x8003;
+        emulator.registers.cpu_flags |= 0x80;
+        emulator.memory[0x0010] = 0x07;
+        emulator.memory[0x00FF] = 0x01;
+        emulator.ppu.palette[1] = 0x30;
+        emulator.ppu.ctrl = 0x80;
+        assert_eq!(
+            diff(&baseline, &emulator),
+            [
+                "0 cycles, 0 frames, scanline 0 dot 0 -> scanline 0 dot 0",
+                "registers: A 00 -> 42, PC 0000 -> 8003",
+                "flags: N 0 -> 1",
+                "zero page: 2 bytes",
+                "  $0010  00 -> 07",
+                "  $00FF  00 -> 01",
+                "PPU: PPUCTRL 00 -> 80",
+                "palette: 1 bytes",
+                "  $3F01  00 -> 30",
+            ]
+        );
+    }
+
+    #[test]
+    fn counts_the_bytes_past_the_first_few() {
+        let mut emulator = Emulator::new();
+        let baseline = capture(&emulator);
+        emulator.memory[0x0300..0x0300 + BYTES_SHOWN + 3].fill(0xFF);
+        let lines = diff(&baseline, &emulator);
+        assert_eq!(lines[1], format!("RAM: {} bytes", BYTES_SHOWN + 3));
+        assert_eq!(lines[2], "  $0300  00 -> FF");
+        assert_eq!(lines[1 + BYTES_SHOWN], format!("  ${:04X}  00 -> FF", 0x0300 + BYTES_SHOWN - 1));
+        assert_eq!(lines[2 + BYTES_SHOWN], "  ... 3 more");
+        assert_eq!(lines.len(), 3 + BYTES_SHOWN);
+    }
+}
@@ -0,0 +1,46 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use crate::Emulator;
+
+// Instruction trace in the nestest.log layout so it can be diffed against
+// other emulators' logs:
+// C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7
+pub struct Trace {
+    out: BufWriter<File>,
+}
+
+// The line for the instruction at PC, before it runs. Operands are named
+// from the RAM map, with none loaded the line matches nestest.log.
+pub fn line(emulator: &Emulator) -> String {
+    let pc = emulator.registers.program_counter;
+    let (text, length) = emulator.ram_map.disassemble(&emulator.memory, pc);
+    let bytes: Vec<String> = (0..length).map(|i| format!("{:02X}", emulator.memory[pc.wrapping_add(i) as usize])).collect();
+    let regs = &emulator.registers;
+    format!(
+        "{:04X}  {:<8}  {:<32}A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} PPU:{:>3},{:>3} CYC:{}",
+        pc,
+        bytes.join(" "),
+        text,
+        regs.a_reg,
+        regs.x_reg,
+        regs.y_reg,
+        regs.cpu_flags,
+        regs.stack_pointer,
+        emulator.ppu.scanline,
+        emulator.ppu.dot,
+        emulator.total_cycles
+    )
+}
+
+impl Trace {
+    pub fn create(path: &str) -> io::Result<Self> {
+        Ok(Trace {
+            out: BufWriter::new(File::create(path)?),
+        })
+    }
+
+    pub fn write(&mut self, line: &str) {
+        // a trace that cannot be written should not stop the game
+        let _ = writeln!(self.out, "{}", line);
+    }
+}
@@ -0,0 +1,336 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::io;
+use crate::debugger::parse_hex;
+use crate::watch::WatchType;
+use crate::Emulator;
+
+/* Trigger File, a small subset of TOML
+    [[trigger]]
+    name = "World 1-2"
+    when = "$075F == 0 and $075C == 1"
+    message = "made it out of 1-1"      optional
+    once = true                         optional, false fires every time the condition turns true
+    pause = false                       optional, stop in the debugger or pause when it fires
+
+   A condition is terms joined by "and" and "or", and binds tighter. A term is
+   an address with an optional :u8, :s8 or :u16 type followed by either a
+   comparison (== != < <= > >=) with a decimal or $hex number, or one of
+   changed, increased and decreased, which compare with the previous frame.
+   Tokens are separated by spaces.
+*/
+
+// how many fired triggers the TUI keeps
+const RECENT_LENGTH: usize = 5;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Test {
+    Equal(i32),
+    NotEqual(i32),
+    Less(i32),
+    LessOrEqual(i32),
+    Greater(i32),
+    GreaterOrEqual(i32),
+    Changed,
+    Increased,
+    Decreased,
+}
+
+struct Term {
+    address: u16,
+    kind: WatchType,
+    test: Test,
+    // value at the end of the previous frame
+    last: Option<i32>,
+}
+
+pub struct Trigger {
+    pub name: String,
+    pub message: String,
+    pub once: bool,
+    pub pause: bool,
+    // any of these groups with all of its terms true
+    condition: Vec<Vec<Term>>,
+    // fires on the frame the condition becomes true, not every frame it stays true
+    was_true: bool,
+    pub fired: u32,
+}
+
+#[derive(Default)]
+pub struct Triggers {
+    pub list: Vec<Trigger>,
+    // latest notifications, newest last
+    pub recent: VecDeque<String>,
+}
+
+fn parse_number(text: &str) -> Option<i32> {
+    if text.starts_with('$') {
+        return parse_hex(text).map(|value| value as i32);
+    }
+    text.parse().ok()
+}
+
+fn parse_term(words: &[&str]) -> Result<Term, String> {
+    let text = words.join(" ");
+    let (operand, test) = match words {
+        [operand, test] => (
+            *operand,
+            match *test {
+                "changed" => Test::Changed,
+                "increased" => Test::Increased,
+                "decreased" => Test::Decreased,
+                _ => return Err(format!("expected changed, increased or decreased in {:?}", text)),
+            },
+        ),
+        [operand, op, value] => {
+            let value = parse_number(value).ok_or_else(|| format!("bad number {}", value))?;
+            let test = match *op {
+                "==" => Test::Equal(value),
+                "!=" => Test::NotEqual(value),
+                "<" => Test::Less(value),
+                "<=" => Test::LessOrEqual(value),
+                ">" => Test::Greater(value),
+                ">=" => Test::GreaterOrEqual(value),
+                _ => return Err(format!("unknown comparison {}", op)),
+            };
+            (*operand, test)
+        }
+        _ => return Err(format!("cannot parse term {:?}", text)),
+    };
+    let (address, kind) = match operand.split_once(':') {
+        Some((address, kind)) => (address, WatchType::from_name(kind).ok_or_else(|| format!("unknown type {}, expected u8, s8 or u16", kind))?),
+        None => (operand, WatchType::U8),
+    };
+    let address = parse_hex(address).filter(|_| address.starts_with('$')).ok_or_else(|| format!("bad address {}", address))?;
+    Ok(Term { address, kind, test, last: None })
+}
+
+fn parse_condition(text: &str) -> Result<Vec<Vec<Term>>, String> {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let mut condition = Vec::new();
+    for group in words.split(|w| *w == "or") {
+        let terms = group.split(|w| *w == "and").map(parse_term).collect::<Result<Vec<_>, _>>()?;
+        condition.push(terms);
+    }
+    Ok(condition)
+}
+
+enum Value {
+    Text(String),
+    Bool(bool),
+}
+
+// Basic strings with \" and \\ escapes, and booleans. A # outside a string starts a comment.
+fn parse_value(text: &str) -> Result<Value, String> {
+    let text = text.trim();
+    if let Some(rest) = text.strip_prefix('"') {
+        let mut value = String::new();
+        let mut chars = rest.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => {
+                    let after = chars.as_str().trim();
+                    if !after.is_empty() && !after.starts_with('#') {
+                        return Err(format!("unexpected {:?} after string", after));
+                    }
+                    return Ok(Value::Text(value));
+                }
+                '\\' => match chars.next() {
+                    Some('"') => value.push('"'),
+                    Some('\\') => value.push('\\'),
+                    Some('n') => value.push('\n'),
+                    other => return Err(format!("unsupported escape \\{}", other.map(String::from).unwrap_or_default())),
+                },
+                _ => value.push(c),
+            }
+        }
+        return Err("unterminated string".to_string());
+    }
+    match text.split('#').next().unwrap_or("").trim() {
+        "true" => Ok(Value::Bool(true)),
+        "false" => Ok(Value::Bool(false)),
+        other => Err(format!("expected a string or true/false, got {}", other)),
+    }
+}
+
+impl Trigger {
+    fn new() -> Self {
+        Trigger {
+            name: String::new(),
+            message: String::new(),
+            once: true,
+            pause: false,
+            condition: Vec::new(),
+            was_true: false,
+            fired: 0,
+        }
+    }
+
+    fn set(&mut self, key: &str, value: Value) -> Result<(), String> {
+        match (key, value) {
+            ("name", Value::Text(text)) => self.name = text,
+            ("message", Value::Text(text)) => self.message = text,
+            ("when", Value::Text(text)) => self.condition = parse_condition(&text)?,
+            ("once", Value::Bool(value)) => self.once = value,
+            ("pause", Value::Bool(value)) => self.pause = value,
+            ("name" | "message" | "when", _) => return Err(format!("{} must be a string", key)),
+            ("once" | "pause", _) => return Err(format!("{} must be true or false", key)),
+            _ => return Err(format!("unknown key {}", key)),
+        }
+        Ok(())
+    }
+
+    // Every term reads memory every frame so change tests always compare against the last frame.
+    fn check(&mut self, memory: &[u8]) -> bool {
+        let mut result = false;
+        for group in self.condition.iter_mut() {
+            let mut all = true;
+            for term in group.iter_mut() {
+                let value = term.kind.read(memory, term.address);
+                all &= match term.test {
+                    Test::Equal(target) => value == target,
+                    Test::NotEqual(target) => value != target,
+                    Test::Less(target) => value < target,
+                    Test::LessOrEqual(target) => value <= target,
+                    Test::Greater(target) => value > target,
+                    Test::GreaterOrEqual(target) => value >= target,
+                    Test::Changed => term.last.is_some_and(|last| last != value),
+                    Test::Increased => term.last.is_some_and(|last| value > last),
+                    Test::Decreased => term.last.is_some_and(|last| value < last),
+                };
+                term.last = Some(value);
+            }
+            result |= all;
+        }
+        result
+    }
+
+    pub fn text(&self) -> String {
+        let state = match (self.fired, self.once) {
+            (0, _) => "waiting".to_string(),
+            (_, true) => "done".to_string(),
+            (count, false) => format!("fired {}x", count),
+        };
+        format!("{} [{}]", self.name, state)
+    }
+}
+
+impl Triggers {
+    pub fn load(path: &str) -> io::Result<Self> {
+        let bad = |number: usize, e: String| io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", number + 1, e));
+        let mut triggers = Triggers::default();
+        let mut current: Option<(usize, Trigger)> = None;
+        let finish = |current: Option<(usize, Trigger)>, triggers: &mut Triggers| -> io::Result<()> {
+            if let Some((number, trigger)) = current {
+                if trigger.name.is_empty() || trigger.condition.is_empty() {
+                    return Err(bad(number, "a trigger needs a name and a when".to_string()));
+                }
+                triggers.list.push(trigger);
+            }
+            Ok(())
+        };
+        for (number, line) in fs::read_to_string(path)?.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if line == "[[trigger]]" {
+                finish(current.take(), &mut triggers)?;
+                current = Some((number, Trigger::new()));
+                continue;
+            }
+            let (key, value) = line.split_once('=').ok_or_else(|| bad(number, format!("expected key = value, got {}", line)))?;
+            let Some((_, trigger)) = current.as_mut() else {
+                return Err(bad(number, "keys must come after [[trigger]]".to_string()));
+            };
+            let value = parse_value(value).map_err(|e| bad(number, e))?;
+            trigger.set(key.trim(), value).map_err(|e| bad(number, e))?;
+        }
+        finish(current.take(), &mut triggers)?;
+        Ok(triggers)
+    }
+}
+
+// Called once per frame, notifies for every trigger whose condition just became true.
+pub fn end_frame(emulator: &mut Emulator) {
+    let frame = emulator.ppu.frame;
+    let mut stop = false;
+    for trigger in emulator.triggers.list.iter_mut() {
+        let now = trigger.check(&emulator.memory);
+        let rising = now && !trigger.was_true;
+        trigger.was_true = now;
+        if !rising || (trigger.once && trigger.fired > 0) {
+            continue;
+        }
+        trigger.fired += 1;
+        let note = if trigger.message.is_empty() { trigger.name.clone() } else { format!("{}: {}", trigger.name, trigger.message) };
+        println!("frame {}: trigger {}", frame, note);
+        if emulator.triggers.recent.len() == RECENT_LENGTH {
+            emulator.triggers.recent.pop_front();
+        }
+        emulator.triggers.recent.push_back(format!("frame {}: {}", frame, note));
+        stop |= trigger.pause;
+    }
+    if stop {
+        match emulator.debugger.as_mut() {
+            Some(debugger) => debugger.stepping = true,
+            None => emulator.pause(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trigger(when: &str) -> Trigger {
+        let mut trigger = Trigger::new();
+        trigger.set("when", Value::Text(when.to_string())).unwrap();
+        trigger
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let mut memory = [0u8; 0x800];
+        let mut condition = trigger("$0010 == 1 and $0011 == 2 or $0012 >= $80");
+        assert!(!condition.check(&memory));
+        memory[0x10] = 1;
+        assert!(!condition.check(&memory));
+        memory[0x11] = 2;
+        assert!(condition.check(&memory));
+        memory[0x10] = 0;
+        memory[0x12] = 0x90;
+        assert!(condition.check(&memory));
+    }
+
+    #[test]
+    fn change_tests_compare_with_the_last_frame() {
+        let mut memory = [0u8; 0x800];
+        let mut increased = trigger("$0020:s8 increased");
+        // nothing to compare with on the first frame
+        assert!(!increased.check(&memory));
+        memory[0x20] = 5;
+        assert!(increased.check(&memory));
+        // 0xFF is -1 as s8
+        memory[0x20] = 0xFF;
+        assert!(!increased.check(&memory));
+    }
+
+    #[test]
+    fn rejects_bad_terms() {
+        for when in ["$0010 = 1", "$0010 == x", "0010 == 1", "$0010:u32 == 1", "$0010 grew", "$0010"] {
+            assert!(parse_condition(when).is_err(), "{:?} accepted", when);
+        }
+    }
+
+    #[test]
+    fn parses_strings_booleans_and_comments() {
+        assert!(matches!(parse_value(r#" "say \"hi\"\\" # note"#), Ok(Value::Text(text)) if text == "say \"hi\"\\"));
+        assert!(matches!(parse_value("true # always"), Ok(Value::Bool(true))));
+        assert!(parse_value("\"open").is_err());
+        assert!(parse_value("yes").is_err());
+        let mut trigger = Trigger::new();
+        assert!(trigger.set("once", Value::Text("no".to_string())).is_err());
+        assert!(trigger.set("color", Value::Bool(true)).is_err());
+    }
+}
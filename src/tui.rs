@@ -0,0 +1,276 @@
+use std::io::{self, Read};
+use std::process::Command;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::disasm::disassemble_named;
+use crate::input::button_text;
+use crate::Emulator;
+
+// how many instructions to show before and after PC
+const DISASM_BEFORE: usize = 6;
+const DISASM_AFTER: usize = 12;
+const LEFT_WIDTH: usize = 40;
+// redraw at most this often while the game runs
+const REFRESH: Duration = Duration::from_millis(100);
+
+// The terminal while --tui is on. A thread owns stdin so the game keeps
+// running between key presses, the prompt reads its lines from it too.
+pub struct Live {
+    keys: Receiver<u8>,
+    last_draw: Option<Instant>,
+    // keys arrive one at a time without echo while running
+    raw: bool,
+    // stty -g from before, put back on exit
+    saved: Option<String>,
+}
+
+// Run stty on the terminal, its output when it worked.
+fn stty(args: &[&str]) -> Option<String> {
+    let output = Command::new("stty").args(args).stdin(std::process::Stdio::inherit()).output().ok()?;
+    output.status.success().then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+impl Live {
+    pub fn start() -> Self {
+        let (sender, keys) = mpsc::channel();
+        thread::spawn(move || {
+            let mut buffer = [0u8; 64];
+            let mut stdin = io::stdin();
+            while let Ok(n @ 1..) = stdin.read(&mut buffer) {
+                if buffer[..n].iter().any(|byte| sender.send(*byte).is_err()) {
+                    return;
+                }
+            }
+        });
+        let mut live = Live::with_keys(keys);
+        live.saved = stty(&["-g"]);
+        live
+    }
+
+    fn with_keys(keys: Receiver<u8>) -> Self {
+        Live { keys, last_draw: None, raw: false, saved: None }
+    }
+
+    fn set_raw(&mut self, raw: bool) {
+        if self.raw != raw && self.saved.is_some() {
+            stty(if raw { &["-icanon", "-echo", "min", "1"] } else { &["icanon", "echo"] });
+        }
+        self.raw = raw;
+    }
+
+    // True when the last redraw is REFRESH old.
+    fn due(&mut self) -> bool {
+        if self.last_draw.is_some_and(|at| at.elapsed() < REFRESH) {
+            return false;
+        }
+        self.last_draw = Some(Instant::now());
+        true
+    }
+
+    // A line typed at the prompt, None once stdin closes.
+    pub fn read_line(&mut self) -> Option<String> {
+        self.set_raw(false);
+        let mut line = Vec::new();
+        loop {
+            match self.keys.recv() {
+                Ok(b'\n') => return Some(String::from_utf8_lossy(&line).to_string()),
+                Ok(byte) => line.push(byte),
+                Err(_) if line.is_empty() => return None,
+                Err(_) => return Some(String::from_utf8_lossy(&line).to_string()),
+            }
+        }
+    }
+}
+
+impl Drop for Live {
+    fn drop(&mut self) {
+        if let Some(saved) = self.saved.as_deref() {
+            stty(&[saved]);
+        }
+    }
+}
+
+fn flags_text(flags: u8) -> String {
+    // NV-BDIZC, set flags in upper case
+    "NV-BDIZC"
+        .chars()
+        .enumerate()
+        .map(|(i, c)| if flags & (0x80 >> i) != 0 { c } else { c.to_ascii_lowercase() })
+        .collect()
+}
+
+fn left_pane(emulator: &Emulator) -> Vec<String> {
+    let regs = &emulator.registers;
+    let ppu = &emulator.ppu;
+    let mut lines = vec![
+        "\x1b[1m CPU \x1b[0m".to_string(),
+        format!(" PC ${:04X}  SP ${:02X}", regs.program_counter, regs.stack_pointer),
+        format!(" A  ${:02X}    X  ${:02X}   Y  ${:02X}", regs.a_reg, regs.x_reg, regs.y_reg),
+        format!(" P  {}  cycles {}", flags_text(regs.cpu_flags), emulator.total_cycles),
+        String::new(),
+        "\x1b[1m PPU \x1b[0m".to_string(),
+        format!(" scanline {:3}  dot {:3}  frame {}", ppu.scanline, ppu.dot, ppu.frame),
+        format!(" ctrl ${:02X}  mask ${:02X}  status ${:02X}", ppu.ctrl, ppu.mask, ppu.status),
+        format!(" v ${:04X}  t ${:04X}  x {}", ppu.v, ppu.t, ppu.fine_x),
+        format!(" lag frames {}{}", emulator.lag.frames, if emulator.lag.last_frame_lagged { "  (last frame lagged)" } else { "" }),
+        String::new(),
+        "\x1b[1m Stack \x1b[0m".to_string(),
+    ];
+    let top = emulator.registers.stack_pointer as u16 + 1;
+    for row in 0..4 {
+        let mut line = String::new();
+        for i in 0..4 {
+            let offset = top + row * 4 + i;
+            if offset <= 0xFF {
+                line.push_str(&format!(" ${:04X}:{:02X}", 0x0100 + offset, emulator.memory[0x0100 + offset as usize]));
+            }
+        }
+        lines.push(line);
+    }
+    let practice = &emulator.practice;
+    lines.push(String::new());
+    lines.push("\x1b[1m Practice \x1b[0m".to_string());
+    lines.push(format!(" slot {}  attempt frame {}", practice.last.as_deref().unwrap_or("-"), ppu.frame - practice.start_frame.min(ppu.frame)));
+    lines.push(format!(
+        " P1 {}  P2 {}",
+        button_text(emulator.controllers[0].buttons()),
+        button_text(emulator.controllers[1].buttons())
+    ));
+    if !emulator.watches.list.is_empty() {
+        lines.push(String::new());
+        lines.push("\x1b[1m Watches \x1b[0m".to_string());
+        for watch in emulator.watches.overlay(&emulator.memory) {
+            lines.push(format!(" {}", watch));
+        }
+    }
+    if !emulator.triggers.recent.is_empty() {
+        lines.push(String::new());
+        lines.push("\x1b[1m Triggers \x1b[0m".to_string());
+        for note in emulator.triggers.recent.iter() {
+            lines.push(format!(" {}", note));
+        }
+    }
+    lines
+}
+
+fn right_pane(emulator: &Emulator) -> Vec<String> {
+    let mut lines = vec!["\x1b[1m Disassembly \x1b[0m".to_string()];
+    // the instruction at PC has not run yet, earlier ones come from the history
+    let pc = emulator.registers.program_counter;
+    let executed: Vec<&(u16, [u8; 3])> = emulator.history.iter().filter(|(address, _)| *address != pc).collect();
+    for (address, bytes) in executed.iter().rev().take(DISASM_BEFORE).rev() {
+        lines.push(format!("   ${:04X}  {}", address, disassemble_named(*bytes, *address, &|a| emulator.ram_map.name(a)).0));
+    }
+    let mut address = pc;
+    for i in 0..DISASM_AFTER {
+        let (text, length) = emulator.ram_map.disassemble(&emulator.memory, address);
+        if i == 0 {
+            lines.push(format!("\x1b[7m > ${:04X}  {:<24}\x1b[0m", address, text));
+        } else {
+            lines.push(format!("   ${:04X}  {}", address, text));
+        }
+        address = address.wrapping_add(length);
+    }
+    lines
+}
+
+// Visible width, escape sequences take no columns.
+fn visible_len(text: &str) -> usize {
+    let mut len = 0;
+    let mut escape = false;
+    for c in text.chars() {
+        match c {
+            '\x1b' => escape = true,
+            'm' if escape => escape = false,
+            _ if escape => {}
+            _ => len += 1,
+        }
+    }
+    len
+}
+
+// Called once per frame while the game runs: space or p stops at the
+// prompt, and the screen redraws every REFRESH.
+pub fn end_frame(emulator: &mut Emulator) {
+    let Some(debugger) = emulator.debugger.as_mut() else {
+        return;
+    };
+    let Some(live) = debugger.tui.as_mut() else {
+        return;
+    };
+    live.set_raw(true);
+    while let Ok(key) = live.keys.try_recv() {
+        if matches!(key, b' ' | b'p') {
+            debugger.stepping = true;
+        }
+    }
+    if live.due() {
+        draw(emulator);
+        println!(" running, space or p stops at the prompt");
+    }
+}
+
+// Redraw the whole screen: machine state on the left, code on the right.
+// The debugger prompt is printed underneath so commands work as usual.
+pub fn draw(emulator: &Emulator) {
+    let left = left_pane(emulator);
+    let right = right_pane(emulator);
+    let mut screen = String::from("\x1b[2J\x1b[H");
+    for i in 0..left.len().max(right.len()) {
+        let l = left.get(i).map(|s| s.as_str()).unwrap_or("");
+        let r = right.get(i).map(|s| s.as_str()).unwrap_or("");
+        let padding = LEFT_WIDTH.saturating_sub(visible_len(l));
+        screen.push_str(&format!("{}{}\u{2502}{}\n", l, " ".repeat(padding), r));
+    }
+    screen.push_str(&"\u{2500}".repeat(LEFT_WIDTH + 40));
+    println!("{}", screen);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_sequences_take_no_columns() {
+        assert_eq!(visible_len("\x1b[1m CPU \x1b[0m"), 5);
+        assert_eq!(visible_len("\x1b[7m > $8000\x1b[0m"), 8);
+        assert_eq!(visible_len("plain"), 5);
+    }
+
+    #[test]
+    fn left_pane_shows_registers_ppu_and_only_used_sections() {
+        let mut emulator = Emulator::new();
+        emulator.verbose = false;
+        emulator.registers.program_counter = 0x8123;
+        emulator.registers.cpu_flags = 0x81;
+        emulator.ppu.scanline = 42;
+        let lines = left_pane(&emulator);
+        assert!(lines.contains(&" PC $8123  SP $00".to_string()));
+        assert!(lines.iter().any(|l| l.starts_with(" P  Nv-bdizC")));
+        assert!(lines.iter().any(|l| l.starts_with(" scanline  42")));
+        assert!(!lines.iter().any(|l| l.contains("Watches") || l.contains("Triggers")));
+        assert!(lines.iter().all(|l| visible_len(l) <= LEFT_WIDTH));
+    }
+
+    #[test]
+    fn prompt_lines_come_from_the_key_thread() {
+        let (sender, keys) = mpsc::channel();
+        let mut live = Live::with_keys(keys);
+        for byte in b"m 0300\nc" {
+            sender.send(*byte).unwrap();
+        }
+        assert_eq!(live.read_line().as_deref(), Some("m 0300"));
+        drop(sender);
+        assert_eq!(live.read_line().as_deref(), Some("c"));
+        assert_eq!(live.read_line(), None);
+    }
+
+    #[test]
+    fn redraws_are_throttled() {
+        let (_sender, keys) = mpsc::channel();
+        let mut live = Live::with_keys(keys);
+        assert!(live.due());
+        assert!(!live.due());
+    }
+}
@@ -0,0 +1,270 @@
+use std::fs;
+use std::io;
+
+// Arcade PPUs used on VS. System boards.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum VsPpu {
+    // RGB PPU with the standard palette order
+    Rp2c03,
+    // RP2C04-0001 to -0004, each with its own scrambled palette order
+    Rp2c04(u8),
+    // RC2C05-01 to -05, standard palette but PPUCTRL and PPUMASK trade places
+    // and PPUSTATUS carries an ID in its low bits that games check
+    Rc2c05(u8),
+}
+
+impl VsPpu {
+    // NES 2.0 byte 13 low nibble
+    fn from_nes2(value: u8) -> Self {
+        let value = value & 0x0F;
+        match value {
+            2..=5 => VsPpu::Rp2c04(value - 1),
+            8..=0xC => VsPpu::Rc2c05(value - 7),
+            _ => VsPpu::Rp2c03,
+        }
+    }
+
+    // Low five bits of PPUSTATUS on the 2C05s that have an ID.
+    pub fn status_id(&self) -> Option<u8> {
+        match self {
+            VsPpu::Rc2c05(1) | VsPpu::Rc2c05(4) => Some(0x1B),
+            VsPpu::Rc2c05(2) => Some(0x3D),
+            VsPpu::Rc2c05(3) => Some(0x1C),
+            _ => None,
+        }
+    }
+
+    pub fn swaps_ctrl_and_mask(&self) -> bool {
+        matches!(self, VsPpu::Rc2c05(_))
+    }
+}
+
+// Color order of each RP2C04, entry i holds the standard palette index the
+// chip shows for i. The unused slots all come out black or white.
+const RP2C04_PALETTES: [[u8; 64]; 4] = [
+    // RP2C04-0001
+    [
+        0x35, 0x23, 0x16, 0x22, 0x1C, 0x09, 0x1D, 0x15, 0x20, 0x00, 0x27, 0x05, 0x04, 0x28, 0x08, 0x20,
+        0x21, 0x3E, 0x1F, 0x29, 0x3C, 0x32, 0x36, 0x12, 0x3F, 0x2B, 0x2E, 0x1E, 0x3D, 0x2D, 0x24, 0x01,
+        0x0E, 0x31, 0x33, 0x2A, 0x2C, 0x0C, 0x1B, 0x14, 0x2E, 0x07, 0x34, 0x06, 0x13, 0x02, 0x26, 0x2E,
+        0x2E, 0x19, 0x10, 0x0A, 0x39, 0x03, 0x37, 0x17, 0x0F, 0x11, 0x0B, 0x0D, 0x38, 0x25, 0x18, 0x3A,
+    ],
+    // RP2C04-0002
+    [
+        0x2E, 0x27, 0x18, 0x39, 0x3A, 0x25, 0x1C, 0x31, 0x16, 0x13, 0x38, 0x34, 0x20, 0x23, 0x3C, 0x0B,
+        0x0F, 0x21, 0x06, 0x3D, 0x1B, 0x29, 0x1E, 0x22, 0x1D, 0x24, 0x0E, 0x2B, 0x32, 0x08, 0x2E, 0x03,
+        0x04, 0x36, 0x26, 0x33, 0x11, 0x1F, 0x10, 0x02, 0x14, 0x3F, 0x00, 0x09, 0x12, 0x2E, 0x28, 0x20,
+        0x3E, 0x0D, 0x2A, 0x17, 0x0C, 0x01, 0x15, 0x19, 0x2E, 0x2C, 0x07, 0x37, 0x35, 0x05, 0x0A, 0x2D,
+    ],
+    // RP2C04-0003
+    [
+        0x14, 0x25, 0x3A, 0x10, 0x0B, 0x20, 0x31, 0x09, 0x01, 0x2E, 0x36, 0x08, 0x15, 0x3D, 0x3E, 0x3C,
+        0x22, 0x1C, 0x05, 0x12, 0x19, 0x18, 0x17, 0x1B, 0x00, 0x03, 0x2E, 0x02, 0x16, 0x06, 0x34, 0x35,
+        0x23, 0x0F, 0x0E, 0x37, 0x0D, 0x27, 0x26, 0x20, 0x29, 0x04, 0x21, 0x24, 0x11, 0x2D, 0x2E, 0x1F,
+        0x2C, 0x1E, 0x39, 0x33, 0x07, 0x2A, 0x28, 0x1D, 0x0A, 0x2E, 0x32, 0x38, 0x13, 0x2B, 0x3F, 0x0C,
+    ],
+    // RP2C04-0004
+    [
+        0x18, 0x03, 0x1C, 0x28, 0x2E, 0x35, 0x01, 0x17, 0x10, 0x1F, 0x2A, 0x0E, 0x36, 0x37, 0x0B, 0x39,
+        0x25, 0x1E, 0x12, 0x34, 0x2E, 0x1D, 0x06, 0x26, 0x3E, 0x1B, 0x22, 0x19, 0x04, 0x2E, 0x3A, 0x21,
+        0x05, 0x0A, 0x07, 0x02, 0x13, 0x14, 0x00, 0x15, 0x0C, 0x3D, 0x11, 0x0F, 0x0D, 0x38, 0x2D, 0x24,
+        0x33, 0x20, 0x08, 0x16, 0x3F, 0x2B, 0x20, 0x3C, 0x2E, 0x27, 0x23, 0x31, 0x29, 0x32, 0x2C, 0x09,
+    ],
+];
+
+// DIP switches, coin slots and the arcade PPU of a VS. System cabinet.
+pub struct VsSystem {
+    pub ppu: VsPpu,
+    // switch 1 in bit 0 through switch 8 in bit 7
+    pub dip: u8,
+    pub service: bool,
+    // frames each coin switch stays closed
+    coin: [u8; 2],
+    // 2C04 palette order, entry i holds the standard palette index shown for i.
+    // The header picks a built-in one, --vs-palette overrides it.
+    pub palette_map: Option<[u8; 64]>,
+    // mapper 99 keeps all CHR here and swaps 8KB of it into the PPU from $4016 bit 2
+    chr: Vec<u8>,
+    chr_bank: usize,
+}
+
+// how long a coin drop holds the switch, games debounce a frame or two
+const COIN_FRAMES: u8 = 3;
+
+impl VsSystem {
+    // None unless header byte 7 bit 0 marks a VS. System dump.
+    pub fn from_header(rom: &[u8]) -> Option<Self> {
+        if rom.len() < 16 || &rom[0..4] != b"NES\x1A" || rom[7] & 0x01 == 0 {
+            return None;
+        }
+        // only NES 2.0 headers say which PPU the cabinet had
+        let nes2 = rom[7] & 0x0C == 0x08;
+        let ppu = if nes2 { VsPpu::from_nes2(rom[13]) } else { VsPpu::Rp2c03 };
+        let mapper = (rom[6] >> 4) | (rom[7] & 0xF0);
+        let trainer = if rom[6] & 0x04 != 0 { 512 } else { 0 };
+        let chr_start = 16 + trainer + rom[4] as usize * 16384;
+        let chr_end = (chr_start + rom[5] as usize * 8192).min(rom.len());
+        let chr = if mapper == 99 && chr_start < chr_end { rom[chr_start..chr_end].to_vec() } else { Vec::new() };
+        let palette_map = match ppu {
+            VsPpu::Rp2c04(n) => Some(RP2C04_PALETTES[n as usize - 1]),
+            _ => None,
+        };
+        Some(VsSystem {
+            ppu,
+            dip: 0,
+            service: false,
+            coin: [0; 2],
+            palette_map,
+            chr,
+            chr_bank: 0,
+        })
+    }
+
+    // Header byte 7 bit 1. PlayChoice-10 games are plain NES games plus an
+    // instruction screen on a second monitor, so they need nothing special.
+    pub fn is_playchoice(rom: &[u8]) -> bool {
+        rom.len() >= 16 && &rom[0..4] == b"NES\x1A" && rom[7] & 0x02 != 0
+    }
+
+    // A 64 byte file, one standard palette index per 2C04 color.
+    pub fn load_palette_map(&mut self, path: &str) -> io::Result<()> {
+        let data = fs::read(path)?;
+        let map: [u8; 64] = data
+            .as_slice()
+            .try_into()
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "palette map must be 64 bytes"))?;
+        self.palette_map = Some(map.map(|index| index & 0x3F));
+        Ok(())
+    }
+
+    pub fn map_color(&self, index: u8) -> u8 {
+        match self.palette_map.as_ref() {
+            Some(map) => map[(index & 0x3F) as usize],
+            None => index,
+        }
+    }
+
+    pub fn insert_coin(&mut self, slot: usize) {
+        if slot < 2 {
+            self.coin[slot] = COIN_FRAMES;
+        }
+    }
+
+    pub fn end_frame(&mut self) {
+        for coin in self.coin.iter_mut() {
+            *coin = coin.saturating_sub(1);
+        }
+    }
+
+    // $4016 bits 2-6: service button, DIP switches 1-2, coin slots 1-2
+    pub fn read_4016(&self) -> u8 {
+        let mut bits = (self.service as u8) << 2;
+        bits |= (self.dip & 0x03) << 3;
+        bits |= ((self.coin[0] > 0) as u8) << 5;
+        bits |= ((self.coin[1] > 0) as u8) << 6;
+        bits
+    }
+
+    // $4017 bits 2-7: DIP switches 3-8
+    pub fn read_4017(&self) -> u8 {
+        self.dip & 0xFC
+    }
+
+    // Mapper 99 CHR bank select, returns the new bank's data when it changed.
+    pub fn write_4016(&mut self, value: u8) -> Option<&[u8]> {
+        let bank = ((value >> 2) & 1) as usize;
+        if self.chr.len() < 0x4000 || bank == self.chr_bank {
+            return None;
+        }
+        self.chr_bank = bank;
+        Some(&self.chr[bank * 0x2000..(bank + 1) * 0x2000])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Emulator;
+
+    // NES 2.0 VS. System header, PPU type in byte 13
+    fn header(mapper: u8, chr_banks: u8, ppu: u8) -> Vec<u8> {
+        let mut rom = vec![0; 16];
+        rom[0..4].copy_from_slice(b"NES\x1A");
+        rom[4] = 1;
+        rom[5] = chr_banks;
+        rom[6] = mapper << 4;
+        rom[7] = (mapper & 0xF0) | 0x08 | 0x01;
+        rom[13] = ppu;
+        rom
+    }
+
+    #[test]
+    fn the_header_picks_the_2c04_palette() {
+        let vs = VsSystem::from_header(&header(0, 0, 2)).unwrap();
+        assert_eq!(vs.ppu, VsPpu::Rp2c04(1));
+        assert_eq!(vs.map_color(0x00), 0x35);
+        let vs = VsSystem::from_header(&header(0, 0, 5)).unwrap();
+        assert_eq!(vs.map_color(0x3F), 0x09);
+        // the other PPUs use the standard order
+        let vs = VsSystem::from_header(&header(0, 0, 0)).unwrap();
+        assert_eq!((vs.palette_map, vs.map_color(0x16)), (None, 0x16));
+    }
+
+    #[test]
+    fn a_palette_file_overrides_the_built_in_order() {
+        let path = std::env::temp_dir().join(format!("rnes-vs-{}.pal", std::process::id()));
+        let path = path.to_str().unwrap();
+        fs::write(path, [0x41; 64]).unwrap();
+        let mut vs = VsSystem::from_header(&header(0, 0, 3)).unwrap();
+        vs.load_palette_map(path).unwrap();
+        assert_eq!(vs.map_color(0x00), 0x01);
+        fs::write(path, [0; 63]).unwrap();
+        assert!(vs.load_palette_map(path).is_err());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn coin_service_and_dip_bits_read_back_on_4016_and_4017() {
+        let mut vs = VsSystem::from_header(&header(0, 0, 0)).unwrap();
+        vs.dip = 0b1010_0110;
+        vs.service = true;
+        assert_eq!(vs.read_4016(), 0x04 | (0b10 << 3));
+        assert_eq!(vs.read_4017(), 0b1010_0100);
+        vs.insert_coin(1);
+        assert_eq!(vs.read_4016() & 0x60, 0x40);
+        for _ in 0..COIN_FRAMES {
+            vs.end_frame();
+        }
+        assert_eq!(vs.read_4016() & 0x60, 0);
+    }
+
+    #[test]
+    fn mapper_99_swaps_chr_from_4016_bit_2() {
+        let mut rom = header(99, 2, 0);
+        rom.extend(vec![0xEA; 16384]);
+        rom.extend(vec![0x11; 8192]);
+        rom.extend(vec![0x22; 8192]);
+        let mut emulator = Emulator::new();
+        emulator.verbose = false;
+        emulator.load_rom_bytes(&rom);
+        assert_eq!(emulator.ppu.chr[0], 0x11);
+        emulator.write_byte(0x4016, 0x04);
+        assert_eq!(emulator.ppu.chr[0x1FFF], 0x22);
+        // the same bank again changes nothing
+        assert!(emulator.vs.as_mut().unwrap().write_4016(0x04).is_none());
+        emulator.write_byte(0x4016, 0x00);
+        assert_eq!(emulator.ppu.chr[0], 0x11);
+    }
+
+    #[test]
+    fn the_2c05_trades_ppuctrl_and_ppumask() {
+        let mut emulator = Emulator::new();
+        emulator.verbose = false;
+        emulator.load_rom_bytes(&header(0, 0, 8));
+        emulator.write_byte(0x2000, 0x1E);
+        emulator.write_byte(0x2001, 0x80);
+        assert_eq!((emulator.ppu.ctrl, emulator.ppu.mask), (0x80, 0x1E));
+        // RC2C05-01 answers with its ID in the low bits of PPUSTATUS
+        assert_eq!(emulator.read_byte(0x2002) & 0x1F, 0x1B);
+    }
+}
@@ -0,0 +1,171 @@
+use std::fs;
+use std::io;
+use crate::debugger::parse_hex;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum WatchType {
+    U8,
+    S8,
+    // little endian pair starting at the address
+    U16,
+}
+
+impl WatchType {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "u8" => Some(WatchType::U8),
+            "s8" | "i8" => Some(WatchType::S8),
+            "u16" => Some(WatchType::U16),
+            _ => None,
+        }
+    }
+
+    pub fn read(&self, memory: &[u8], address: u16) -> i32 {
+        let low = memory[address as usize];
+        match self {
+            WatchType::U8 => low as i32,
+            WatchType::S8 => low as i8 as i32,
+            WatchType::U16 => u16::from_le_bytes([low, memory[address.wrapping_add(1) as usize]]) as i32,
+        }
+    }
+}
+
+pub struct Watch {
+    pub name: String,
+    pub address: u16,
+    pub kind: WatchType,
+    // value at the end of the previous frame, for change logging
+    last: Option<i32>,
+}
+
+impl Watch {
+    pub fn new(name: &str, address: u16, kind: WatchType) -> Self {
+        Watch {
+            name: name.to_string(),
+            address,
+            kind,
+            last: None,
+        }
+    }
+
+    // Parses "lives = $075A" or "scroll_x = $00FD:u8", the type defaults to u8.
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let (name, target) = line.split_once('=').ok_or_else(|| format!("expected name = address, got {}", line))?;
+        let name = name.trim();
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return Err(format!("bad watch name {:?}", name));
+        }
+        let (address, kind) = match target.trim().split_once(':') {
+            Some((address, kind)) => (address, WatchType::from_name(kind.trim()).ok_or_else(|| format!("unknown type {}, expected u8, s8 or u16", kind.trim()))?),
+            None => (target.trim(), WatchType::U8),
+        };
+        let address = parse_hex(address.trim()).ok_or_else(|| format!("bad address {}", address.trim()))?;
+        Ok(Watch::new(name, address, kind))
+    }
+
+    pub fn value(&self, memory: &[u8]) -> i32 {
+        self.kind.read(memory, self.address)
+    }
+
+    pub fn text(&self, memory: &[u8]) -> String {
+        let value = self.value(memory);
+        match self.kind {
+            WatchType::U16 => format!("{} = {} (${:04X})", self.name, value, value),
+            _ => format!("{} = {} (${:02X})", self.name, value, memory[self.address as usize]),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Watches {
+    pub list: Vec<Watch>,
+    // print a line whenever a watched value differs from the previous frame
+    pub log_changes: bool,
+}
+
+impl Watches {
+    // One watch per line, blank lines and lines starting with # are skipped.
+    pub fn load(path: &str) -> io::Result<Self> {
+        let mut watches = Watches::default();
+        for (number, line) in fs::read_to_string(path)?.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let watch = Watch::parse(line).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", number + 1, e)))?;
+            watches.add(watch);
+        }
+        Ok(watches)
+    }
+
+    // A watch with the same name is replaced.
+    pub fn add(&mut self, watch: Watch) {
+        match self.list.iter_mut().find(|w| w.name == watch.name) {
+            Some(existing) => *existing = watch,
+            None => self.list.push(watch),
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool {
+        let count = self.list.len();
+        self.list.retain(|w| w.name != name);
+        self.list.len() != count
+    }
+
+    // Lines for the overlay, one per watch.
+    pub fn overlay(&self, memory: &[u8]) -> Vec<String> {
+        self.list.iter().map(|w| w.text(memory)).collect()
+    }
+
+    // Called once per frame, returns the changes since the previous frame.
+    pub fn end_frame(&mut self, memory: &[u8], frame: u64) -> Vec<String> {
+        let mut changes = Vec::new();
+        for watch in self.list.iter_mut() {
+            let value = watch.value(memory);
+            if let Some(last) = watch.last {
+                if last != value {
+                    changes.push(format!("frame {}: {} {} -> {}", frame, watch.name, last, value));
+                }
+            }
+            watch.last = Some(value);
+        }
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_reads_each_type() {
+        let mut memory = [0u8; 0x800];
+        memory[0x75A] = 0xFE;
+        memory[0x75B] = 0x01;
+        let lives = Watch::parse("lives = $075A").unwrap();
+        assert_eq!((lives.kind, lives.value(&memory)), (WatchType::U8, 0xFE));
+        assert_eq!(Watch::parse("speed=$075A:s8").unwrap().text(&memory), "speed = -2 ($FE)");
+        assert_eq!(Watch::parse(" score = $075A : u16 ").unwrap().text(&memory), "score = 510 ($01FE)");
+    }
+
+    #[test]
+    fn rejects_bad_watches() {
+        for line in ["lives $075A", " = $075A", "extra lives = $075A", "lives = $075A:u32", "lives = nowhere"] {
+            assert!(Watch::parse(line).is_err(), "{:?} accepted", line);
+        }
+    }
+
+    #[test]
+    fn logs_changes_from_the_second_frame_on() {
+        let mut memory = [0u8; 0x800];
+        let mut watches = Watches::default();
+        watches.add(Watch::parse("x = $0010").unwrap());
+        assert!(watches.end_frame(&memory, 1).is_empty());
+        memory[0x10] = 3;
+        assert_eq!(watches.end_frame(&memory, 2), ["frame 2: x 0 -> 3"]);
+        // same name replaces the watch
+        watches.add(Watch::parse("x = $0011").unwrap());
+        assert_eq!(watches.list.len(), 1);
+        assert!(watches.remove("x") && !watches.remove("x"));
+    }
+}